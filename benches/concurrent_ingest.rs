@@ -0,0 +1,69 @@
+//! Benchmarks of ingest throughput with parallel writers.
+//!
+//! Compares a single-shard storage (one global lock) with the default
+//! sharded layout. Run with `cargo bench --bench concurrent_ingest`.
+
+use std::sync::Arc;
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+
+use prom_mock_rs::storage::{
+    Label, MemoryStorage, Sample, Storage, TimeSeries, DEFAULT_SHARD_COUNT,
+};
+
+/// Series written by each writer thread per iteration.
+const SERIES_PER_WRITER: usize = 2_000;
+
+/// Write `SERIES_PER_WRITER` series from each of `writers` threads.
+fn ingest(storage: &Arc<MemoryStorage>, writers: usize) {
+    let handles: Vec<_> = (0..writers)
+        .map(|w| {
+            let storage = Arc::clone(storage);
+            thread::spawn(move || {
+                for i in 0..SERIES_PER_WRITER {
+                    let mut ts = TimeSeries::new(vec![
+                        Label::new("__name__", format!("metric_{}", i % 50)),
+                        Label::new("writer", w.to_string()),
+                        Label::new("instance", format!("host_{i}")),
+                    ]);
+                    ts.add_sample(Sample::new(1_000, 1.0));
+                    storage.add_series(ts);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().expect("writer thread");
+    }
+}
+
+fn bench_concurrent_ingest(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrent_ingest");
+    group.sample_size(20);
+
+    for writers in [1, 2, 4, 8] {
+        group.throughput(Throughput::Elements((writers * SERIES_PER_WRITER) as u64));
+        for shards in [1, DEFAULT_SHARD_COUNT] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{shards}_shards"), writers),
+                &writers,
+                |b, &writers| {
+                    // Return the storage so dropping it is not measured
+                    b.iter_batched(
+                        || Arc::new(MemoryStorage::new().with_shard_count(shards)),
+                        |storage| {
+                            ingest(&storage, writers);
+                            storage
+                        },
+                        BatchSize::PerIteration,
+                    );
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_concurrent_ingest);
+criterion_main!(benches);
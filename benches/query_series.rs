@@ -0,0 +1,72 @@
+//! Benchmarks comparing indexed series selection with a full scan.
+//!
+//! Run with `cargo bench --bench query_series`.
+
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use regex::Regex;
+
+use prom_mock_rs::matchers::{EqualMatcher, LabelMatcher, NotEqualMatcher, RegexMatcher};
+use prom_mock_rs::storage::{Label, MemoryStorage, Sample, Storage, TimeSeries};
+
+/// Populate storage with `count` series spread over jobs, instances and methods.
+fn populate(count: usize) -> MemoryStorage {
+    let storage = MemoryStorage::new();
+    for i in 0..count {
+        let mut ts = TimeSeries::new(vec![
+            Label::new("__name__", format!("metric_{}", i % 50)),
+            Label::new("job", format!("job_{}", i % 20)),
+            Label::new("instance", format!("host_{i}")),
+            Label::new("method", if i % 4 == 0 { "POST" } else { "GET" }),
+        ]);
+        ts.add_sample(Sample::new(1_000, 1.0));
+        storage.add_series(ts);
+    }
+    storage
+}
+
+fn bench_query_series(c: &mut Criterion) {
+    let cases: Vec<(&str, Vec<Arc<dyn LabelMatcher>>)> = vec![
+        (
+            "equal",
+            vec![
+                Arc::new(EqualMatcher::new("__name__", "metric_7")),
+                Arc::new(EqualMatcher::new("job", "job_7")),
+            ],
+        ),
+        (
+            "regex",
+            vec![
+                Arc::new(EqualMatcher::new("__name__", "metric_3")),
+                Arc::new(RegexMatcher::new("job", Regex::new("^job_(3|13)$").expect("valid"))),
+            ],
+        ),
+        (
+            "negative",
+            vec![
+                Arc::new(EqualMatcher::new("__name__", "metric_11")),
+                Arc::new(NotEqualMatcher::new("method", "POST")),
+            ],
+        ),
+    ];
+
+    for count in [10_000, 100_000] {
+        let storage = populate(count);
+        let mut group = c.benchmark_group(format!("query_series/{count}"));
+        group.sample_size(20);
+
+        for (name, matchers) in &cases {
+            group.bench_with_input(BenchmarkId::new("index", name), matchers, |b, m| {
+                b.iter(|| black_box(storage.query_series(m)));
+            });
+            group.bench_with_input(BenchmarkId::new("scan", name), matchers, |b, m| {
+                b.iter(|| black_box(storage.scan_series(m)));
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_query_series);
+criterion_main!(benches);
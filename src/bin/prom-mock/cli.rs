@@ -2,7 +2,9 @@
 
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use prom_mock_rs::storage::{IngestPolicy, RetentionAnchor, SampleEncoding};
+use prom_mock_rs::timeutil::ClockAnchor;
 use time::OffsetDateTime;
 
 /// Command-line arguments for the Prometheus mock server.
@@ -17,6 +19,10 @@ use time::OffsetDateTime;
     about = "Simple Prometheus API mock: query and query_range with fixtures"
 )]
 pub struct Cli {
+    /// Run a tool instead of the server
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Address to listen on
     #[arg(long, default_value = "127.0.0.1:19090")]
     pub listen: String,
@@ -25,10 +31,18 @@ pub struct Cli {
     #[arg(long)]
     pub fixtures: Option<PathBuf>,
 
+    /// Reload the fixtures file when it changes, checking at this interval (0s disables)
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    pub fixtures_watch_interval: std::time::Duration,
+
     /// Fixed "now" time (ISO-8601, e.g. 2025-08-03T00:00:00Z)
     #[arg(long, value_parser = parse_time)]
     pub fixed_now: Option<OffsetDateTime>,
 
+    /// Reference time overriding the fixtures' clock_anchor (now, startup or RFC3339)
+    #[arg(long, conflicts_with = "fixed_now")]
+    pub clock_anchor: Option<ClockAnchor>,
+
     /// Artificial latency for each request (e.g. 100ms, 1s)
     #[arg(long, value_parser = humantime::parse_duration, default_value = "0s")]
     pub latency: std::time::Duration,
@@ -36,6 +50,68 @@ pub struct Cli {
     /// Error probability (0.0..1.0). When triggered, returns 503.
     #[arg(long, default_value_t = 0.0)]
     pub error_rate: f32,
+
+    /// Sample encoding for remote-written series (raw or xor)
+    #[arg(long, default_value = "raw")]
+    pub storage_encoding: SampleEncoding,
+
+    /// Drop remote-written samples older than this (e.g. 2h)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub retention: Option<std::time::Duration>,
+
+    /// What retention is measured from (latest-sample or clock)
+    #[arg(long, default_value = "latest-sample")]
+    pub retention_anchor: RetentionAnchor,
+
+    /// Maximum number of stored series; new series beyond it get HTTP 429
+    #[arg(long)]
+    pub max_series: Option<usize>,
+
+    /// Maximum number of samples kept per series (oldest are dropped)
+    #[arg(long)]
+    pub max_samples_per_series: Option<usize>,
+
+    /// Handling of duplicate and out-of-order samples (overwrite or reject)
+    #[arg(long, default_value = "overwrite")]
+    pub ingest_policy: IngestPolicy,
+
+    /// With --ingest-policy reject, accept samples this far behind a series' newest one
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "0s")]
+    pub out_of_order_window: std::time::Duration,
+
+    /// Interval of the background task applying retention and sample caps
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "1m")]
+    pub compaction_interval: std::time::Duration,
+
+    /// Storage snapshot file: loaded at startup if present, written on shutdown
+    #[arg(long)]
+    pub storage_snapshot: Option<PathBuf>,
+
+    /// Write-ahead log directory: replayed at startup, appended on every write
+    #[arg(long)]
+    pub wal_dir: Option<PathBuf>,
+
+    /// YAML file of generated series written into storage at startup
+    #[arg(long)]
+    pub seed: Option<PathBuf>,
+
+    /// Enable the TSDB admin API (delete_series, clean_tombstones, snapshot)
+    #[arg(long)]
+    pub enable_admin_api: bool,
+
+    /// Directory the admin snapshot endpoint writes snapshots to
+    #[arg(long, default_value = "snapshots")]
+    pub admin_snapshot_dir: PathBuf,
+}
+
+/// Tools run instead of the server.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Check fixtures for problems and exit non-zero if there are any
+    Validate {
+        /// Path to a YAML fixtures file or directory
+        fixtures: PathBuf,
+    },
 }
 
 /// Parse time string into `OffsetDateTime`.
@@ -7,18 +7,19 @@
 
 use std::io;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
 
 use clap::Parser;
 use tracing_subscriber::{fmt, EnvFilter};
 
-use prom_mock_rs::fixtures::FixtureBook;
+use prom_mock_rs::fixtures::{FixtureBook, SeedConfig};
 use prom_mock_rs::http::{build_router, AppState};
-use prom_mock_rs::storage::MemoryStorage;
+use prom_mock_rs::storage::{IngestPolicy, MemoryStorage, StorageLimits};
 
 mod cli;
 
-use cli::Cli;
+use cli::{Cli, Command};
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
@@ -28,34 +29,166 @@ async fn main() -> io::Result<()> {
 
     // Parse CLI arguments
     let cli = Cli::parse();
-
-    // Load fixtures (can work without file - empty set)
-    let book = if let Some(path) = &cli.fixtures {
-        FixtureBook::load_from_path(path)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
-    } else {
-        FixtureBook::default()
-    };
+    if let Some(Command::Validate { fixtures }) = &cli.command {
+        validate(fixtures);
+        return Ok(());
+    }
 
     // Create in-memory storage for remote write
-    let storage = Arc::new(MemoryStorage::new());
+    let limits = StorageLimits {
+        retention: cli.retention,
+        retention_anchor: cli.retention_anchor,
+        max_series: cli.max_series,
+        max_samples_per_series: cli.max_samples_per_series,
+    };
+    let ingest_policy = match cli.ingest_policy {
+        IngestPolicy::Reject { .. } => {
+            IngestPolicy::Reject { out_of_order_window: cli.out_of_order_window }
+        }
+        policy => policy,
+    };
+    let storage = Arc::new(
+        MemoryStorage::new()
+            .with_encoding(cli.storage_encoding)
+            .with_limits(limits)
+            .with_ingest_policy(ingest_policy),
+    );
+    if let Some(path) = cli.storage_snapshot.as_deref().filter(|p| p.exists()) {
+        let meta = storage.restore_snapshot(path).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("failed to load snapshot {}: {e}", path.display()),
+            )
+        })?;
+        tracing::info!(
+            "restored {} series ({} samples) from {}",
+            meta.series,
+            meta.samples,
+            path.display()
+        );
+    }
+    if let Some(dir) = &cli.wal_dir {
+        let samples = storage.open_wal(dir).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("failed to replay WAL {}: {e}", dir.display()),
+            )
+        })?;
+        tracing::info!("replayed {} samples from WAL {}", samples, dir.display());
+    }
+    if limits.is_enabled() {
+        storage.spawn_compaction(cli.compaction_interval);
+    }
 
     let mut builder = AppState::builder()
-        .with_storage(storage)
-        .with_fixtures(book)
+        .with_storage(storage.clone())
         .with_latency(cli.latency)
         .with_error_rate(cli.error_rate);
 
+    // Fixtures are optional, without a file every fixture query answers 404
+    if let Some(path) = &cli.fixtures {
+        builder = builder.with_fixtures_path(path);
+    }
     if let Some(fixed_time) = cli.fixed_now {
         builder = builder.with_fixed_now(fixed_time);
     }
+    if let Some(anchor) = cli.clock_anchor {
+        builder = builder.with_clock_anchor(anchor);
+    }
+    if cli.enable_admin_api {
+        builder = builder.with_admin_api(cli.admin_snapshot_dir);
+    }
 
     let state = builder.build()?;
+    if let Some(path) = &cli.seed {
+        let config = SeedConfig::load_from_path(path).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("failed to load seed {}: {e}", path.display()),
+            )
+        })?;
+        let now = state.query.fixed_now.unwrap_or_else(time::OffsetDateTime::now_utc);
+        let samples = config
+            .seed(storage.as_ref(), now)
+            .map_err(|e| io::Error::other(format!("failed to seed storage: {e}")))?;
+        tracing::info!(
+            "seeded {} series ({} samples) from {}",
+            config.series.len(),
+            samples,
+            path.display()
+        );
+    }
+    if !cli.fixtures_watch_interval.is_zero() {
+        state.mock.spawn_fixture_watcher(cli.fixtures_watch_interval);
+    }
 
     let app = build_router(state);
 
     let addr: SocketAddr = cli.listen.parse().map_err(io::Error::other)?;
     tracing::info!("starting prom-mock on http://{addr}");
-    axum::serve(tokio::net::TcpListener::bind(addr).await?, app).await?;
+    axum::serve(tokio::net::TcpListener::bind(addr).await?, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    if let Some(path) = &cli.storage_snapshot {
+        let meta = storage
+            .save_snapshot(path)
+            .map_err(|e| io::Error::other(format!("failed to write snapshot: {e}")))?;
+        tracing::info!("wrote {} series to {}", meta.series, path.display());
+    }
     Ok(())
 }
+
+/// Load fixtures and print every problem found by validation.
+///
+/// Exits the process with status 1 if loading fails or problems are found.
+fn validate(path: &Path) {
+    let book = match FixtureBook::load_from_path(path) {
+        Ok(book) => book,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    let issues = book.validate();
+    for issue in &issues {
+        println!("{issue}");
+    }
+    if !issues.is_empty() {
+        eprintln!("{} problems found in {}", issues.len(), path.display());
+        std::process::exit(1);
+    }
+    println!("{}: {} routes OK", path.display(), book.routes.len());
+}
+
+/// Wait for Ctrl-C or, on unix, SIGTERM to start a graceful shutdown.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("failed to listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut stream) => {
+                stream.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+    tracing::info!("shutting down");
+}
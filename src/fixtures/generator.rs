@@ -0,0 +1,514 @@
+//! Synthetic series generated from a description instead of listed points.
+//!
+//! A [`GeneratedSeries`] computes its value at any time, so the same
+//! declaration answers range queries at their step, instant queries at their
+//! evaluation time, and can seed storage through a [`SeedConfig`]. All
+//! generators are deterministic: the same time always yields the same value.
+//!
+//! ```yaml
+//! - metric: {__name__: http_requests_total, job: api}
+//!   type: counter
+//!   rate: 5
+//!   reset_every: 6h
+//!   gaps: {every: 1h, duration: 5m}
+//! ```
+
+use std::collections::BTreeMap;
+use std::f64::consts::TAU;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{duration_format, FixtureError};
+use crate::http::exposition::format_value;
+use crate::storage::{Label, Sample, Storage, StorageError, TimeSeries};
+
+/// Number of steps a random walk remembers; it stays within this many steps of its start.
+pub const WALK_WINDOW: i64 = 64;
+
+/// A series whose samples are computed by a generator.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct GeneratedSeries {
+    /// Labels of the series, including `__name__`
+    pub metric: BTreeMap<String, String>,
+    /// How values are computed
+    #[serde(flatten)]
+    pub generator: Generator,
+    /// Periodic time ranges without samples
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gaps: Option<Gaps>,
+}
+
+/// Value generators, selected by `type`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Generator {
+    /// Always the same value.
+    Constant {
+        /// The value
+        value: f64,
+    },
+    /// A counter increasing linearly, optionally dropping back to `start`.
+    Counter {
+        /// Value after a reset
+        #[serde(default)]
+        start: f64,
+        /// Increase per second
+        rate: f64,
+        /// Reset interval, aligned to the UNIX epoch
+        #[serde(default, with = "duration_format::option")]
+        reset_every: Option<Duration>,
+    },
+    /// A sine wave around `offset`.
+    Sine {
+        /// Center of the wave
+        #[serde(default)]
+        offset: f64,
+        /// Largest deviation from the center
+        amplitude: f64,
+        /// Length of one oscillation
+        #[serde(with = "duration_format")]
+        period: Duration,
+    },
+    /// A seeded random walk changing every `interval`.
+    RandomWalk {
+        /// Value the walk moves around
+        #[serde(default)]
+        start: f64,
+        /// Size of the random moves; the walk stays within
+        /// `WALK_WINDOW * step` of `start`
+        step: f64,
+        /// Seed of the random numbers
+        #[serde(default)]
+        seed: u64,
+        /// Lower bound of the values
+        min: Option<f64>,
+        /// Upper bound of the values
+        max: Option<f64>,
+        /// How often the value changes (15s by default)
+        #[serde(default = "default_walk_interval", with = "duration_format")]
+        interval: Duration,
+    },
+    /// Cycles through `values`, holding each for `every`.
+    Steps {
+        /// Values in order
+        values: Vec<f64>,
+        /// How long each value holds, aligned to the UNIX epoch
+        #[serde(with = "duration_format")]
+        every: Duration,
+    },
+}
+
+/// Periodic absence of samples, e.g. a target down for 5m every hour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Gaps {
+    /// Distance between gap starts, aligned to the UNIX epoch
+    #[serde(with = "duration_format")]
+    pub every: Duration,
+    /// Length of each gap
+    #[serde(with = "duration_format")]
+    pub duration: Duration,
+}
+
+fn default_walk_interval() -> Duration {
+    Duration::from_secs(15)
+}
+
+impl Generator {
+    /// Compute the value at a time.
+    ///
+    /// # Parameters
+    ///
+    /// - `t` - Time in UNIX seconds
+    ///
+    /// # Returns
+    ///
+    /// Returns the generated value.
+    pub fn value_at(&self, t: f64) -> f64 {
+        match self {
+            Generator::Constant { value } => *value,
+            Generator::Counter { start, rate, reset_every } => {
+                let elapsed = match reset_every {
+                    Some(period) => t.rem_euclid(period.as_secs_f64()),
+                    None => t,
+                };
+                start + rate * elapsed
+            }
+            Generator::Sine { offset, amplitude, period } => {
+                offset + amplitude * (TAU * t / period.as_secs_f64()).sin()
+            }
+            Generator::RandomWalk { start, step, seed, min, max, interval } => {
+                // Sum of the last WALK_WINDOW moves, so any time can be
+                // computed without walking from the beginning of time
+                let k = (t / interval.as_secs_f64()).floor() as i64;
+                let moves: f64 = (k - WALK_WINDOW + 1..=k)
+                    .map(|j| step * (2.0 * unit_noise(*seed, j) - 1.0))
+                    .sum();
+                let value = start + moves;
+                let value = min.map_or(value, |min| value.max(min));
+                max.map_or(value, |max| value.min(max))
+            }
+            Generator::Steps { values, every } => {
+                let k = (t / every.as_secs_f64()).floor() as i64;
+                values[k.rem_euclid(values.len() as i64) as usize]
+            }
+        }
+    }
+
+    /// Check the parameters can generate values.
+    fn check(&self) -> Result<(), String> {
+        let positive = |name: &str, d: &Duration| {
+            if d.is_zero() {
+                Err(format!("{name} must be greater than 0"))
+            } else {
+                Ok(())
+            }
+        };
+        match self {
+            Generator::Constant { .. } => Ok(()),
+            Generator::Counter { rate, reset_every, .. } => {
+                if *rate < 0.0 {
+                    return Err("counter rate must not be negative".to_string());
+                }
+                reset_every.as_ref().map_or(Ok(()), |d| positive("reset_every", d))
+            }
+            Generator::Sine { period, .. } => positive("period", period),
+            Generator::RandomWalk { min, max, interval, .. } => {
+                if let (Some(min), Some(max)) = (min, max) {
+                    if min > max {
+                        return Err("random walk min must not exceed max".to_string());
+                    }
+                }
+                positive("interval", interval)
+            }
+            Generator::Steps { values, every } => {
+                if values.is_empty() {
+                    return Err("steps needs at least one value".to_string());
+                }
+                positive("every", every)
+            }
+        }
+    }
+}
+
+impl GeneratedSeries {
+    /// Compute the sample at a time.
+    ///
+    /// # Parameters
+    ///
+    /// - `t` - Time in UNIX seconds
+    ///
+    /// # Returns
+    ///
+    /// Returns the value, or `None` if `t` falls into a gap.
+    pub fn value_at(&self, t: f64) -> Option<f64> {
+        let in_gap = self.gaps.is_some_and(|gaps| {
+            t.rem_euclid(gaps.every.as_secs_f64()) < gaps.duration.as_secs_f64()
+        });
+        (!in_gap).then(|| self.generator.value_at(t))
+    }
+
+    /// Check the generator and gaps are usable.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first problem found.
+    pub fn check(&self) -> Result<(), String> {
+        self.generator.check()?;
+        if let Some(gaps) = self.gaps {
+            if gaps.every.is_zero() {
+                return Err("gaps every must be greater than 0".to_string());
+            }
+            if gaps.duration >= gaps.every {
+                return Err("gaps duration must be shorter than every".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Build instant query data with the value of each series at `time`.
+///
+/// # Parameters
+///
+/// - `series` - Generated series
+/// - `time` - Evaluation time in UNIX seconds
+///
+/// # Returns
+///
+/// Returns `vector` data; series in a gap are left out.
+pub fn vector(series: &[GeneratedSeries], time: f64) -> Value {
+    let result: Vec<Value> = series
+        .iter()
+        .filter_map(|s| {
+            let value = s.value_at(time)?;
+            Some(json!({"metric": s.metric, "value": sample(time, value)}))
+        })
+        .collect();
+    json!({"resultType": "vector", "result": result})
+}
+
+/// Build range query data with the values of each series at `times`.
+///
+/// # Parameters
+///
+/// - `series` - Generated series
+/// - `times` - Evaluation times in UNIX seconds, one per step
+///
+/// # Returns
+///
+/// Returns `matrix` data; series without any samples are left out.
+pub fn matrix(series: &[GeneratedSeries], times: &[f64]) -> Value {
+    let result: Vec<Value> = series
+        .iter()
+        .filter_map(|s| {
+            let values: Vec<Value> =
+                times.iter().filter_map(|&t| Some(sample(t, s.value_at(t)?))).collect();
+            (!values.is_empty()).then(|| json!({"metric": s.metric, "values": values}))
+        })
+        .collect();
+    json!({"resultType": "matrix", "result": result})
+}
+
+/// A `[<unix time>, "<value>"]` pair, with whole seconds as integers.
+fn sample(t: f64, value: f64) -> Value {
+    let time = if t.fract() == 0.0 { json!(t as i64) } else { json!(t) };
+    json!([time, format_value(value)])
+}
+
+/// Deterministic noise in `[0, 1)` for a seed and index (SplitMix64).
+fn unit_noise(seed: u64, index: i64) -> f64 {
+    let mut z = seed.wrapping_add((index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Generated series written into storage at startup.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SeedConfig {
+    /// How far back from now samples are generated (1h by default)
+    #[serde(default = "default_seed_range", with = "duration_format")]
+    pub range: Duration,
+    /// Distance between generated samples (15s by default)
+    #[serde(default = "default_walk_interval", with = "duration_format")]
+    pub interval: Duration,
+    /// Series to generate
+    pub series: Vec<GeneratedSeries>,
+}
+
+fn default_seed_range() -> Duration {
+    Duration::from_secs(3600)
+}
+
+impl SeedConfig {
+    /// Load a seed configuration from a YAML file.
+    ///
+    /// # Parameters
+    ///
+    /// - `path` - Path to the YAML file
+    ///
+    /// # Returns
+    ///
+    /// Returns the configuration with all generators checked.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FixtureError` if the file cannot be read or parsed, or if a
+    /// series has unusable parameters.
+    pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self, FixtureError> {
+        let config: Self = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+        // Samples are written at millisecond timestamps
+        if config.interval < Duration::from_millis(1) {
+            return Err(FixtureError::InvalidSeries {
+                index: 0,
+                reason: "interval must be at least 1ms".to_string(),
+            });
+        }
+        for (index, series) in config.series.iter().enumerate() {
+            series.check().map_err(|reason| FixtureError::InvalidSeries { index, reason })?;
+        }
+        Ok(config)
+    }
+
+    /// Write the generated samples of the last `range` before `now` into storage.
+    ///
+    /// Sample times are multiples of `interval`, like scrapes would be.
+    ///
+    /// # Parameters
+    ///
+    /// - `storage` - Storage to write to
+    /// - `now` - End of the generated range
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of samples written.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StorageError` if storage limits reject a series.
+    pub fn seed(
+        &self,
+        storage: &dyn Storage,
+        now: time::OffsetDateTime,
+    ) -> Result<usize, StorageError> {
+        let interval = self.interval.as_millis() as i64;
+        let end = (now.unix_timestamp_nanos() / 1_000_000) as i64;
+        let end = end - end.rem_euclid(interval);
+        let start = end - self.range.as_millis() as i64;
+
+        let mut written = 0;
+        for series in &self.series {
+            let labels = series.metric.iter().map(|(name, value)| Label::new(name, value));
+            let mut ts = TimeSeries::new(labels.collect());
+            for timestamp in (start..=end).step_by(interval as usize) {
+                if let Some(value) = series.value_at(timestamp as f64 / 1000.0) {
+                    ts.add_sample(Sample::new(timestamp, value));
+                }
+            }
+            written += ts.samples.len();
+            storage.try_add_series(ts)?;
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::matchers::{EqualMatcher, LabelMatcher};
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    fn series(yaml: &str) -> GeneratedSeries {
+        serde_yaml::from_str(yaml).expect("valid series")
+    }
+
+    fn values(series: &GeneratedSeries, times: &[f64]) -> Vec<Option<f64>> {
+        times.iter().map(|&t| series.value_at(t)).collect()
+    }
+
+    /// Test the value of each generator type.
+    #[test]
+    fn test_generators() {
+        let constant = series("{metric: {}, type: constant, value: 3}");
+        assert_eq!(values(&constant, &[0.0, 1e9]), vec![Some(3.0), Some(3.0)]);
+
+        let counter = series("{metric: {}, type: counter, start: 10, rate: 2, reset_every: 1m}");
+        assert_eq!(
+            values(&counter, &[0.0, 30.0, 59.0, 60.0, 90.0]),
+            vec![Some(10.0), Some(70.0), Some(128.0), Some(10.0), Some(70.0)]
+        );
+
+        let sine = series("{metric: {}, type: sine, offset: 5, amplitude: 2, period: 4s}");
+        let sampled = values(&sine, &[0.0, 1.0, 3.0]);
+        assert!((sampled[0].unwrap() - 5.0).abs() < 1e-9);
+        assert!((sampled[1].unwrap() - 7.0).abs() < 1e-9);
+        assert!((sampled[2].unwrap() - 3.0).abs() < 1e-9);
+
+        let steps = series("{metric: {}, type: steps, values: [1, 2, 3], every: 10s}");
+        assert_eq!(
+            values(&steps, &[0.0, 10.0, 25.0, 30.0]),
+            vec![Some(1.0), Some(2.0), Some(3.0), Some(1.0)]
+        );
+    }
+
+    /// Test random walks are deterministic per seed, bounded and move per interval.
+    #[test]
+    fn test_random_walk() {
+        let walk = |seed: u64| {
+            series(&format!(
+                "{{metric: {{}}, type: random_walk, start: 50, step: 5, seed: {seed}, min: 0, max: 100}}"
+            ))
+        };
+        let times: Vec<f64> = (0..200).map(|i| 1_700_000_100.0 + i as f64 * 15.0).collect();
+        let a = values(&walk(1), &times);
+        assert_eq!(a, values(&walk(1), &times));
+        assert_ne!(a, values(&walk(2), &times));
+        assert!(a.iter().flatten().all(|v| (0.0..=100.0).contains(v)));
+        assert!(a.windows(2).any(|w| w[0] != w[1]));
+        // Constant within an interval
+        assert_eq!(walk(1).value_at(times[0] + 14.0), a[0]);
+    }
+
+    /// Test gaps, vector and matrix data.
+    #[test]
+    fn test_gaps_vector_and_matrix() {
+        let gappy = series(
+            "{metric: {__name__: up, job: api}, type: constant, value: 1, gaps: {every: 1m, duration: 20s}}",
+        );
+        assert_eq!(values(&gappy, &[0.0, 19.0, 20.0, 60.0]), vec![None, None, Some(1.0), None]);
+
+        assert_eq!(
+            vector(std::slice::from_ref(&gappy), 30.0),
+            json!({"resultType": "vector", "result": [
+                {"metric": {"__name__": "up", "job": "api"}, "value": [30, "1"]}
+            ]})
+        );
+        assert_eq!(vector(std::slice::from_ref(&gappy), 10.0)["result"], json!([]));
+        assert_eq!(
+            matrix(&[gappy], &[0.0, 30.0, 45.5]),
+            json!({"resultType": "matrix", "result": [
+                {"metric": {"__name__": "up", "job": "api"}, "values": [[30, "1"], [45.5, "1"]]}
+            ]})
+        );
+    }
+
+    /// Test invalid parameters are rejected.
+    #[test]
+    fn test_check() {
+        for bad in [
+            "{metric: {}, type: counter, rate: -1}",
+            "{metric: {}, type: sine, amplitude: 1, period: 0s}",
+            "{metric: {}, type: steps, values: [], every: 1m}",
+            "{metric: {}, type: random_walk, step: 1, min: 5, max: 1}",
+            "{metric: {}, type: constant, value: 1, gaps: {every: 1m, duration: 1m}}",
+        ] {
+            assert!(series(bad).check().is_err(), "{bad} should be rejected");
+        }
+    }
+
+    /// Test seeding storage with generated samples.
+    #[test]
+    fn test_seed_storage() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("seed.yaml");
+        fs::write(
+            &path,
+            "range: 1m\ninterval: 15s\nseries:\n  - metric: {__name__: up, job: api}\n    type: constant\n    value: 1\n",
+        )
+        .expect("write seed");
+        let config = SeedConfig::load_from_path(&path).expect("valid seed");
+
+        let storage = MemoryStorage::new();
+        let now = time::macros::datetime!(2025-08-03 00:00:07 UTC);
+        assert_eq!(config.seed(&storage, now), Ok(5));
+        let matchers: Vec<Arc<dyn LabelMatcher>> = vec![Arc::new(EqualMatcher::new("job", "api"))];
+        let series = storage.query_series(&matchers);
+        assert_eq!(series.len(), 1);
+        let timestamps: Vec<i64> = series[0].samples.iter().map(|s| s.timestamp).collect();
+        let end = 1_754_179_200_000;
+        assert_eq!(timestamps, vec![end - 60_000, end - 45_000, end - 30_000, end - 15_000, end]);
+
+        for bad in [
+            "series:\n  - {metric: {}, type: steps, values: [], every: 1m}\n",
+            "interval: 500us\nseries: []\n",
+            "interval: 0s\nseries: []\n",
+        ] {
+            fs::write(&path, bad).expect("write seed");
+            assert!(
+                matches!(
+                    SeedConfig::load_from_path(&path),
+                    Err(FixtureError::InvalidSeries { index: 0, .. })
+                ),
+                "{bad} should be rejected"
+            );
+        }
+    }
+}
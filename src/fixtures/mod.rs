@@ -0,0 +1,1884 @@
+//! Fixture definitions for predefined API responses and route matching.
+//!
+//! Fixture books are checked for problems beyond parse errors by
+//! [`FixtureBook::validate`], see [`validate`].
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use fnv::FnvHashMap;
+use rand::Rng;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::timeutil::{resolve_relative, ClockAnchor, ResolvedParam};
+
+pub mod generator;
+pub mod template;
+pub mod validate;
+
+pub use generator::{GeneratedSeries, Generator, SeedConfig};
+pub use template::{TemplateContext, TemplateError};
+pub use validate::{is_fixture_path, ValidationIssue, FIXTURE_PATHS};
+
+/// Errors that can occur when loading or processing fixtures.
+#[derive(Debug, Error)]
+pub enum FixtureError {
+    /// I/O error while reading fixture file.
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+    /// YAML parsing error.
+    #[error("yaml: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    /// A route is not usable.
+    #[error("route {index}: {reason}")]
+    InvalidRoute {
+        /// Position of the route in the fixture file
+        index: usize,
+        /// Description of the problem
+        reason: String,
+    },
+    /// `defaults.clock_anchor` is not `now`, `startup` or an RFC3339 time.
+    #[error("clock_anchor: {0}")]
+    InvalidClockAnchor(String),
+    /// A generated series in a seed configuration is not usable.
+    #[error("series {index}: {reason}")]
+    InvalidSeries {
+        /// Position of the series in the configuration
+        index: usize,
+        /// Description of the problem
+        reason: String,
+    },
+    /// A reload changes the `clock_anchor` that `now` was resolved from at startup.
+    #[error("clock_anchor changed from {from} to {to}, restart to apply it")]
+    ClockAnchorChanged {
+        /// Anchor in effect
+        from: String,
+        /// Anchor of the reloaded fixtures
+        to: String,
+    },
+    /// Fixtures were asked to reload, but were not loaded from a file.
+    #[error("no fixture file configured")]
+    NoFixtureFile,
+    /// A fixture file includes itself, directly or through other files.
+    #[error("include cycle through {}", .0.display())]
+    IncludeCycle(PathBuf),
+    /// Error in an included file or a file of a fixture directory.
+    #[error("{}: {source}", path.display())]
+    InFile {
+        /// File that failed to load
+        path: PathBuf,
+        /// Error in that file
+        source: Box<FixtureError>,
+    },
+}
+
+impl FixtureError {
+    /// Attribute the error to a fixture file.
+    fn in_file(self, path: &Path) -> Self {
+        FixtureError::InFile { path: path.to_path_buf(), source: Box::new(self) }
+    }
+}
+
+/// File extensions loaded from fixture directories.
+const FIXTURE_EXTENSIONS: [&str; 3] = ["yaml", "yml", "json"];
+
+/// Scenario state every scenario starts in, as in WireMock.
+pub const SCENARIO_STARTED: &str = "Started";
+
+/// A collection of fixture routes and their default settings.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct FixtureBook {
+    /// Schema version of the fixture file.
+    pub version: Option<u8>,
+    /// Default settings for responses.
+    pub defaults: Option<Defaults>,
+    /// Fixture files or directories to load, relative to this file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<PathBuf>,
+    /// List of route matchers and their responses.
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    /// Files the book was loaded from.
+    #[serde(skip)]
+    pub sources: Vec<PathBuf>,
+}
+
+/// Default settings for fixture responses.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Defaults {
+    /// Default status ("success" by default).
+    pub status: Option<String>,
+    /// Reference time for relative time resolution: `now` (the live clock),
+    /// `startup` (frozen at server start) or an RFC3339 time.
+    pub clock_anchor: Option<String>,
+}
+
+/// A route definition with matcher and response.
+///
+/// A route answers either with a single `respond` or with a `sequence` of
+/// responses that advances on every matched request.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Route {
+    /// Request matcher criteria.
+    #[serde(rename = "match")]
+    pub matcher: Matcher,
+    /// Response to return when matched.
+    pub respond: Option<Respond>,
+    /// Responses to return on successive matches.
+    pub sequence: Option<Sequence>,
+    /// Name of the scenario this route belongs to.
+    pub scenario: Option<String>,
+    /// Scenario state required for the route to match (any state if unset).
+    pub required_state: Option<String>,
+    /// Scenario state to move to after the route matched.
+    pub new_state: Option<String>,
+    /// Where the route was loaded from, if it came from a file.
+    #[serde(skip)]
+    pub location: Option<RouteLocation>,
+}
+
+/// Position of a route in its fixture file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteLocation {
+    /// Fixture file
+    pub file: PathBuf,
+    /// Index of the route in the file's `routes`
+    pub index: usize,
+    /// Line of the route, unknown for flow style YAML and JSON
+    pub line: Option<usize>,
+}
+
+/// Successive responses of a route.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Sequence {
+    /// What happens after the last response.
+    #[serde(default)]
+    pub mode: SequenceMode,
+    /// Responses in the order they are returned.
+    pub responses: Vec<SequenceStep>,
+}
+
+/// Behavior of a sequence once all responses were returned.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SequenceMode {
+    /// Keep returning the last response.
+    #[default]
+    Last,
+    /// Start over with the first response.
+    Cycle,
+}
+
+/// A response in a sequence, returned for a number of consecutive calls.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SequenceStep {
+    /// Response to return.
+    #[serde(flatten)]
+    pub respond: Respond,
+    /// Number of consecutive calls answered with this response (1 by default).
+    pub times: Option<u32>,
+}
+
+/// Request matching criteria for a fixture route.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Matcher {
+    /// API path, one of [`FIXTURE_PATHS`].
+    pub path: String,
+    /// `PromQL` query string.
+    pub query: Option<String>,
+    /// Regular expression the whole `PromQL` query must match.
+    pub query_regex: Option<String>,
+    /// Compare `query` and `match[]` after [`normalize_query_tokens`], ignoring
+    /// spacing, comments, quoting and label matcher order.
+    #[serde(default)]
+    pub query_normalized: bool,
+    /// Series selectors the request's `match[]` parameters must equal, in any order.
+    #[serde(default, rename = "match[]", skip_serializing_if = "Vec::is_empty")]
+    pub matches: Vec<String>,
+    /// Start time for `query_range` and metadata requests.
+    pub start: Option<String>,
+    /// End time for `query_range` and metadata requests.
+    pub end: Option<String>,
+    /// Step interval for `query_range`.
+    pub step: Option<String>,
+    /// `query_regex` compiled when the fixtures are loaded, or on first use.
+    #[serde(skip)]
+    pub(crate) compiled_query_regex: CompiledRegex,
+}
+
+/// Compiled `query_regex` of a [`Matcher`], kept for the matcher's lifetime.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CompiledRegex(OnceLock<Result<Regex, regex::Error>>);
+
+impl PartialEq for CompiledRegex {
+    /// Always equal: the compiled form is derived from `query_regex`.
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Matcher {
+    /// Get the compiled `query_regex`, anchored like `PromQL` regex matchers.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` without a `query_regex`, otherwise the compiled
+    /// pattern or the reason it is invalid.
+    pub fn query_regex(&self) -> Option<Result<&Regex, &regex::Error>> {
+        let pattern = self.query_regex.as_deref()?;
+        // The pattern is checked on its own first, so that it cannot
+        // escape the anchoring group
+        let compiled = self.compiled_query_regex.0.get_or_init(|| {
+            Regex::new(pattern).and_then(|_| Regex::new(&format!("^(?:{pattern})$")))
+        });
+        Some(compiled.as_ref())
+    }
+}
+
+/// Response data for a matched fixture route.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Respond {
+    /// Response status (success/error).
+    pub status: Option<String>,
+    /// Response data in Prometheus format, omitted with `generate`.
+    #[serde(default)]
+    pub data: serde_json::Value,
+    /// Render the `{{ expr }}` templates in `data`; otherwise it is literal.
+    #[serde(default)]
+    pub template: bool,
+    /// Warning messages.
+    pub warnings: Option<Vec<String>>,
+    /// Error type for error responses.
+    #[serde(rename = "errorType")]
+    pub error_type: Option<String>,
+    /// Error message for error responses.
+    pub error: Option<String>,
+    /// Delay before answering, on top of the global latency.
+    pub delay: Option<Delay>,
+    /// Probability (0.0-1.0) of answering 503 instead.
+    pub failure_rate: Option<f32>,
+    /// HTTP status code; derived from `status` and `errorType` if unset.
+    pub http_status: Option<u16>,
+    /// Extra HTTP response headers.
+    pub headers: Option<BTreeMap<String, String>>,
+    /// Series whose samples are generated per request instead of `data`.
+    pub generate: Option<Vec<GeneratedSeries>>,
+}
+
+/// Simulated delay of a fixture response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Delay {
+    /// Always wait this long, e.g. `"250ms"`.
+    Fixed(#[serde(with = "duration_format")] Duration),
+    /// Wait a uniformly distributed time between `min` and `max`.
+    Uniform {
+        /// Shortest delay
+        #[serde(with = "duration_format")]
+        min: Duration,
+        /// Longest delay
+        #[serde(with = "duration_format")]
+        max: Duration,
+    },
+}
+
+impl Delay {
+    /// Pick the delay for one response.
+    ///
+    /// # Returns
+    ///
+    /// Returns the time to wait before answering.
+    pub fn sample(&self) -> Duration {
+        match *self {
+            Delay::Fixed(delay) => delay,
+            Delay::Uniform { min, max } if min < max => rand::thread_rng().gen_range(min..=max),
+            Delay::Uniform { min, .. } => min,
+        }
+    }
+}
+
+/// Human-readable durations like `"1s"` or `"250ms"` in fixture files.
+mod duration_format {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&humantime::format_duration(*duration).to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        humantime::parse_duration(&value).map_err(serde::de::Error::custom)
+    }
+
+    /// The same for optional durations.
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            duration: &Option<Duration>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match duration {
+                Some(duration) => super::serialize(duration, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Duration>, D::Error> {
+            let value = Option::<String>::deserialize(deserializer)?;
+            value
+                .map(|value| humantime::parse_duration(&value).map_err(serde::de::Error::custom))
+                .transpose()
+        }
+    }
+}
+
+impl Respond {
+    /// HTTP status code of the response.
+    ///
+    /// Without an explicit `http_status`, error responses use the code
+    /// Prometheus answers with for their `errorType` and all other
+    /// responses use 200.
+    ///
+    /// # Parameters
+    ///
+    /// - `status` - Effective response status, see [`FixtureBook::effective_status`]
+    ///
+    /// # Returns
+    ///
+    /// Returns the HTTP status code.
+    pub fn status_code(&self, status: &str) -> u16 {
+        if let Some(code) = self.http_status {
+            return code;
+        }
+        if status != "error" {
+            return 200;
+        }
+        match self.error_type.as_deref() {
+            Some("bad_data") => 400,
+            Some("not_found") => 404,
+            Some("not_acceptable") => 406,
+            Some("execution") => 422,
+            Some("canceled") => 499,
+            Some("timeout" | "unavailable") => 503,
+            _ => 500,
+        }
+    }
+
+    /// Response data for a request.
+    ///
+    /// Generated series answer range requests (when `ctx` has range
+    /// parameters) with a matrix at every step and instant requests with a
+    /// vector at `now`. Otherwise `data` is returned, with its templates
+    /// rendered if `template` is set.
+    ///
+    /// # Parameters
+    ///
+    /// - `ctx` - Variable values for the request
+    ///
+    /// # Returns
+    ///
+    /// Returns the data to answer with.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TemplateError` if a template cannot be rendered or the range
+    /// parameters cannot be used.
+    pub fn render_data(&self, ctx: &TemplateContext) -> Result<serde_json::Value, TemplateError> {
+        let Some(series) = &self.generate else {
+            if !self.template {
+                return Ok(self.data.clone());
+            }
+            return template::render(&self.data, ctx);
+        };
+        if ctx.start.is_none() && ctx.end.is_none() && ctx.step.is_none() {
+            return Ok(generator::vector(series, ctx.now));
+        }
+        Ok(generator::matrix(series, &template::step_times(ctx)?))
+    }
+}
+
+impl FixtureBook {
+    /// Load fixtures from a YAML file or a directory of fixture files.
+    ///
+    /// A directory is loaded as if its `*.yaml`, `*.yml` and `*.json` files
+    /// were included in file name order. Files listed under `include` are
+    /// loaded after the including file, and their routes follow its own.
+    /// `defaults.status` only applies to the routes of the file declaring
+    /// it; `clock_anchor` is taken from the first file setting it.
+    ///
+    /// # Parameters
+    ///
+    /// - `path` - Path to the YAML fixtures file or directory
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(FixtureBook)` on success, or `FixtureError` if a file cannot be read or parsed.
+    /// Errors are `FixtureError::InFile`, naming the failing file and, through
+    /// nested `InFile` errors, the files including it.
+    pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self, FixtureError> {
+        let path = path.as_ref();
+        let mut book = if path.is_dir() {
+            let mut book = Self { sources: vec![path.to_path_buf()], ..Self::default() };
+            for file in fixture_files(path).map_err(|e| e.in_file(path))? {
+                let included = Self::load_file(&file, &mut vec![]).map_err(|e| e.in_file(&file))?;
+                book.merge(included);
+            }
+            book
+        } else {
+            Self::load_file(path, &mut vec![]).map_err(|e| e.in_file(path))?
+        };
+        // defaults.status defaults to success
+        if book.defaults.is_none() {
+            book.defaults = Some(Defaults { status: Some("success".into()), clock_anchor: None });
+        } else if let Some(defaults) = &book.defaults {
+            if defaults.status.is_none() {
+                if let Some(defaults_mut) = &mut book.defaults {
+                    defaults_mut.status = Some("success".into());
+                }
+            }
+        }
+        book.clock_anchor().map_err(|e| e.in_file(path))?;
+        Ok(book)
+    }
+
+    /// Get the reference time declared by `defaults.clock_anchor`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the parsed anchor, or `None` if the book does not declare one.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FixtureError::InvalidClockAnchor` if the anchor is not `now`,
+    /// `startup` or an RFC3339 time.
+    pub fn clock_anchor(&self) -> Result<Option<ClockAnchor>, FixtureError> {
+        self.defaults
+            .as_ref()
+            .and_then(|d| d.clock_anchor.as_deref())
+            .map(|anchor| anchor.parse().map_err(FixtureError::InvalidClockAnchor))
+            .transpose()
+    }
+
+    /// Load a fixture file and, recursively, the files it includes.
+    fn load_file(path: &Path, stack: &mut Vec<PathBuf>) -> Result<Self, FixtureError> {
+        let canonical = fs::canonicalize(path)?;
+        if stack.contains(&canonical) {
+            return Err(FixtureError::IncludeCycle(path.to_path_buf()));
+        }
+
+        let txt = fs::read_to_string(path)?;
+        let mut book: Self = serde_yaml::from_str(&txt)?;
+        book.check_routes()?;
+        book.sources = vec![path.to_path_buf()];
+        let lines = route_lines(&txt);
+        let lines_known = lines.len() == book.routes.len();
+        for (index, route) in book.routes.iter_mut().enumerate() {
+            let line = if lines_known { Some(lines[index]) } else { None };
+            route.location = Some(RouteLocation { file: path.to_path_buf(), index, line });
+        }
+
+        // The file's default status only applies to its own routes
+        if let Some(status) = book.defaults.as_ref().and_then(|d| d.status.clone()) {
+            for respond in book.routes.iter_mut().flat_map(Route::responses_mut) {
+                respond.status.get_or_insert_with(|| status.clone());
+            }
+        }
+
+        stack.push(canonical);
+        let base = path.parent().unwrap_or(Path::new(""));
+        for include in std::mem::take(&mut book.include) {
+            let include = base.join(include);
+            let files = if include.is_dir() {
+                book.sources.push(include.clone());
+                fixture_files(&include).map_err(|e| e.in_file(&include))?
+            } else {
+                vec![include]
+            };
+            for file in files {
+                let included = Self::load_file(&file, stack).map_err(|e| e.in_file(&file))?;
+                book.merge(included);
+            }
+        }
+        stack.pop();
+        Ok(book)
+    }
+
+    /// Append the routes of another book, keeping the first clock anchor.
+    fn merge(&mut self, other: Self) {
+        let anchor = other.defaults.and_then(|d| d.clock_anchor);
+        if let Some(anchor) = anchor {
+            let defaults =
+                self.defaults.get_or_insert(Defaults { status: None, clock_anchor: None });
+            defaults.clock_anchor.get_or_insert(anchor);
+        }
+        self.routes.extend(other.routes);
+        self.sources.extend(other.sources);
+    }
+
+    /// Find a matching fixture route for the given request parameters.
+    ///
+    /// This ignores fixture state: scenario routes match in their initial
+    /// state and sequences return their first response. Request handlers use
+    /// [`FixtureBook::next_response`] instead.
+    ///
+    /// # Parameters
+    ///
+    /// - `path` - API path like "/api/v1/query" or "/`api/v1/query_range`"
+    /// - `params` - Query parameters including `PromQL` query and time range
+    /// - `now` - Optional fixed time for relative time resolution
+    ///
+    /// # Returns
+    ///
+    /// Returns `Some(Respond)` if a matching route is found, `None` otherwise.
+    pub fn find_match(
+        &self,
+        path: &str,
+        params: &QueryParams,
+        now: Option<time::OffsetDateTime>,
+    ) -> Option<&Respond> {
+        self.routes
+            .iter()
+            .filter(|r| r.in_state(SCENARIO_STARTED))
+            .find(|r| r.matches(path, params, now))
+            .and_then(|r| r.response_at(0))
+    }
+
+    /// Find the response for a request and advance sequences and scenarios.
+    ///
+    /// Routes of a scenario only match while the scenario is in their
+    /// `required_state`; a matched route moves its scenario to `new_state`.
+    /// Every match counts as a call of the route, selecting the next
+    /// response of its sequence.
+    ///
+    /// # Parameters
+    ///
+    /// - `path` - API path like "/api/v1/query" or "/`api/v1/query_range`"
+    /// - `params` - Query parameters including `PromQL` query and time range
+    /// - `now` - Optional fixed time for relative time resolution
+    /// - `state` - Call counts and scenario states, updated on a match
+    ///
+    /// # Returns
+    ///
+    /// Returns `Some(Respond)` if a matching route is found, `None` otherwise.
+    pub fn next_response(
+        &self,
+        path: &str,
+        params: &QueryParams,
+        now: Option<time::OffsetDateTime>,
+        state: &FixtureState,
+    ) -> Option<&Respond> {
+        let mut inner = state.inner.lock().unwrap();
+        let (index, route) = self.routes.iter().enumerate().find(|(_, r)| {
+            let current = r.scenario.as_ref().and_then(|name| inner.scenarios.get(name));
+            r.in_state(current.map_or(SCENARIO_STARTED, String::as_str))
+                && r.matches(path, params, now)
+        })?;
+
+        let calls = inner.calls.entry(index).or_default();
+        let respond = route.response_at(*calls);
+        *calls += 1;
+        if let (Some(name), Some(new_state)) = (&route.scenario, &route.new_state) {
+            inner.scenarios.insert(name.clone(), new_state.clone());
+        }
+        respond
+    }
+
+    /// Check every route has exactly one way to respond.
+    fn check_routes(&self) -> Result<(), FixtureError> {
+        for (index, route) in self.routes.iter().enumerate() {
+            let invalid =
+                |reason: &str| FixtureError::InvalidRoute { index, reason: reason.into() };
+            match (&route.respond, &route.sequence) {
+                (None, None) => return Err(invalid("either respond or sequence is required")),
+                (Some(_), Some(_)) => {
+                    return Err(invalid("respond and sequence are mutually exclusive"))
+                }
+                (None, Some(sequence)) => {
+                    if sequence.responses.is_empty() {
+                        return Err(invalid("sequence has no responses"));
+                    }
+                    if sequence.responses.iter().any(|step| step.times == Some(0)) {
+                        return Err(invalid("sequence times must be at least 1"));
+                    }
+                }
+                (Some(_), None) => {}
+            }
+            if route.scenario.is_none()
+                && (route.required_state.is_some() || route.new_state.is_some())
+            {
+                return Err(invalid("required_state and new_state need a scenario"));
+            }
+            for respond in route.responses() {
+                if respond.failure_rate.is_some_and(|rate| !(0.0..=1.0).contains(&rate)) {
+                    return Err(invalid("failure_rate must be between 0.0 and 1.0"));
+                }
+                if respond.http_status.is_some_and(|code| !(100..=599).contains(&code)) {
+                    return Err(invalid("http_status must be between 100 and 599"));
+                }
+                if let Some(Delay::Uniform { min, max }) = respond.delay {
+                    if min > max {
+                        return Err(invalid("delay min must not exceed max"));
+                    }
+                }
+                if let Some(series) = &respond.generate {
+                    if !respond.data.is_null() {
+                        return Err(invalid("data and generate are mutually exclusive"));
+                    }
+                    for (i, series) in series.iter().enumerate() {
+                        if let Err(reason) = series.check() {
+                            let reason = format!("generate[{i}]: {reason}");
+                            return Err(FixtureError::InvalidRoute { index, reason });
+                        }
+                    }
+                }
+                if respond.template {
+                    if let Err(e) = template::variables(&respond.data) {
+                        return Err(FixtureError::InvalidRoute { index, reason: e.to_string() });
+                    }
+                }
+            }
+            // Compiles the pattern once, for all later requests
+            if let Some(Err(e)) = route.matcher.query_regex() {
+                return Err(invalid(&format!("invalid query_regex: {e}")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the effective status for a response, using defaults if not specified.
+    ///
+    /// # Parameters
+    ///
+    /// - `resp` - Response object to get status from
+    ///
+    /// # Returns
+    ///
+    /// Returns the status string, defaulting to "success" if not specified.
+    pub fn effective_status<'a>(&'a self, resp: &'a Respond) -> &'a str {
+        resp.status
+            .as_deref()
+            .or_else(|| self.defaults.as_ref().and_then(|d| d.status.as_deref()))
+            .unwrap_or("success")
+    }
+}
+
+impl Route {
+    /// Whether the route can match while its scenario is in `state`.
+    fn in_state(&self, state: &str) -> bool {
+        self.required_state.as_deref().map_or(true, |required| required == state)
+    }
+
+    /// Whether the request matches the route criteria.
+    fn matches(&self, path: &str, params: &QueryParams, now: Option<time::OffsetDateTime>) -> bool {
+        if self.matcher.path != path {
+            return false;
+        }
+
+        // query must match if specified
+        if let Some(q) = &self.matcher.query {
+            let equal = if self.matcher.query_normalized {
+                query_equal_normalized(q, &params.query)
+            } else {
+                &params.query == q
+            };
+            if !equal {
+                return false;
+            }
+        }
+        match self.matcher.query_regex() {
+            Some(Ok(re)) if !re.is_match(&params.query) => return false,
+            // Loading rejects invalid patterns, only books built in code have them
+            Some(Err(_)) => return false,
+            _ => {}
+        }
+
+        if !self.matcher.matches.is_empty() && !self.selectors_match(&params.matches) {
+            return false;
+        }
+
+        // Instant queries have no range; query_range needs all of it
+        if path.ends_with("/query") {
+            return true;
+        }
+        if path.ends_with("/query_range")
+            && (params.start.is_none() || params.end.is_none() || params.step.is_none())
+        {
+            return false;
+        }
+
+        // Fixture can contain absolute values or relative (now-15m)
+        let param_matches =
+            |expect: &Option<String>, got: &Option<String>, relative: bool| match (expect, got) {
+                (None, _) => true,
+                (Some(_), None) => false,
+                (Some(expect), Some(got)) if relative => param_equal(expect, got, now),
+                (Some(expect), Some(got)) => expect == got,
+            };
+        param_matches(&self.matcher.start, &params.start, true)
+            && param_matches(&self.matcher.end, &params.end, true)
+            && param_matches(&self.matcher.step, &params.step, false)
+    }
+
+    /// Whether the request's `match[]` selectors equal the route's, in any order.
+    fn selectors_match(&self, selectors: &[String]) -> bool {
+        let canonical = |selectors: &[String]| -> Vec<String> {
+            let mut canonical: Vec<String> = selectors
+                .iter()
+                .map(|s| {
+                    if self.matcher.query_normalized {
+                        normalize_query_tokens(s).unwrap_or_else(|_| s.clone())
+                    } else {
+                        s.clone()
+                    }
+                })
+                .collect();
+            canonical.sort();
+            canonical
+        };
+        canonical(&self.matcher.matches) == canonical(selectors)
+    }
+
+    /// Mutable access to all responses of the route.
+    fn responses_mut(&mut self) -> impl Iterator<Item = &mut Respond> {
+        let steps = self
+            .sequence
+            .iter_mut()
+            .flat_map(|s| s.responses.iter_mut().map(|step| &mut step.respond));
+        self.respond.iter_mut().chain(steps)
+    }
+
+    /// All responses the route can answer with.
+    fn responses(&self) -> impl Iterator<Item = &Respond> {
+        let steps = self.sequence.iter().flat_map(|s| s.responses.iter().map(|step| &step.respond));
+        self.respond.iter().chain(steps)
+    }
+
+    /// Response for the route's call with the given zero-based number.
+    fn response_at(&self, call: u64) -> Option<&Respond> {
+        let Some(sequence) = &self.sequence else {
+            return self.respond.as_ref();
+        };
+        let times = |step: &SequenceStep| u64::from(step.times.unwrap_or(1).max(1));
+        let total: u64 = sequence.responses.iter().map(times).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut position = match sequence.mode {
+            SequenceMode::Last => call.min(total - 1),
+            SequenceMode::Cycle => call % total,
+        };
+        for step in &sequence.responses {
+            if position < times(step) {
+                return Some(&step.respond);
+            }
+            position -= times(step);
+        }
+        None
+    }
+}
+
+/// Mutable fixture state: route call counts and current scenario states.
+///
+/// Kept outside the [`FixtureBook`] so the book stays immutable and can be
+/// shared; [`FixtureState::reset`] restores the initial state.
+#[derive(Debug, Default)]
+pub struct FixtureState {
+    inner: Mutex<FixtureStateInner>,
+}
+
+#[derive(Debug, Default)]
+struct FixtureStateInner {
+    /// Number of matched requests per route index
+    calls: FnvHashMap<usize, u64>,
+    /// Current state per scenario name, `SCENARIO_STARTED` if absent
+    scenarios: FnvHashMap<String, String>,
+}
+
+impl FixtureState {
+    /// Create the initial state.
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `FixtureState` with no calls and all scenarios started.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget all calls and move every scenario back to its initial state.
+    pub fn reset(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.calls.clear();
+        inner.scenarios.clear();
+    }
+
+    /// Current state of a scenario.
+    ///
+    /// # Parameters
+    ///
+    /// - `scenario` - Scenario name
+    ///
+    /// # Returns
+    ///
+    /// Returns the scenario state, [`SCENARIO_STARTED`] if it never changed.
+    pub fn scenario_state(&self, scenario: &str) -> String {
+        let inner = self.inner.lock().unwrap();
+        inner.scenarios.get(scenario).map_or(SCENARIO_STARTED, String::as_str).to_string()
+    }
+}
+
+/// Line numbers (1-based) of the items of a block style top-level `routes` list.
+fn route_lines(txt: &str) -> Vec<usize> {
+    let mut lines = Vec::new();
+    let mut in_routes = false;
+    let mut item_indent = None;
+    for (number, line) in txt.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        let is_item = trimmed == "-" || trimmed.starts_with("- ");
+        if indent == 0 && !is_item {
+            // A top-level key starts a new section
+            in_routes = trimmed.starts_with("routes:");
+            continue;
+        }
+        if in_routes && is_item && *item_indent.get_or_insert(indent) == indent {
+            lines.push(number + 1);
+        }
+    }
+    lines
+}
+
+/// Fixture files of a directory in file name order.
+fn fixture_files(dir: &Path) -> Result<Vec<PathBuf>, FixtureError> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let extension = path.extension().and_then(|e| e.to_str());
+        if path.is_file() && extension.is_some_and(|e| FIXTURE_EXTENSIONS.contains(&e)) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[allow(clippy::unnested_or_patterns)]
+fn param_equal(expect: &str, got: &str, now: Option<time::OffsetDateTime>) -> bool {
+    match (resolve_relative(expect, now), resolve_relative(got, now)) {
+        // All value comparisons - resolved parameters can compare to each other and raw to raw
+        (ResolvedParam::Absolute(e), ResolvedParam::Absolute(g))
+        | (ResolvedParam::Relative(e), ResolvedParam::Relative(g))
+        | (ResolvedParam::Relative(e), ResolvedParam::Absolute(g))
+        | (ResolvedParam::Absolute(e), ResolvedParam::Relative(g))
+        | (ResolvedParam::Raw(e), ResolvedParam::Raw(g)) => e == g,
+        // Raw vs resolved types don't match
+        (ResolvedParam::Raw(_), ResolvedParam::Absolute(_) | ResolvedParam::Relative(_))
+        | (ResolvedParam::Absolute(_) | ResolvedParam::Relative(_), ResolvedParam::Raw(_)) => false,
+    }
+}
+
+/// Compare two queries by their normalized token form.
+fn query_equal_normalized(expect: &str, got: &str) -> bool {
+    match (normalize_query_tokens(expect), normalize_query_tokens(got)) {
+        (Ok(e), Ok(g)) => e == g,
+        (Err(e), _) => {
+            tracing::warn!("cannot normalize fixture query {:?}: {}", expect, e);
+            false
+        }
+        (_, Err(_)) => false,
+    }
+}
+
+/// Normalize the tokens of a `PromQL` query for comparison.
+///
+/// Tokens are separated by single spaces, comments are dropped, string
+/// literals are re-quoted with double quotes, the matchers inside every
+/// `{...}` are sorted and empty `{}` after a metric name are removed, so
+/// `rate(x{b="2", a='1'}[5m] )` and `rate(x{a="1",b="2"}[5m])` are equal.
+///
+/// This is a token rewrite, not a `PromQL` parse: queries that only differ
+/// in spacing, comments, quoting or label matcher order compare equal, but
+/// equivalent queries written differently do not, e.g. `sum by (job) (x)`
+/// and `sum(x) by (job)`, `x[5m]` and `x[300s]`, `up` and
+/// `{__name__="up"}`, or redundant parentheses. Invalid queries with
+/// balanced braces and terminated strings are normalized too.
+///
+/// # Parameters
+///
+/// - `query` - `PromQL` query string
+///
+/// # Returns
+///
+/// Returns the canonical query string.
+///
+/// # Errors
+///
+/// Returns a description of the problem for unterminated strings or
+/// unbalanced braces.
+pub fn normalize_query_tokens(query: &str) -> Result<String, String> {
+    let tokens = tokenize_query(query)?;
+    let mut out: Vec<String> = Vec::with_capacity(tokens.len());
+    let mut iter = tokens.into_iter();
+    while let Some(token) = iter.next() {
+        if token != "{" {
+            if token == "}" {
+                return Err("unexpected '}'".to_string());
+            }
+            out.push(token);
+            continue;
+        }
+
+        // Collect the matchers of this selector, split at commas
+        let mut matchers: Vec<Vec<String>> = vec![vec![]];
+        loop {
+            match iter.next().as_deref() {
+                None => return Err("unclosed '{'".to_string()),
+                Some("}") => break,
+                Some("{") => return Err("unexpected '{' inside label matchers".to_string()),
+                Some(",") => matchers.push(vec![]),
+                Some(t) => matchers.last_mut().expect("at least one matcher").push(t.to_string()),
+            }
+        }
+        matchers.retain(|m| !m.is_empty());
+        matchers.sort();
+
+        let follows_name = out.last().is_some_and(|t| is_word(t));
+        if matchers.is_empty() && follows_name {
+            continue;
+        }
+        let inner: Vec<String> = matchers.into_iter().map(|m| m.join(" ")).collect();
+        if inner.is_empty() {
+            out.push("{ }".to_string());
+        } else {
+            out.push(format!("{{ {} }}", inner.join(" , ")));
+        }
+    }
+    Ok(out.join(" "))
+}
+
+/// Whether a token is an identifier, keyword, number or duration.
+fn is_word(token: &str) -> bool {
+    token.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | ':' | '.'))
+}
+
+/// Split a `PromQL` query into tokens, canonicalizing string literals.
+fn tokenize_query(query: &str) -> Result<Vec<String>, String> {
+    const TWO_CHAR_OPERATORS: [&str; 6] = ["=~", "!~", "!=", "==", ">=", "<="];
+
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '#' => {
+                // Comment until the end of the line
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '"' | '\'' | '`' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(format!("unterminated string starting with {c}")),
+                        Some(q) if q == c => break,
+                        Some('\\') if c != '`' => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(e @ ('\\' | '"' | '\'')) => value.push(e),
+                            Some(e) => {
+                                value.push('\\');
+                                value.push(e);
+                            }
+                            None => return Err(format!("unterminated string starting with {c}")),
+                        },
+                        Some(v) => value.push(v),
+                    }
+                }
+                tokens.push(format!("{value:?}"));
+            }
+            c if c.is_ascii_alphanumeric() || matches!(c, '_' | ':' | '.') => {
+                let mut word = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_ascii_alphanumeric() || matches!(next, '_' | ':' | '.')) {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(word);
+            }
+            c => {
+                let pair = chars.peek().map(|next| format!("{c}{next}"));
+                match pair.filter(|p| TWO_CHAR_OPERATORS.contains(&p.as_str())) {
+                    Some(op) => {
+                        chars.next();
+                        tokens.push(op);
+                    }
+                    None => tokens.push(c.to_string()),
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Query parameters in unified form.
+#[derive(Debug, Default)]
+pub struct QueryParams {
+    /// `PromQL` query, empty for metadata requests
+    pub query: String,
+    /// Start time, resolved if relative
+    pub start: Option<String>,
+    /// End time, resolved if relative
+    pub end: Option<String>,
+    /// Step interval of range queries
+    pub step: Option<String>,
+    /// Series selectors of `match[]` parameters
+    pub matches: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+    use tempfile::NamedTempFile;
+    use time::macros::datetime;
+
+    use super::*;
+
+    /// Test basic FixtureBook creation and default values.
+    #[test]
+    fn test_fixture_book_defaults() {
+        let book = FixtureBook::default();
+        assert_eq!(book.version, None);
+        assert_eq!(book.defaults, None);
+        assert!(book.routes.is_empty());
+
+        // Test with defaults
+        let book = FixtureBook {
+            version: Some(1),
+            defaults: Some(Defaults {
+                status: Some("success".to_string()),
+                clock_anchor: Some("now".to_string()),
+            }),
+            routes: vec![],
+            ..FixtureBook::default()
+        };
+        assert_eq!(book.version, Some(1));
+        assert!(book.defaults.is_some());
+    }
+
+    /// Test loading FixtureBook from YAML with various configurations.
+    #[test]
+    fn test_load_from_yaml() {
+        // Test minimal YAML
+        let yaml_content = r#"
+version: 1
+routes: []
+"#;
+        let temp_file = NamedTempFile::new().expect("create temp file");
+        fs::write(&temp_file, yaml_content).expect("write temp file");
+
+        let book = FixtureBook::load_from_path(&temp_file).expect("load fixture book");
+        assert_eq!(book.version, Some(1));
+        assert!(book.routes.is_empty());
+        // Should have default status
+        assert_eq!(book.defaults.as_ref().unwrap().status.as_ref().unwrap(), "success");
+
+        // Test with explicit defaults
+        let yaml_content = r#"
+version: 1
+defaults:
+  status: "error"
+  clock_anchor: "2022-01-01T00:00:00Z"
+routes:
+  - match:
+      path: "/api/v1/query"
+      query: "up"
+    respond:
+      data: {"resultType": "vector", "result": []}
+"#;
+        let temp_file = NamedTempFile::new().expect("create temp file");
+        fs::write(&temp_file, yaml_content).expect("write temp file");
+
+        let book = FixtureBook::load_from_path(&temp_file).expect("load fixture book");
+        assert_eq!(book.version, Some(1));
+        assert_eq!(book.defaults.as_ref().unwrap().status.as_ref().unwrap(), "error");
+        assert_eq!(book.routes.len(), 1);
+        assert_eq!(book.routes[0].matcher.path, "/api/v1/query");
+        assert_eq!(book.routes[0].matcher.query.as_ref().unwrap(), "up");
+        assert_eq!(
+            book.clock_anchor().expect("valid anchor"),
+            Some(ClockAnchor::At(datetime!(2022-01-01 00:00:00 UTC)))
+        );
+    }
+
+    /// Test an unparseable clock anchor fails loading.
+    #[test]
+    fn test_load_invalid_clock_anchor() {
+        let temp_file = NamedTempFile::new().expect("create temp file");
+        fs::write(&temp_file, "defaults:\n  clock_anchor: yesterday\nroutes: []\n")
+            .expect("write temp file");
+
+        let err = FixtureBook::load_from_path(&temp_file).expect_err("invalid anchor");
+        let FixtureError::InFile { path, source } = err else { panic!("expected InFile") };
+        assert_eq!(path, temp_file.path());
+        assert!(matches!(*source, FixtureError::InvalidClockAnchor(_)));
+    }
+
+    /// Test invalid YAML handling.
+    #[test]
+    fn test_load_invalid_yaml() {
+        let yaml_content = "invalid: yaml: content: [";
+        let temp_file = NamedTempFile::new().expect("create temp file");
+        fs::write(&temp_file, yaml_content).expect("write temp file");
+
+        let result = FixtureBook::load_from_path(&temp_file);
+        assert!(result.is_err());
+        let FixtureError::InFile { source, .. } = result.unwrap_err() else {
+            panic!("expected InFile")
+        };
+        assert!(matches!(*source, FixtureError::Yaml(_)));
+    }
+
+    /// Test file not found handling.
+    #[test]
+    fn test_load_nonexistent_file() {
+        let result = FixtureBook::load_from_path("/nonexistent/file.yaml");
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        assert!(err.to_string().starts_with("/nonexistent/file.yaml: io: "), "{err}");
+        let FixtureError::InFile { source, .. } = err else { panic!("expected InFile") };
+        assert!(matches!(*source, FixtureError::Io(_)));
+    }
+
+    fn queries(book: &FixtureBook) -> Vec<&str> {
+        book.routes.iter().filter_map(|r| r.matcher.query.as_deref()).collect()
+    }
+
+    fn statuses(book: &FixtureBook) -> Vec<&str> {
+        book.routes.iter().map(|r| book.effective_status(r.respond.as_ref().unwrap())).collect()
+    }
+
+    /// Test loading a directory merges fixture files in name order with per-file defaults.
+    #[test]
+    fn test_load_directory() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let route = |query: &str| {
+            format!(
+                "  - match: {{path: /api/v1/query, query: {query}}}\n    respond: {{data: 1}}\n"
+            )
+        };
+        fs::write(
+            dir.path().join("b.yaml"),
+            format!("defaults: {{status: error, clock_anchor: now}}\nroutes:\n{}", route("b")),
+        )
+        .expect("write b");
+        fs::write(dir.path().join("a.yml"), format!("routes:\n{}{}", route("a1"), route("a2")))
+            .expect("write a");
+        fs::write(
+            dir.path().join("c.json"),
+            r#"{"routes": [{"match": {"path": "/api/v1/query", "query": "c"}, "respond": {"data": 1}}]}"#,
+        )
+        .expect("write c");
+        fs::write(dir.path().join("README.md"), "not a fixture").expect("write readme");
+
+        let book = FixtureBook::load_from_path(dir.path()).expect("load fixture directory");
+        assert_eq!(queries(&book), vec!["a1", "a2", "b", "c"]);
+        assert_eq!(statuses(&book), vec!["success", "success", "error", "success"]);
+        assert_eq!(book.defaults.as_ref().and_then(|d| d.clock_anchor.as_deref()), Some("now"));
+        assert_eq!(book.sources.len(), 4);
+
+        // Errors name the file and the route within it
+        fs::write(
+            dir.path().join("d.yaml"),
+            format!("routes:\n{}  - match: {{path: x}}\n", route("d")),
+        )
+        .expect("write d");
+        let err = FixtureBook::load_from_path(dir.path()).expect_err("invalid route");
+        let expected = format!(
+            "{}: route 1: either respond or sequence is required",
+            dir.path().join("d.yaml").display()
+        );
+        assert_eq!(err.to_string(), expected);
+    }
+
+    /// Test includes are resolved relative to the including file, after its own routes.
+    #[test]
+    fn test_load_includes() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let route = |query: &str| {
+            format!(
+                "  - match: {{path: /api/v1/query, query: {query}}}\n    respond: {{data: 1}}\n"
+            )
+        };
+        fs::create_dir(dir.path().join("team")).expect("create dir");
+        fs::write(
+            dir.path().join("root.yaml"),
+            format!("include: [shared.yaml, team]\nroutes:\n{}", route("root")),
+        )
+        .expect("write root");
+        fs::write(
+            dir.path().join("shared.yaml"),
+            format!("defaults: {{status: warning}}\nroutes:\n{}", route("shared")),
+        )
+        .expect("write shared");
+        fs::write(dir.path().join("team/x.yaml"), format!("routes:\n{}", route("team")))
+            .expect("write team");
+
+        let book =
+            FixtureBook::load_from_path(dir.path().join("root.yaml")).expect("load fixtures");
+        assert_eq!(queries(&book), vec!["root", "shared", "team"]);
+        assert_eq!(statuses(&book), vec!["success", "warning", "success"]);
+        assert!(book.include.is_empty());
+
+        // Errors in included files name the whole include chain
+        fs::write(dir.path().join("team/x.yaml"), "routes: [").expect("write team");
+        let err =
+            FixtureBook::load_from_path(dir.path().join("root.yaml")).expect_err("broken include");
+        let FixtureError::InFile { path, source } = err else { panic!("expected InFile") };
+        assert_eq!(path, dir.path().join("root.yaml"));
+        let FixtureError::InFile { path, source } = *source else { panic!("expected InFile") };
+        assert_eq!(path, dir.path().join("team").join("x.yaml"));
+        assert!(matches!(*source, FixtureError::Yaml(_)));
+
+        // Include cycles are detected
+        fs::write(dir.path().join("team/x.yaml"), "include: [../root.yaml]\n").expect("write team");
+        let err = FixtureBook::load_from_path(dir.path().join("root.yaml")).expect_err("cycle");
+        assert!(err.to_string().contains("include cycle"), "{err}");
+    }
+
+    /// Test finding matches for simple queries.
+    #[test]
+    fn test_find_match_simple_query() {
+        let book = FixtureBook {
+            version: Some(1),
+            defaults: Some(Defaults { status: Some("success".to_string()), clock_anchor: None }),
+            routes: vec![
+                Route {
+                    matcher: Matcher {
+                        path: "/api/v1/query".to_string(),
+                        query: Some("up".to_string()),
+                        start: None,
+                        end: None,
+                        step: None,
+                        ..Matcher::default()
+                    },
+                    respond: Some(Respond {
+                        status: None,
+                        data: json!({"resultType": "vector", "result": []}),
+                        warnings: None,
+                        error_type: None,
+                        error: None,
+                        ..Respond::default()
+                    }),
+                    ..Route::default()
+                },
+                Route {
+                    matcher: Matcher {
+                        path: "/api/v1/query".to_string(),
+                        query: Some("cpu_usage".to_string()),
+                        start: None,
+                        end: None,
+                        step: None,
+                        ..Matcher::default()
+                    },
+                    respond: Some(Respond {
+                        status: Some("error".to_string()),
+                        data: json!({}),
+                        warnings: None,
+                        error_type: Some("execution".to_string()),
+                        error: Some("query failed".to_string()),
+                        ..Respond::default()
+                    }),
+                    ..Route::default()
+                },
+            ],
+            ..FixtureBook::default()
+        };
+
+        // Test matching query
+        let params = QueryParams {
+            query: "up".to_string(),
+            start: None,
+            end: None,
+            step: None,
+            matches: vec![],
+        };
+        let result = book.find_match("/api/v1/query", &params, None);
+        assert!(result.is_some());
+        assert_eq!(result.unwrap().data["resultType"], "vector");
+
+        // Test different query
+        let params = QueryParams {
+            query: "cpu_usage".to_string(),
+            start: None,
+            end: None,
+            step: None,
+            matches: vec![],
+        };
+        let result = book.find_match("/api/v1/query", &params, None);
+        assert!(result.is_some());
+        assert_eq!(result.unwrap().status.as_ref().unwrap(), "error");
+
+        // Test non-matching query
+        let params = QueryParams {
+            query: "memory_usage".to_string(),
+            start: None,
+            end: None,
+            step: None,
+            matches: vec![],
+        };
+        let result = book.find_match("/api/v1/query", &params, None);
+        assert!(result.is_none());
+
+        // Test wrong path
+        let params = QueryParams {
+            query: "up".to_string(),
+            start: None,
+            end: None,
+            step: None,
+            matches: vec![],
+        };
+        let result = book.find_match("/api/v1/query_range", &params, None);
+        assert!(result.is_none());
+    }
+
+    /// Test finding matches for query_range requests.
+    #[test]
+    fn test_find_match_query_range() {
+        let book = FixtureBook {
+            version: Some(1),
+            defaults: None,
+            routes: vec![Route {
+                matcher: Matcher {
+                    path: "/api/v1/query_range".to_string(),
+                    query: Some("up".to_string()),
+                    start: Some("now-1h".to_string()),
+                    end: Some("now".to_string()),
+                    step: Some("5m".to_string()),
+                    ..Matcher::default()
+                },
+                respond: Some(Respond {
+                    status: None,
+                    data: json!({"resultType": "matrix", "result": []}),
+                    warnings: None,
+                    error_type: None,
+                    error: None,
+                    ..Respond::default()
+                }),
+                ..Route::default()
+            }],
+            ..FixtureBook::default()
+        };
+
+        let fixed_time = datetime!(2022-01-01 12:00:00 UTC);
+
+        // Test matching query_range
+        let params = QueryParams {
+            query: "up".to_string(),
+            start: Some("now-1h".to_string()),
+            end: Some("now".to_string()),
+            step: Some("5m".to_string()),
+            matches: vec![],
+        };
+        let result = book.find_match("/api/v1/query_range", &params, Some(fixed_time));
+        assert!(result.is_some());
+        assert_eq!(result.unwrap().data["resultType"], "matrix");
+
+        // Test missing required parameters for query_range
+        let params = QueryParams {
+            query: "up".to_string(),
+            start: None,
+            end: Some("now".to_string()),
+            step: Some("5m".to_string()),
+            matches: vec![],
+        };
+        let result = book.find_match("/api/v1/query_range", &params, Some(fixed_time));
+        assert!(result.is_none());
+
+        // Test different step value
+        let params = QueryParams {
+            query: "up".to_string(),
+            start: Some("now-1h".to_string()),
+            end: Some("now".to_string()),
+            step: Some("1m".to_string()),
+            matches: vec![],
+        };
+        let result = book.find_match("/api/v1/query_range", &params, Some(fixed_time));
+        assert!(result.is_none());
+    }
+
+    fn query_book(matcher: Matcher) -> FixtureBook {
+        FixtureBook {
+            routes: vec![Route {
+                matcher,
+                respond: Some(Respond {
+                    status: None,
+                    data: json!({"resultType": "vector", "result": []}),
+                    warnings: None,
+                    error_type: None,
+                    error: None,
+                    ..Respond::default()
+                }),
+                ..Route::default()
+            }],
+            ..FixtureBook::default()
+        }
+    }
+
+    fn matches(book: &FixtureBook, query: &str) -> bool {
+        let params = QueryParams {
+            query: query.to_string(),
+            start: None,
+            end: None,
+            step: None,
+            matches: vec![],
+        };
+        book.find_match("/api/v1/query", &params, None).is_some()
+    }
+
+    /// Test query_regex matches the whole query.
+    #[test]
+    fn test_find_match_query_regex() {
+        let book = query_book(Matcher {
+            path: "/api/v1/query".to_string(),
+            query_regex: Some(r"rate\(http_requests_total\{.*\}\[\d+m\]\)".to_string()),
+            ..Matcher::default()
+        });
+        assert!(matches(&book, r#"rate(http_requests_total{job="api"}[5m])"#));
+        assert!(matches(&book, "rate(http_requests_total{}[15m])"));
+        assert!(!matches(&book, r#"sum(rate(http_requests_total{job="api"}[5m]))"#));
+        assert!(!matches(&book, "rate(http_requests_total{}[5s])"));
+
+        // Invalid patterns never match
+        let book = query_book(Matcher {
+            path: "/api/v1/query".to_string(),
+            query_regex: Some("rate(".to_string()),
+            ..Matcher::default()
+        });
+        assert!(!matches(&book, "rate("));
+
+        // Loading compiles patterns up front and rejects invalid ones
+        let temp_file = NamedTempFile::new().expect("create temp file");
+        let route = |pattern: &str| {
+            format!("routes:\n  - match: {{path: /api/v1/query, query_regex: '{pattern}'}}\n    respond: {{data: 1}}\n")
+        };
+        fs::write(&temp_file, route("up|down")).expect("write temp file");
+        let book = FixtureBook::load_from_path(&temp_file).expect("load fixture book");
+        assert!(book.routes[0].matcher.compiled_query_regex.0.get().is_some());
+        assert!(matches(&book, "down"));
+        assert!(!matches(&book, "upx"));
+        for pattern in ["rate(", "a)|(b"] {
+            fs::write(&temp_file, route(pattern)).expect("write temp file");
+            let err = FixtureBook::load_from_path(&temp_file).expect_err(pattern);
+            let prefix = format!("{}: route 0: invalid query_regex:", temp_file.path().display());
+            assert!(err.to_string().starts_with(&prefix), "{err}");
+        }
+    }
+
+    /// Test metadata routes match match[] selectors as a set and optional start/end.
+    #[test]
+    fn test_find_match_metadata() {
+        let book = query_book(Matcher {
+            path: "/api/v1/series".to_string(),
+            matches: vec!["up".to_string(), r#"{job="api"}"#.to_string()],
+            start: Some("now-1h".to_string()),
+            ..Matcher::default()
+        });
+        let now = datetime!(2022-01-01 12:00:00 UTC);
+        let params = |matches: &[&str], start: Option<&str>| QueryParams {
+            start: start.map(str::to_string),
+            matches: matches.iter().map(|m| m.to_string()).collect(),
+            ..QueryParams::default()
+        };
+        let series = |params: &QueryParams| book.find_match("/api/v1/series", params, Some(now));
+
+        assert!(series(&params(&["up", r#"{job="api"}"#], Some("1641034800"))).is_some());
+        assert!(series(&params(&[r#"{job="api"}"#, "up"], Some("1641034800"))).is_some());
+        assert!(series(&params(&["up"], Some("1641034800"))).is_none());
+        assert!(series(&params(&["up", r#"{job="api"}"#], None)).is_none());
+        assert!(series(&params(&["up", r#"{job="api"}"#], Some("1641038400"))).is_none());
+
+        let book = query_book(Matcher {
+            path: "/api/v1/label/job/values".to_string(),
+            ..Matcher::default()
+        });
+        let any = QueryParams::default();
+        assert!(book.find_match("/api/v1/label/job/values", &any, None).is_some());
+        assert!(book.find_match("/api/v1/label/instance/values", &any, None).is_none());
+    }
+
+    /// Test query_normalized ignores whitespace, quoting and matcher order.
+    #[test]
+    fn test_find_match_query_normalized() {
+        let book = query_book(Matcher {
+            path: "/api/v1/query".to_string(),
+            query: Some(r#"rate(x{job="api", env="prod"}[5m])"#.to_string()),
+            query_normalized: true,
+            ..Matcher::default()
+        });
+        assert!(matches(&book, r#"rate(x{job="api",env="prod"}[5m] )"#));
+        assert!(matches(&book, "rate(x{env='prod',job=\"api\",}[5m])"));
+        assert!(matches(&book, "rate(\n  x{env=\"prod\", job=\"api\"}[5m]  # comment\n)"));
+        assert!(!matches(&book, r#"rate(x{job="api",env="dev"}[5m])"#));
+        assert!(!matches(&book, r#"rate(x{job="api"}[5m])"#));
+        assert!(!matches(&book, r#"rate(x{job="api"}"#));
+
+        // Without normalization the query must be identical
+        let book = query_book(Matcher {
+            path: "/api/v1/query".to_string(),
+            query: Some(r#"rate(x{job="api"}[5m])"#.to_string()),
+            ..Matcher::default()
+        });
+        assert!(!matches(&book, r#"rate(x{job="api"}[5m] )"#));
+    }
+
+    /// Test canonical query forms.
+    #[test]
+    fn test_normalize_query_tokens() {
+        assert_eq!(normalize_query_tokens("up{}").as_deref(), Ok("up"));
+        assert_eq!(normalize_query_tokens("{job='a'}").as_deref(), Ok(r#"{ job = "a" }"#));
+        assert_eq!(
+            normalize_query_tokens(r#"sum by (job) (up{b!~"x",a=~`.*`})"#).as_deref(),
+            Ok(r#"sum by ( job ) ( up { a =~ ".*" , b !~ "x" } )"#)
+        );
+        assert_eq!(normalize_query_tokens("a or b").ok(), normalize_query_tokens("a  or\tb").ok(),);
+        assert_ne!(normalize_query_tokens("a or b").ok(), normalize_query_tokens("aorb").ok());
+        assert!(normalize_query_tokens(r#"up{job="api"#).is_err());
+        assert!(normalize_query_tokens("up{job=\"a\"").is_err());
+        assert!(normalize_query_tokens("up}").is_err());
+    }
+
+    fn respond_value(value: u64) -> Respond {
+        Respond {
+            data: json!({"resultType": "scalar", "result": [0, value.to_string()]}),
+            ..Respond::default()
+        }
+    }
+
+    fn value_of(respond: Option<&Respond>) -> Option<String> {
+        respond.and_then(|r| r.data["result"][1].as_str().map(str::to_string))
+    }
+
+    fn sequence_book(mode: SequenceMode) -> FixtureBook {
+        let step = |value, times| SequenceStep { respond: respond_value(value), times };
+        query_book_route(Route {
+            matcher: Matcher { path: "/api/v1/query".to_string(), ..Matcher::default() },
+            sequence: Some(Sequence { mode, responses: vec![step(1, Some(2)), step(2, None)] }),
+            ..Route::default()
+        })
+    }
+
+    fn query_book_route(route: Route) -> FixtureBook {
+        FixtureBook { routes: vec![route], ..FixtureBook::default() }
+    }
+
+    fn next_values(book: &FixtureBook, state: &FixtureState, calls: usize) -> Vec<Option<String>> {
+        let params = QueryParams {
+            query: "up".to_string(),
+            start: None,
+            end: None,
+            step: None,
+            matches: vec![],
+        };
+        (0..calls)
+            .map(|_| value_of(book.next_response("/api/v1/query", &params, None, state)))
+            .collect()
+    }
+
+    /// Test sequences advance per call, honoring times and the mode.
+    #[test]
+    fn test_sequence_modes() {
+        let v = |value: &str| Some(value.to_string());
+
+        let book = sequence_book(SequenceMode::Last);
+        let state = FixtureState::new();
+        assert_eq!(next_values(&book, &state, 5), vec![v("1"), v("1"), v("2"), v("2"), v("2")]);
+
+        let book = sequence_book(SequenceMode::Cycle);
+        let state = FixtureState::new();
+        assert_eq!(next_values(&book, &state, 5), vec![v("1"), v("1"), v("2"), v("1"), v("1")]);
+
+        // Stateless matching and reset both start over
+        let params = QueryParams {
+            query: "up".to_string(),
+            start: None,
+            end: None,
+            step: None,
+            matches: vec![],
+        };
+        assert_eq!(value_of(book.find_match("/api/v1/query", &params, None)), v("1"));
+        state.reset();
+        assert_eq!(next_values(&book, &state, 1), vec![v("1")]);
+    }
+
+    /// Test scenario routes only match in their required state and move it on.
+    #[test]
+    fn test_scenario_transitions() {
+        let route = |required: Option<&str>, new: Option<&str>, value| Route {
+            matcher: Matcher { path: "/api/v1/query".to_string(), ..Matcher::default() },
+            respond: Some(respond_value(value)),
+            scenario: Some("alert".to_string()),
+            required_state: required.map(str::to_string),
+            new_state: new.map(str::to_string),
+            ..Route::default()
+        };
+        let book = FixtureBook {
+            routes: vec![
+                route(Some(SCENARIO_STARTED), Some("pending"), 0),
+                route(Some("pending"), Some("firing"), 1),
+                route(Some("firing"), None, 2),
+            ],
+            ..FixtureBook::default()
+        };
+        let v = |value: &str| Some(value.to_string());
+
+        let state = FixtureState::new();
+        assert_eq!(state.scenario_state("alert"), SCENARIO_STARTED);
+        assert_eq!(next_values(&book, &state, 4), vec![v("0"), v("1"), v("2"), v("2")]);
+        assert_eq!(state.scenario_state("alert"), "firing");
+
+        state.reset();
+        assert_eq!(state.scenario_state("alert"), SCENARIO_STARTED);
+        assert_eq!(next_values(&book, &state, 1), vec![v("0")]);
+    }
+
+    /// Test sequences and scenarios load from YAML and bad routes are rejected.
+    #[test]
+    fn test_load_sequences_and_scenarios() {
+        let yaml_content = r#"
+routes:
+  - match:
+      path: "/api/v1/query"
+      query: "up"
+    scenario: outage
+    required_state: Started
+    new_state: down
+    sequence:
+      mode: cycle
+      responses:
+        - data: {"resultType": "vector", "result": []}
+          times: 2
+        - status: error
+          errorType: unavailable
+          error: down
+          data: null
+"#;
+        let temp_file = NamedTempFile::new().expect("create temp file");
+        fs::write(&temp_file, yaml_content).expect("write temp file");
+        let book = FixtureBook::load_from_path(&temp_file).expect("load fixture book");
+        let sequence = book.routes[0].sequence.as_ref().expect("sequence");
+        assert_eq!(sequence.mode, SequenceMode::Cycle);
+        assert_eq!(sequence.responses[0].times, Some(2));
+        assert_eq!(sequence.responses[1].respond.error_type.as_deref(), Some("unavailable"));
+        assert_eq!(book.routes[0].new_state.as_deref(), Some("down"));
+
+        for (routes, reason) in [
+            ("- match: {path: /api/v1/query}", "either respond or sequence is required"),
+            (
+                "- match: {path: /api/v1/query}\n  respond: {data: 1}\n  sequence: {responses: [{data: 1}]}",
+                "respond and sequence are mutually exclusive",
+            ),
+            ("- match: {path: /api/v1/query}\n  sequence: {responses: []}", "sequence has no responses"),
+            (
+                "- match: {path: /api/v1/query}\n  sequence: {responses: [{data: 1, times: 0}]}",
+                "sequence times must be at least 1",
+            ),
+            (
+                "- match: {path: /api/v1/query}\n  respond: {data: 1}\n  new_state: done",
+                "required_state and new_state need a scenario",
+            ),
+        ] {
+            fs::write(&temp_file, format!("routes:\n{routes}\n")).expect("write temp file");
+            let err = FixtureBook::load_from_path(&temp_file).expect_err(reason);
+            let file = temp_file.path().display();
+            assert_eq!(err.to_string(), format!("{file}: route 0: {reason}"));
+        }
+    }
+
+    /// Test HTTP status codes follow Prometheus error types unless set explicitly.
+    #[test]
+    fn test_status_code() {
+        let error = |error_type: &str| Respond {
+            error_type: Some(error_type.to_string()),
+            ..Respond::default()
+        };
+        assert_eq!(Respond::default().status_code("success"), 200);
+        assert_eq!(error("bad_data").status_code("success"), 200);
+        assert_eq!(error("bad_data").status_code("error"), 400);
+        assert_eq!(error("execution").status_code("error"), 422);
+        assert_eq!(error("timeout").status_code("error"), 503);
+        assert_eq!(error("something_else").status_code("error"), 500);
+        assert_eq!(Respond::default().status_code("error"), 500);
+
+        let explicit = Respond { http_status: Some(429), ..error("unavailable") };
+        assert_eq!(explicit.status_code("error"), 429);
+    }
+
+    /// Test delays parse as fixed durations or ranges and stay within the range.
+    #[test]
+    fn test_delay() {
+        let yaml_content = r#"
+routes:
+  - match: {path: /api/v1/query_range}
+    respond:
+      data: null
+      delay: 250ms
+      failure_rate: 0.5
+      http_status: 202
+      headers: {X-Mock: "yes"}
+  - match: {path: /api/v1/query}
+    respond:
+      data: null
+      delay: {min: 10ms, max: 1s}
+"#;
+        let temp_file = NamedTempFile::new().expect("create temp file");
+        fs::write(&temp_file, yaml_content).expect("write temp file");
+        let book = FixtureBook::load_from_path(&temp_file).expect("load fixture book");
+
+        let slow = book.routes[0].respond.as_ref().expect("respond");
+        assert_eq!(slow.delay, Some(Delay::Fixed(Duration::from_millis(250))));
+        assert_eq!(slow.failure_rate, Some(0.5));
+        assert_eq!(slow.http_status, Some(202));
+        assert_eq!(
+            slow.headers.as_ref().and_then(|h| h.get("X-Mock")).map(String::as_str),
+            Some("yes")
+        );
+
+        let delay = book.routes[1].respond.as_ref().and_then(|r| r.delay).expect("delay");
+        assert_eq!(
+            delay,
+            Delay::Uniform { min: Duration::from_millis(10), max: Duration::from_secs(1) }
+        );
+        for _ in 0..100 {
+            let sample = delay.sample();
+            assert!((Duration::from_millis(10)..=Duration::from_secs(1)).contains(&sample));
+        }
+
+        for (respond, reason) in [
+            ("{data: 1, failure_rate: 1.5}", "failure_rate must be between 0.0 and 1.0"),
+            ("{data: 1, http_status: 99}", "http_status must be between 100 and 599"),
+            ("{data: 1, delay: {min: 2s, max: 1s}}", "delay min must not exceed max"),
+        ] {
+            let yaml = format!("routes:\n- match: {{path: /api/v1/query}}\n  respond: {respond}\n");
+            fs::write(&temp_file, yaml).expect("write temp file");
+            let err = FixtureBook::load_from_path(&temp_file).expect_err(reason);
+            let file = temp_file.path().display();
+            assert_eq!(err.to_string(), format!("{file}: route 0: {reason}"));
+        }
+    }
+
+    /// Test templates are only rendered in responses with `template: true`.
+    #[test]
+    fn test_template_opt_in() {
+        let temp_file = NamedTempFile::new().expect("create temp file");
+        let data = r#"{resultType: string, result: [0, "{{ now"]}"#;
+        let yaml =
+            format!("routes:\n- match: {{path: /api/v1/query}}\n  respond: {{data: {data}}}\n");
+        fs::write(&temp_file, yaml).expect("write temp file");
+        let book = FixtureBook::load_from_path(&temp_file).expect("literal braces load");
+        let respond = book.routes[0].respond.as_ref().expect("respond");
+        let ctx = TemplateContext { now: 60.0, ..TemplateContext::default() };
+        assert_eq!(
+            respond.render_data(&ctx).expect("literal data"),
+            json!({"resultType": "string", "result": [0, "{{ now"]})
+        );
+
+        let templated = Respond {
+            data: json!({"resultType": "scalar", "result": ["{{ now }}", "1"]}),
+            template: true,
+            ..Respond::default()
+        };
+        assert_eq!(
+            templated.render_data(&ctx).expect("rendered data"),
+            json!({"resultType": "scalar", "result": [60, "1"]})
+        );
+
+        let yaml = format!(
+            "routes:\n- match: {{path: /api/v1/query}}\n  \
+             respond: {{template: true, data: {data}}}\n"
+        );
+        fs::write(&temp_file, yaml).expect("write temp file");
+        assert!(FixtureBook::load_from_path(&temp_file).is_err());
+    }
+
+    /// Test effective_status method with defaults.
+    #[test]
+    fn test_effective_status() {
+        let book = FixtureBook {
+            version: None,
+            defaults: Some(Defaults {
+                status: Some("default_success".to_string()),
+                clock_anchor: None,
+            }),
+            routes: vec![],
+            ..FixtureBook::default()
+        };
+
+        // Response with explicit status
+        let respond = Respond {
+            status: Some("custom_status".to_string()),
+            data: json!({}),
+            warnings: None,
+            error_type: None,
+            error: None,
+            ..Respond::default()
+        };
+        assert_eq!(book.effective_status(&respond), "custom_status");
+
+        // Response without explicit status (should use default)
+        let respond = Respond {
+            status: None,
+            data: json!({}),
+            warnings: None,
+            error_type: None,
+            error: None,
+            ..Respond::default()
+        };
+        assert_eq!(book.effective_status(&respond), "default_success");
+
+        // Book without defaults
+        let book_no_defaults = FixtureBook::default();
+        assert_eq!(book_no_defaults.effective_status(&respond), "success");
+    }
+
+    /// Test param_equal function with various time formats.
+    #[test]
+    fn test_param_equal() {
+        let fixed_time = datetime!(2022-01-01 12:00:00 UTC);
+
+        // Exact string matches
+        assert!(param_equal("1640995200", "1640995200", None));
+        assert!(!param_equal("1640995200", "1640995300", None));
+
+        // Relative time comparisons
+        assert!(param_equal("now", "now", Some(fixed_time)));
+        assert!(param_equal("now-1h", "now-1h", Some(fixed_time)));
+        assert!(!param_equal("now-1h", "now-2h", Some(fixed_time)));
+
+        // Mixed absolute/relative should work if they resolve to same value
+        let timestamp_1h_ago = "1641034800"; // fixed_time - 1 hour
+        assert!(param_equal("now-1h", timestamp_1h_ago, Some(fixed_time)));
+
+        // Raw vs resolved types should not match
+        assert!(!param_equal("raw_string", "1640995200", Some(fixed_time)));
+        assert!(!param_equal("1640995200", "raw_string", Some(fixed_time)));
+    }
+}
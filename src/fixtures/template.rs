@@ -0,0 +1,650 @@
+//! Templates in fixture response data.
+//!
+//! Strings in the `data` of a response with `template: true` may contain
+//! `{{ expr }}` placeholders that are evaluated per request, so fixtures do
+//! not need to hardcode timestamps matching a `--fixed-now`. Expressions
+//! combine numbers, durations (`5m`) and the variables `now`, `time`,
+//! `start`, `end`, `step` and `query` with `+ - * /` and parentheses. Times
+//! are UNIX seconds, durations seconds. For instant queries `time` is the
+//! evaluation time, and `now` follows it.
+//!
+//! A string that is a single placeholder becomes a JSON number, unless the
+//! expression ends in `| string`. An object `{"$steps": <template>}` expands
+//! into an array with one rendering of the template per range query step,
+//! where `t` is the step's time and `i` its index:
+//!
+//! ```yaml
+//! values: {"$steps": ["{{ t }}", "{{ i * 2 | string }}"]}
+//! ```
+//!
+//! Responses without `template: true` answer their `data` as written, so
+//! literal `{{` in label values or strings needs no escaping.
+
+use std::collections::BTreeSet;
+
+use serde_json::{Map, Number, Value};
+use thiserror::Error;
+
+/// Key of an object expanding into one element per range query step.
+pub const STEPS_KEY: &str = "$steps";
+
+/// Maximum number of elements `$steps` expands to, Prometheus' points limit.
+pub const MAX_STEPS: usize = 11_000;
+
+/// Values of the template variables for one request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TemplateContext {
+    /// Current time in UNIX seconds, the evaluation time for instant queries
+    pub now: f64,
+    /// Evaluation time in UNIX seconds, for instant queries
+    pub time: Option<f64>,
+    /// Range start in UNIX seconds, for range queries
+    pub start: Option<f64>,
+    /// Range end in UNIX seconds, for range queries
+    pub end: Option<f64>,
+    /// Range step in seconds, for range queries
+    pub step: Option<f64>,
+    /// PromQL query of the request
+    pub query: String,
+}
+
+/// Errors parsing or rendering templates.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TemplateError {
+    /// A placeholder could not be parsed.
+    #[error("invalid template {template:?}: {reason}")]
+    Syntax { template: String, reason: String },
+    /// A variable has no value for this request.
+    #[error("template variable {0} is not available for this request")]
+    Unavailable(&'static str),
+    /// An expression could not be evaluated.
+    #[error("template evaluation failed: {0}")]
+    Eval(String),
+}
+
+/// Render all templates in `data`.
+///
+/// # Parameters
+///
+/// - `data` - Response data, possibly containing templates
+/// - `ctx` - Variable values for the request
+///
+/// # Returns
+///
+/// Returns the data with placeholders replaced and `$steps` expanded.
+///
+/// # Errors
+///
+/// Returns `TemplateError` if a template is invalid, uses a variable the
+/// request does not provide, or cannot be evaluated.
+pub fn render(data: &Value, ctx: &TemplateContext) -> Result<Value, TemplateError> {
+    render_value(data, &Scope { ctx, point: None })
+}
+
+/// Parse all templates in `data` without rendering them.
+///
+/// # Parameters
+///
+/// - `data` - Response data, possibly containing templates
+///
+/// # Returns
+///
+/// Returns the names of the request variables the templates use, sorted.
+/// `$steps` uses `start`, `end` and `step`.
+///
+/// # Errors
+///
+/// Returns `TemplateError::Syntax` if a template is invalid or uses `t` or
+/// `i` outside of `$steps`.
+pub fn variables(data: &Value) -> Result<Vec<&'static str>, TemplateError> {
+    let mut used = BTreeSet::new();
+    collect_variables(data, false, &mut used)?;
+    Ok(used.into_iter().collect())
+}
+
+/// Template variables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Var {
+    Now,
+    Time,
+    Start,
+    End,
+    Step,
+    Query,
+    T,
+    I,
+}
+
+impl Var {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "now" => Var::Now,
+            "time" => Var::Time,
+            "start" => Var::Start,
+            "end" => Var::End,
+            "step" => Var::Step,
+            "query" => Var::Query,
+            "t" => Var::T,
+            "i" => Var::I,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Var::Now => "now",
+            Var::Time => "time",
+            Var::Start => "start",
+            Var::End => "end",
+            Var::Step => "step",
+            Var::Query => "query",
+            Var::T => "t",
+            Var::I => "i",
+        }
+    }
+
+    /// Whether the variable only exists inside `$steps`.
+    fn per_step(self) -> bool {
+        matches!(self, Var::T | Var::I)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(f64),
+    Var(Var),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, Op, Box<Expr>),
+}
+
+impl Expr {
+    fn visit_vars(&self, f: &mut impl FnMut(Var)) {
+        match self {
+            Expr::Num(_) => {}
+            Expr::Var(var) => f(*var),
+            Expr::Neg(inner) => inner.visit_vars(f),
+            Expr::Binary(lhs, _, rhs) => {
+                lhs.visit_vars(f);
+                rhs.visit_vars(f);
+            }
+        }
+    }
+}
+
+/// Part of a templated string.
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Placeholder { expr: Expr, string: bool },
+}
+
+/// Result of evaluating an expression.
+#[derive(Debug, Clone)]
+enum Val {
+    Num(f64),
+    Text(String),
+}
+
+impl Val {
+    fn into_json(self) -> Result<Value, TemplateError> {
+        match self {
+            Val::Text(text) => Ok(Value::String(text)),
+            Val::Num(n) if n.fract() == 0.0 && n.abs() < 9.0e15 => Ok(Value::from(n as i64)),
+            Val::Num(n) => Number::from_f64(n)
+                .map(Value::Number)
+                .ok_or_else(|| TemplateError::Eval(format!("{n} is not a valid JSON number"))),
+        }
+    }
+
+    fn into_text(self) -> String {
+        match self {
+            Val::Text(text) => text,
+            Val::Num(n) if n.fract() == 0.0 && n.abs() < 9.0e15 => (n as i64).to_string(),
+            Val::Num(n) => n.to_string(),
+        }
+    }
+}
+
+/// Variable values while rendering, with the current `$steps` point if any.
+struct Scope<'a> {
+    ctx: &'a TemplateContext,
+    point: Option<(f64, usize)>,
+}
+
+impl Scope<'_> {
+    fn lookup(&self, var: Var) -> Result<Val, TemplateError> {
+        let unavailable = || TemplateError::Unavailable(var.name());
+        let num = match var {
+            Var::Now => self.ctx.now,
+            Var::Time => self.ctx.time.ok_or_else(unavailable)?,
+            Var::Start => self.ctx.start.ok_or_else(unavailable)?,
+            Var::End => self.ctx.end.ok_or_else(unavailable)?,
+            Var::Step => self.ctx.step.ok_or_else(unavailable)?,
+            Var::Query => return Ok(Val::Text(self.ctx.query.clone())),
+            Var::T => self.point.ok_or_else(unavailable)?.0,
+            Var::I => self.point.ok_or_else(unavailable)?.1 as f64,
+        };
+        Ok(Val::Num(num))
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<Val, TemplateError> {
+        let number = |expr: &Expr| match self.eval(expr)? {
+            Val::Num(n) => Ok(n),
+            Val::Text(_) => Err(TemplateError::Eval("cannot do arithmetic on text".to_string())),
+        };
+        Ok(match expr {
+            Expr::Num(n) => Val::Num(*n),
+            Expr::Var(var) => self.lookup(*var)?,
+            Expr::Neg(inner) => Val::Num(-number(inner)?),
+            Expr::Binary(lhs, op, rhs) => {
+                let (lhs, rhs) = (number(lhs)?, number(rhs)?);
+                Val::Num(match op {
+                    Op::Add => lhs + rhs,
+                    Op::Sub => lhs - rhs,
+                    Op::Mul => lhs * rhs,
+                    Op::Div if rhs == 0.0 => {
+                        return Err(TemplateError::Eval("division by zero".to_string()))
+                    }
+                    Op::Div => lhs / rhs,
+                })
+            }
+        })
+    }
+}
+
+fn render_value(data: &Value, scope: &Scope<'_>) -> Result<Value, TemplateError> {
+    match data {
+        Value::String(text) => render_string(text, scope),
+        Value::Array(items) => {
+            items.iter().map(|item| render_value(item, scope)).collect::<Result<_, _>>()
+        }
+        Value::Object(object) => {
+            if let Some(template) = steps_template(object)? {
+                return render_steps(template, scope);
+            }
+            let mut rendered = Map::with_capacity(object.len());
+            for (key, value) in object {
+                rendered.insert(key.clone(), render_value(value, scope)?);
+            }
+            Ok(Value::Object(rendered))
+        }
+        other => Ok(other.clone()),
+    }
+}
+
+fn render_string(text: &str, scope: &Scope<'_>) -> Result<Value, TemplateError> {
+    let segments = parse_string(text)?;
+    if let [Segment::Placeholder { expr, string }] = segments.as_slice() {
+        let val = scope.eval(expr)?;
+        return if *string { Ok(Value::String(val.into_text())) } else { val.into_json() };
+    }
+    let mut rendered = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Text(part) => rendered.push_str(&part),
+            Segment::Placeholder { expr, .. } => rendered.push_str(&scope.eval(&expr)?.into_text()),
+        }
+    }
+    Ok(Value::String(rendered))
+}
+
+fn render_steps(template: &Value, scope: &Scope<'_>) -> Result<Value, TemplateError> {
+    step_times(scope.ctx)?
+        .into_iter()
+        .enumerate()
+        .map(|(i, t)| render_value(template, &Scope { ctx: scope.ctx, point: Some((t, i)) }))
+        .collect()
+}
+
+/// Evaluation times of a range query, from `start` to `end` every `step`.
+///
+/// # Parameters
+///
+/// - `ctx` - Variable values of a range request
+///
+/// # Returns
+///
+/// Returns the times in UNIX seconds, empty if `end` is before `start`.
+///
+/// # Errors
+///
+/// Returns `TemplateError` if a range parameter is missing, the step is not
+/// positive or there would be more than [`MAX_STEPS`] times.
+pub fn step_times(ctx: &TemplateContext) -> Result<Vec<f64>, TemplateError> {
+    let start = ctx.start.ok_or(TemplateError::Unavailable("start"))?;
+    let end = ctx.end.ok_or(TemplateError::Unavailable("end"))?;
+    let step = ctx.step.ok_or(TemplateError::Unavailable("step"))?;
+    if step <= 0.0 {
+        return Err(TemplateError::Eval(format!("step must be positive, got {step}")));
+    }
+    if end < start {
+        return Ok(Vec::new());
+    }
+    let points = ((end - start) / step).floor() + 1.0;
+    if points > MAX_STEPS as f64 {
+        return Err(TemplateError::Eval(format!(
+            "range would have {points} points, more than {MAX_STEPS}"
+        )));
+    }
+    Ok((0..points as usize).map(|i| start + i as f64 * step).collect())
+}
+
+/// Template of a `{"$steps": <template>}` object, if it is one.
+fn steps_template(object: &Map<String, Value>) -> Result<Option<&Value>, TemplateError> {
+    match object.get(STEPS_KEY) {
+        Some(template) if object.len() == 1 => Ok(Some(template)),
+        Some(_) => Err(TemplateError::Syntax {
+            template: STEPS_KEY.to_string(),
+            reason: "$steps must be the only key of its object".to_string(),
+        }),
+        None => Ok(None),
+    }
+}
+
+fn collect_variables(
+    data: &Value,
+    in_steps: bool,
+    used: &mut BTreeSet<&'static str>,
+) -> Result<(), TemplateError> {
+    match data {
+        Value::String(text) => {
+            let mut misplaced = None;
+            for segment in parse_string(text)? {
+                if let Segment::Placeholder { expr, .. } = segment {
+                    expr.visit_vars(&mut |var| {
+                        if !var.per_step() {
+                            used.insert(var.name());
+                        } else if !in_steps {
+                            misplaced = Some(var);
+                        }
+                    });
+                }
+            }
+            if let Some(var) = misplaced {
+                return Err(TemplateError::Syntax {
+                    template: text.clone(),
+                    reason: format!("{} is only defined inside $steps", var.name()),
+                });
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_variables(item, in_steps, used)?;
+            }
+        }
+        Value::Object(object) => {
+            if let Some(template) = steps_template(object)? {
+                used.extend(["start", "end", "step"]);
+                return collect_variables(template, true, used);
+            }
+            for value in object.values() {
+                collect_variables(value, in_steps, used)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Split a string into literal text and `{{ expr }}` placeholders.
+fn parse_string(text: &str) -> Result<Vec<Segment>, TemplateError> {
+    let syntax = |reason: String| TemplateError::Syntax { template: text.to_string(), reason };
+    let mut segments = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find("{{") {
+        if open > 0 {
+            segments.push(Segment::Text(rest[..open].to_string()));
+        }
+        let inner = &rest[open + 2..];
+        let close = inner.find("}}").ok_or_else(|| syntax("unclosed {{".to_string()))?;
+        let (source, string) = match inner[..close].split_once('|') {
+            Some((source, filter)) => match filter.trim() {
+                "string" => (source, true),
+                other => return Err(syntax(format!("unknown filter {other:?}"))),
+            },
+            None => (&inner[..close], false),
+        };
+        let expr = parse_expr(source).map_err(syntax)?;
+        segments.push(Segment::Placeholder { expr, string });
+        rest = &inner[close + 2..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest.to_string()));
+    }
+    Ok(segments)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(char),
+}
+
+/// Parse an arithmetic expression.
+fn parse_expr(source: &str) -> Result<Expr, String> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser { tokens: &tokens, pos: 0 };
+    let expr = parser.sum()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected {token:?}")),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Op(c));
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = i;
+            while let Some(&(j, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                end = j + c.len_utf8();
+                chars.next();
+            }
+            let number: f64 = source[i..end]
+                .parse()
+                .map_err(|_| format!("invalid number {:?}", &source[i..end]))?;
+            let mut unit_end = end;
+            while let Some(&(j, c)) = chars.peek() {
+                if !c.is_ascii_alphabetic() {
+                    break;
+                }
+                unit_end = j + 1;
+                chars.next();
+            }
+            let seconds = match &source[end..unit_end] {
+                "" => 1.0,
+                "ms" => 0.001,
+                "s" => 1.0,
+                "m" => 60.0,
+                "h" => 3_600.0,
+                "d" => 86_400.0,
+                "w" => 604_800.0,
+                "y" => 31_536_000.0,
+                unit => return Err(format!("unknown duration unit {unit:?}")),
+            };
+            tokens.push(Token::Num(number * seconds));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = i;
+            while let Some(&(j, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = j + 1;
+                chars.next();
+            }
+            tokens.push(Token::Ident(source[i..end].to_string()));
+        } else {
+            return Err(format!("unexpected character {c:?}"));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser over expression tokens.
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn next_op(&mut self, ops: &str) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(c)) if ops.contains(*c) => {
+                self.pos += 1;
+                Some(*c)
+            }
+            _ => None,
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(c) = self.next_op("+-") {
+            let op = if c == '+' { Op::Add } else { Op::Sub };
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while let Some(c) = self.next_op("*/") {
+            let op = if c == '*' { Op::Mul } else { Op::Div };
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.next_op("-").is_some() {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos).ok_or("expression ends unexpectedly")?;
+        self.pos += 1;
+        match token {
+            Token::Num(n) => Ok(Expr::Num(*n)),
+            Token::Ident(name) => {
+                Var::parse(name).map(Expr::Var).ok_or_else(|| format!("unknown variable {name:?}"))
+            }
+            Token::Op('(') => {
+                let expr = self.sum()?;
+                match self.next_op(")") {
+                    Some(_) => Ok(expr),
+                    None => Err("missing )".to_string()),
+                }
+            }
+            Token::Op(c) => Err(format!("unexpected {c:?}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn range_ctx() -> TemplateContext {
+        TemplateContext {
+            now: 1_722_643_200.0,
+            time: None,
+            start: Some(1_722_642_300.0),
+            end: Some(1_722_642_420.0),
+            step: Some(60.0),
+            query: "up".to_string(),
+        }
+    }
+
+    /// Test placeholders, arithmetic, durations and the string filter.
+    #[test]
+    fn test_render_placeholders() {
+        let ctx = range_ctx();
+        let data = json!({
+            "value": ["{{ now - 5m }}", "1"],
+            "scalar": "{{ (end - start) / step | string }}",
+            "text": "query {{ query }} at {{ now }}",
+            "fraction": "{{ 1 / 4 }}",
+            "negative": "{{ -step * 2 }}",
+            "plain": 42,
+        });
+        assert_eq!(
+            render(&data, &ctx).expect("renders"),
+            json!({
+                "value": [1_722_642_900, "1"],
+                "scalar": "2",
+                "text": "query up at 1722643200",
+                "fraction": 0.25,
+                "negative": -120,
+                "plain": 42,
+            })
+        );
+    }
+
+    /// Test `$steps` generates one element per step between start and end.
+    #[test]
+    fn test_render_steps() {
+        let data = json!({"values": {"$steps": ["{{ t }}", "{{ i * 2 | string }}"]}});
+        assert_eq!(
+            render(&data, &range_ctx()).expect("renders"),
+            json!({"values": [
+                [1_722_642_300, "0"],
+                [1_722_642_360, "2"],
+                [1_722_642_420, "4"],
+            ]})
+        );
+
+        let too_many = TemplateContext { step: Some(0.001), ..range_ctx() };
+        assert!(matches!(render(&data, &too_many), Err(TemplateError::Eval(_))));
+        let instant = TemplateContext { now: 0.0, time: Some(0.0), ..TemplateContext::default() };
+        assert_eq!(render(&data, &instant), Err(TemplateError::Unavailable("start")));
+        assert_eq!(render(&json!("{{ time }}"), &instant), Ok(json!(0)));
+        assert_eq!(
+            render(&json!("{{ time }}"), &range_ctx()),
+            Err(TemplateError::Unavailable("time"))
+        );
+    }
+
+    /// Test syntax checks and variable collection.
+    #[test]
+    fn test_variables() {
+        assert_eq!(variables(&json!(["{{ now }}", "{{ query }}"])), Ok(vec!["now", "query"]));
+        assert_eq!(variables(&json!("{{ time - 5m }}")), Ok(vec!["time"]));
+        assert_eq!(
+            variables(&json!({"$steps": "{{ t + step }}"})),
+            Ok(vec!["end", "start", "step"])
+        );
+        assert_eq!(variables(&json!({"no": "templates {here}"})), Ok(vec![]));
+
+        for bad in
+            ["{{ now", "{{ nope }}", "{{ 5x }}", "{{ now + }}", "{{ now | upper }}", "{{ t }}"]
+        {
+            assert!(
+                matches!(variables(&json!(bad)), Err(TemplateError::Syntax { .. })),
+                "{bad} should be rejected"
+            );
+        }
+        assert!(variables(&json!({"$steps": 1, "extra": 2})).is_err());
+    }
+}
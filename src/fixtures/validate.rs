@@ -0,0 +1,695 @@
+//! Fixture checks beyond parsing.
+//!
+//! A fixture that parses fine can still be useless: a typo like
+//! `resultype: vector` only fails in the client under test, and a route
+//! behind a broader one never matches. [`FixtureBook::validate`] finds such
+//! problems up front, pointing at the file and line of the route.
+
+use std::fmt;
+
+use serde_json::Value;
+
+use super::template::{self, TemplateContext};
+use super::{normalize_query_tokens, FixtureBook, Respond, Route, RouteLocation};
+
+/// API paths answered from fixtures; `<name>` stands for any label name.
+pub const FIXTURE_PATHS: &[&str] = &[
+    "/api/v1/query",
+    "/api/v1/query_range",
+    "/api/v1/series",
+    "/api/v1/labels",
+    "/api/v1/label/<name>/values",
+    "/api/v1/metadata",
+];
+
+/// Whether requests to `path` are answered from fixtures.
+///
+/// # Parameters
+///
+/// - `path` - API path of a fixture route
+///
+/// # Returns
+///
+/// Returns true for the paths in [`FIXTURE_PATHS`].
+pub fn is_fixture_path(path: &str) -> bool {
+    FIXTURE_PATHS.contains(&path)
+        || path
+            .strip_prefix("/api/v1/label/")
+            .and_then(|rest| rest.strip_suffix("/values"))
+            .is_some_and(|name| !name.is_empty() && !name.contains('/') && name != "<name>")
+}
+
+/// Whether `path` is a query endpoint rather than a metadata endpoint.
+fn is_query_path(path: &str) -> bool {
+    path.ends_with("/query") || path.ends_with("/query_range")
+}
+
+/// Fixture schema version understood by this server.
+pub const SUPPORTED_VERSION: u8 = 1;
+
+/// Variables templates are rendered with for the schema checks.
+const SAMPLE_CONTEXT: TemplateContext = TemplateContext {
+    now: 0.0,
+    time: Some(0.0),
+    start: Some(0.0),
+    end: Some(0.0),
+    step: Some(1.0),
+    query: String::new(),
+};
+
+/// A problem found in a fixture book.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// Index of the route in the book, `None` for problems of the whole book
+    pub route: Option<usize>,
+    /// Where the route was loaded from
+    pub location: Option<RouteLocation>,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.location, self.route) {
+            (Some(location), _) => {
+                write!(f, "{}", location.file.display())?;
+                if let Some(line) = location.line {
+                    write!(f, ":{line}")?;
+                }
+                write!(f, ": route {}: {}", location.index, self.message)
+            }
+            (None, Some(route)) => write!(f, "route {route}: {}", self.message),
+            (None, None) => f.write_str(&self.message),
+        }
+    }
+}
+
+impl FixtureBook {
+    /// Check the fixtures for problems that parsing does not catch.
+    ///
+    /// Checks the schema version, that routes target paths served from
+    /// fixtures, that query patterns are valid, that response `data` follows
+    /// the Prometheus response format for its `resultType`, and that no
+    /// route is shadowed by an earlier route matching all of its requests.
+    ///
+    /// # Returns
+    ///
+    /// Returns all problems found, empty if the fixtures are fine.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        if let Some(version) = self.version.filter(|v| *v != SUPPORTED_VERSION) {
+            issues.push(ValidationIssue {
+                route: None,
+                location: None,
+                message: format!("unsupported version {version}, expected {SUPPORTED_VERSION}"),
+            });
+        }
+
+        for (index, route) in self.routes.iter().enumerate() {
+            let mut report = |message: String| {
+                issues.push(ValidationIssue {
+                    route: Some(index),
+                    location: route.location.clone(),
+                    message,
+                });
+            };
+            let matcher = &route.matcher;
+
+            if !is_fixture_path(&matcher.path) {
+                report(format!(
+                    "unknown API path {:?}, fixtures serve {}",
+                    matcher.path,
+                    FIXTURE_PATHS.join(", ")
+                ));
+            }
+            if let Some(Err(e)) = matcher.query_regex() {
+                report(format!("invalid query_regex: {e}"));
+            }
+            if matcher.query_normalized {
+                if matcher.query.is_none() && matcher.matches.is_empty() {
+                    report("query_normalized is set without a query or match[]".to_string());
+                }
+                for query in matcher.query.iter().chain(&matcher.matches) {
+                    if let Err(e) = normalize_query_tokens(query) {
+                        report(format!("cannot normalize query: {e}"));
+                    }
+                }
+            }
+            let query_path = is_query_path(&matcher.path);
+            let metric_metadata = matcher.path == "/api/v1/metadata";
+            if (query_path || metric_metadata) && !matcher.matches.is_empty() {
+                report("match[] only applies to series, labels and label values".to_string());
+            }
+            if metric_metadata && (matcher.start.is_some() || matcher.end.is_some()) {
+                report("start and end do not apply to metadata".to_string());
+            }
+            if !query_path && (matcher.query.is_some() || matcher.query_regex.is_some()) {
+                report("query and query_regex only apply to query and query_range".to_string());
+            }
+            if !query_path && matcher.step.is_some() {
+                report("step only applies to query_range".to_string());
+            }
+
+            for (step, respond) in route.responses().enumerate() {
+                let prefix = match route.sequence {
+                    Some(_) => format!("sequence response {step}: "),
+                    None => String::new(),
+                };
+                let status = self.effective_status(respond);
+                for problem in check_respond(status, respond, &matcher.path) {
+                    report(format!("{prefix}{problem}"));
+                }
+                if respond.generate.is_some() && !query_path {
+                    report(format!("{prefix}generate only applies to query and query_range"));
+                }
+                let missing: &[&str] = match matcher.path.as_str() {
+                    path if path.ends_with("/query_range") => &["time"],
+                    path if path.ends_with("/query") => &["start", "end", "step"],
+                    _ => &["step", "time"],
+                };
+                let unavailable = Some(&respond.data)
+                    .filter(|_| respond.template)
+                    .and_then(|data| template::variables(data).ok())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|var| missing.contains(var))
+                    .collect::<Vec<_>>();
+                if !unavailable.is_empty() {
+                    report(format!(
+                        "{prefix}template uses {}, which {} requests do not have",
+                        unavailable.join(", "),
+                        matcher.path
+                    ));
+                }
+            }
+
+            if let Some(earlier) = self.routes[..index].iter().position(|e| shadows(e, route)) {
+                let by = match &self.routes[earlier].location {
+                    Some(RouteLocation { file, line: Some(line), .. }) => {
+                        format!("route {earlier} ({}:{line})", file.display())
+                    }
+                    _ => format!("route {earlier}"),
+                };
+                report(format!("unreachable, all its requests are answered by {by}"));
+            }
+        }
+        issues
+    }
+}
+
+/// Whether every request matching `later` also matches `earlier`.
+///
+/// Conservative: only reports shadowing it can prove from the criteria.
+fn shadows(earlier: &Route, later: &Route) -> bool {
+    let (e, l) = (&earlier.matcher, &later.matcher);
+    if earlier.required_state.is_some() || e.path != l.path {
+        return false;
+    }
+
+    let query_covered = match (&e.query, &l.query) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(eq), Some(lq)) if e.query_normalized => {
+            let (eq, lq) = (normalize_query_tokens(eq), normalize_query_tokens(lq));
+            matches!((eq, lq), (Ok(a), Ok(b)) if a == b)
+        }
+        (Some(eq), Some(lq)) => eq == lq && !l.query_normalized,
+    };
+    let regex_covered = match &e.query_regex {
+        None => true,
+        Some(pattern) => {
+            l.query_regex.as_ref() == Some(pattern)
+                || l.query.as_ref().is_some_and(|lq| {
+                    !l.query_normalized
+                        && e.query_regex().is_some_and(|re| re.is_ok_and(|re| re.is_match(lq)))
+                })
+        }
+    };
+    let param_covered =
+        |expect: &Option<String>, got: &Option<String>| expect.is_none() || expect == got;
+
+    let selectors_covered = e.matches.is_empty() || {
+        let (mut em, mut lm) = (e.matches.clone(), l.matches.clone());
+        em.sort();
+        lm.sort();
+        em == lm && e.query_normalized == l.query_normalized
+    };
+
+    query_covered
+        && regex_covered
+        && selectors_covered
+        && param_covered(&e.start, &l.start)
+        && param_covered(&e.end, &l.end)
+        && param_covered(&e.step, &l.step)
+}
+
+/// Check a response against the Prometheus API response format.
+///
+/// Templated data is checked as rendered for a sample range query with a
+/// single step, other data as written. Generated series always render valid
+/// data.
+fn check_respond(status: &str, respond: &Respond, path: &str) -> Vec<String> {
+    match status {
+        "success" if respond.generate.is_some() => Vec::new(),
+        "success" if !respond.template => check_data(&respond.data, path),
+        "success" => match template::render(&respond.data, &SAMPLE_CONTEXT) {
+            Ok(data) => check_data(&data, path),
+            Err(e) => vec![e.to_string()],
+        },
+        "error" => {
+            let mut problems = Vec::new();
+            if respond.error_type.is_none() {
+                problems.push("error response without errorType".to_string());
+            }
+            if respond.error.is_none() {
+                problems.push("error response without error message".to_string());
+            }
+            problems
+        }
+        other => vec![format!("unknown status {other:?}, expected success or error")],
+    }
+}
+
+/// Check `data` follows the schema of the path's response.
+///
+/// Query data must match its `resultType`; series data is a list of label
+/// sets and label name and value data a list of strings.
+fn check_data(data: &Value, path: &str) -> Vec<String> {
+    if !is_fixture_path(path) {
+        // Already reported, the expected schema is unknown
+        return Vec::new();
+    }
+    if path == "/api/v1/series" {
+        return check_list(data, "label set object", |item| {
+            item.as_object().is_some_and(|labels| labels.values().all(Value::is_string))
+        });
+    }
+    if path == "/api/v1/metadata" {
+        return check_metric_metadata(data);
+    }
+    if !is_query_path(path) {
+        return check_list(data, "string", Value::is_string);
+    }
+
+    let mut problems = Vec::new();
+    let Some(object) = data.as_object() else {
+        return vec!["data must be an object with resultType and result".to_string()];
+    };
+    for key in object.keys().filter(|k| *k != "resultType" && *k != "result") {
+        problems.push(format!("unknown field {key:?} in data"));
+    }
+    let Some(result_type) = object.get("resultType") else {
+        problems.push("data is missing resultType".to_string());
+        return problems;
+    };
+    let Some(result) = object.get("result") else {
+        problems.push("data is missing result".to_string());
+        return problems;
+    };
+
+    match result_type.as_str() {
+        Some("vector") => check_series(result, "value", "histogram", &mut problems),
+        Some("matrix") => check_series(result, "values", "histograms", &mut problems),
+        Some("scalar") => check_sample(result, "result", true, &mut problems),
+        Some("string") => check_sample(result, "result", false, &mut problems),
+        _ => problems.push(format!(
+            "unknown resultType {result_type}, expected vector, matrix, scalar or string"
+        )),
+    }
+    if path.ends_with("/query_range") && result_type.as_str() != Some("matrix") {
+        problems.push(format!("query_range responses need resultType matrix, got {result_type}"));
+    }
+    problems
+}
+
+/// Check metadata `data` is an array of items passing `valid`.
+fn check_list(data: &Value, item: &str, valid: impl Fn(&Value) -> bool) -> Vec<String> {
+    let Some(items) = data.as_array() else {
+        return vec![format!("data must be an array of {item}s")];
+    };
+    items
+        .iter()
+        .enumerate()
+        .filter(|(_, value)| !valid(value))
+        .map(|(i, value)| format!("data[{i}] must be a {item}, got {value}"))
+        .collect()
+}
+
+/// Check metric metadata `data` maps metric names to lists of metadata.
+fn check_metric_metadata(data: &Value) -> Vec<String> {
+    let Some(metrics) = data.as_object() else {
+        return vec!["data must be an object mapping metric names to metadata".to_string()];
+    };
+    let valid = |entry: &Value| {
+        entry.as_object().is_some_and(|fields| {
+            ["type", "help", "unit"]
+                .iter()
+                .all(|key| fields.get(*key).is_some_and(Value::is_string))
+        })
+    };
+    metrics
+        .iter()
+        .filter(|(_, entries)| !entries.as_array().is_some_and(|entries| entries.iter().all(valid)))
+        .map(|(metric, entries)| {
+            format!(
+                "data[{metric:?}] must be an array of {{type, help, unit}} objects, got {entries}"
+            )
+        })
+        .collect()
+}
+
+/// Check the series of a vector (`value`) or matrix (`values`) result.
+fn check_series(
+    result: &Value,
+    samples_key: &str,
+    histograms_key: &str,
+    problems: &mut Vec<String>,
+) {
+    let Some(series) = result.as_array() else {
+        problems.push("result must be an array".to_string());
+        return;
+    };
+    for (i, item) in series.iter().enumerate() {
+        let at = format!("result[{i}]");
+        let Some(object) = item.as_object() else {
+            problems.push(format!("{at} must be an object"));
+            continue;
+        };
+        for key in object.keys() {
+            if ![samples_key, histograms_key, "metric"].contains(&key.as_str()) {
+                problems.push(format!("unknown field {key:?} in {at}"));
+            }
+        }
+        match object.get("metric").and_then(Value::as_object) {
+            Some(labels) => {
+                if let Some((name, _)) = labels.iter().find(|(_, v)| !v.is_string()) {
+                    problems.push(format!("{at}.metric label {name:?} must be a string"));
+                }
+            }
+            None => problems.push(format!("{at} is missing the metric object")),
+        }
+
+        let samples = object.get(samples_key);
+        if samples.is_none() && !object.contains_key(histograms_key) {
+            problems.push(format!("{at} is missing {samples_key}"));
+        }
+        match samples {
+            Some(sample) if samples_key == "value" => {
+                check_sample(sample, &format!("{at}.value"), true, problems)
+            }
+            Some(Value::Array(values)) => {
+                for (j, sample) in values.iter().enumerate() {
+                    check_sample(sample, &format!("{at}.values[{j}]"), true, problems);
+                }
+            }
+            Some(_) => problems.push(format!("{at}.{samples_key} must be an array")),
+            None => {}
+        }
+    }
+}
+
+/// Check a `[<unix time>, "<value>"]` pair, with a float value if `numeric`.
+fn check_sample(sample: &Value, at: &str, numeric: bool, problems: &mut Vec<String>) {
+    let valid = match sample.as_array().map(Vec::as_slice) {
+        Some([time, Value::String(value)]) => {
+            time.is_number() && (!numeric || value.parse::<f64>().is_ok())
+        }
+        _ => false,
+    };
+    if !valid {
+        let expected =
+            if numeric { "[<unix time>, \"<float>\"]" } else { "[<unix time>, \"<string>\"]" };
+        problems.push(format!("{at} must be {expected}, got {sample}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::fixtures::Matcher;
+
+    fn route(path: &str, query: Option<&str>, data: Value) -> Route {
+        Route {
+            matcher: Matcher {
+                path: path.to_string(),
+                query: query.map(str::to_string),
+                ..Matcher::default()
+            },
+            respond: Some(Respond { data, ..Respond::default() }),
+            ..Route::default()
+        }
+    }
+
+    fn messages(book: &FixtureBook) -> Vec<String> {
+        book.validate().iter().map(ToString::to_string).collect()
+    }
+
+    /// Test well-formed responses of every result type pass.
+    #[test]
+    fn test_validate_valid() {
+        let vector = json!({"resultType": "vector", "result": [
+            {"metric": {"__name__": "up"}, "value": [1_700_000_000.5, "1"]}
+        ]});
+        let matrix = json!({"resultType": "matrix", "result": [
+            {"metric": {}, "values": [[1_700_000_000, "NaN"], [1_700_000_015, "+Inf"]]}
+        ]});
+        let book = FixtureBook {
+            version: Some(1),
+            routes: vec![
+                route("/api/v1/query", Some("up"), vector),
+                route(
+                    "/api/v1/query",
+                    Some("pi"),
+                    json!({"resultType": "scalar", "result": [1, "3.14"]}),
+                ),
+                route(
+                    "/api/v1/query",
+                    Some("s"),
+                    json!({"resultType": "string", "result": [1, "hi"]}),
+                ),
+                route("/api/v1/query_range", None, matrix),
+            ],
+            ..FixtureBook::default()
+        };
+        assert_eq!(messages(&book), Vec::<String>::new());
+    }
+
+    /// Test schema violations in data are reported per route.
+    #[test]
+    fn test_validate_schema() {
+        let book = FixtureBook {
+            version: Some(2),
+            routes: vec![
+                route("/api/v1/query", Some("a"), json!({"resultype": "vector", "result": []})),
+                route("/api/v1/query", Some("b"), json!({"resultType": "vectors", "result": []})),
+                route(
+                    "/api/v1/query",
+                    Some("c"),
+                    json!({"resultType": "vector", "result": [
+                        {"metric": {"job": 1}, "value": [1, "one"]},
+                        {"labels": {}}
+                    ]}),
+                ),
+                route("/api/v1/query_range", None, json!({"resultType": "vector", "result": []})),
+                route("/api/v1/targets", None, json!(null)),
+            ],
+            ..FixtureBook::default()
+        };
+        assert_eq!(
+            messages(&book),
+            vec![
+                "unsupported version 2, expected 1",
+                "route 0: unknown field \"resultype\" in data",
+                "route 0: data is missing resultType",
+                "route 1: unknown resultType \"vectors\", expected vector, matrix, scalar or string",
+                "route 2: result[0].metric label \"job\" must be a string",
+                "route 2: result[0].value must be [<unix time>, \"<float>\"], got [1,\"one\"]",
+                "route 2: unknown field \"labels\" in result[1]",
+                "route 2: result[1] is missing the metric object",
+                "route 2: result[1] is missing value",
+                "route 3: query_range responses need resultType matrix, got \"vector\"",
+                "route 4: unknown API path \"/api/v1/targets\", fixtures serve /api/v1/query, \
+                 /api/v1/query_range, /api/v1/series, /api/v1/labels, \
+                 /api/v1/label/<name>/values, /api/v1/metadata",
+            ]
+        );
+    }
+
+    /// Test templated data is checked as rendered and for range-only variables.
+    #[test]
+    fn test_validate_templates() {
+        let series = |samples: Value| {
+            json!({"resultType": "matrix", "result": [
+                {"metric": {"job": "api"}, "values": samples}
+            ]})
+        };
+        let templated = |path: &str, query: &str, data: Value| {
+            let mut route = route(path, Some(query), data);
+            route.respond.as_mut().expect("respond").template = true;
+            route
+        };
+        let book = FixtureBook {
+            routes: vec![
+                templated("/api/v1/query_range", "a", series(json!({"$steps": ["{{ t }}", "1"]}))),
+                templated("/api/v1/query_range", "b", series(json!({"$steps": ["{{ t }}", 1]}))),
+                templated(
+                    "/api/v1/query",
+                    "c",
+                    json!({"resultType": "scalar", "result": ["{{ end }}", "1"]}),
+                ),
+                route(
+                    "/api/v1/query",
+                    Some("d"),
+                    json!({"resultType": "scalar", "result": [1, "{{ end }}"]}),
+                ),
+                templated(
+                    "/api/v1/query",
+                    "e",
+                    json!({"resultType": "scalar", "result": ["{{ time }}", "1"]}),
+                ),
+                templated(
+                    "/api/v1/query_range",
+                    "f",
+                    series(json!({"$steps": ["{{ time }}", "1"]})),
+                ),
+            ],
+            ..FixtureBook::default()
+        };
+        assert_eq!(
+            messages(&book),
+            vec![
+                "route 1: result[0].values[0] must be [<unix time>, \"<float>\"], got [0,1]",
+                "route 2: template uses end, which /api/v1/query requests do not have",
+                "route 3: result must be [<unix time>, \"<float>\"], got [1,\"{{ end }}\"]",
+                "route 5: template uses time, which /api/v1/query_range requests do not have",
+            ]
+        );
+    }
+
+    /// Test metadata routes are checked against their schemas and matcher fields.
+    #[test]
+    fn test_validate_metadata() {
+        let metadata = |path: &str, data: Value| route(path, None, data);
+        let book = FixtureBook {
+            routes: vec![
+                metadata("/api/v1/labels", json!(["__name__", "job"])),
+                metadata("/api/v1/label/job/values", json!(["api", 1])),
+                metadata("/api/v1/series", json!([{"__name__": "up"}, ["up"]])),
+                route("/api/v1/labels", Some("up"), json!([])),
+                Route {
+                    matcher: Matcher {
+                        path: "/api/v1/query".to_string(),
+                        matches: vec!["up".to_string()],
+                        ..Matcher::default()
+                    },
+                    respond: Some(Respond { data: json!("x"), ..Respond::default() }),
+                    ..Route::default()
+                },
+                metadata(
+                    "/api/v1/metadata",
+                    json!({
+                        "up": [{"type": "gauge", "help": "Target is up.", "unit": ""}],
+                        "http_requests_total": [{"type": "counter"}],
+                    }),
+                ),
+                Route {
+                    matcher: Matcher {
+                        path: "/api/v1/metadata".to_string(),
+                        start: Some("now-1h".to_string()),
+                        ..Matcher::default()
+                    },
+                    respond: Some(Respond { data: json!([]), ..Respond::default() }),
+                    ..Route::default()
+                },
+            ],
+            ..FixtureBook::default()
+        };
+        assert_eq!(
+            messages(&book),
+            vec![
+                "route 1: data[1] must be a string, got 1",
+                "route 2: data[1] must be a label set object, got [\"up\"]",
+                "route 3: query and query_regex only apply to query and query_range",
+                "route 3: unreachable, all its requests are answered by route 0",
+                "route 4: match[] only applies to series, labels and label values",
+                "route 4: data must be an object with resultType and result",
+                "route 5: data[\"http_requests_total\"] must be an array of {type, help, unit} \
+                 objects, got [{\"type\":\"counter\"}]",
+                "route 6: start and end do not apply to metadata",
+                "route 6: data must be an object mapping metric names to metadata",
+                "route 6: unreachable, all its requests are answered by route 5",
+            ]
+        );
+        assert!(is_fixture_path("/api/v1/label/__name__/values"));
+        assert!(!is_fixture_path("/api/v1/label//values"));
+        assert!(!is_fixture_path("/api/v1/label/a/b/values"));
+    }
+
+    /// Test routes behind broader earlier routes are reported as unreachable.
+    #[test]
+    fn test_validate_shadowed_routes() {
+        let data = json!({"resultType": "vector", "result": []});
+        let mut normalized = route("/api/v1/query", Some("rate(x[5m])"), data.clone());
+        normalized.matcher.query_normalized = true;
+        let mut regex = route("/api/v1/query", None, data.clone());
+        regex.matcher.query_regex = Some("sum.*".to_string());
+        let mut stateful = route("/api/v1/query", None, data.clone());
+        stateful.required_state = Some("firing".to_string());
+
+        let book = FixtureBook {
+            routes: vec![
+                stateful,
+                normalized,
+                regex,
+                route("/api/v1/query", Some("rate(x[5m] )"), data.clone()),
+                route("/api/v1/query", Some("sum(up)"), data.clone()),
+                route("/api/v1/query", Some("up"), data.clone()),
+                route("/api/v1/query", None, data.clone()),
+                route("/api/v1/query", Some("down"), data),
+            ],
+            ..FixtureBook::default()
+        };
+        assert_eq!(
+            messages(&book),
+            vec![
+                "route 3: unreachable, all its requests are answered by route 1",
+                "route 4: unreachable, all its requests are answered by route 2",
+                "route 7: unreachable, all its requests are answered by route 6",
+            ]
+        );
+    }
+
+    /// Test issues point at the file and line of routes loaded from YAML.
+    #[test]
+    fn test_validate_locations() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("fixtures.yaml");
+        let yaml = r#"version: 1
+# comment
+routes:
+  - match: {path: /api/v1/query, query: up}
+    respond:
+      data: {resultType: vector, result: []}
+
+  - match: {path: /api/v1/query, query: up}
+    respond:
+      status: error
+      data: null
+"#;
+        fs::write(&path, yaml).expect("write fixtures");
+        let book = FixtureBook::load_from_path(&path).expect("load fixtures");
+        let file = path.display();
+        assert_eq!(
+            messages(&book),
+            vec![
+                format!("{file}:8: route 1: error response without errorType"),
+                format!("{file}:8: route 1: error response without error message"),
+                format!("{file}:8: route 1: unreachable, all its requests are answered by route 0 ({file}:4)"),
+            ]
+        );
+    }
+}
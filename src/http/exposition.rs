@@ -0,0 +1,201 @@
+//! Prometheus text exposition format rendering.
+//!
+//! This module renders samples in the plain-text format used by `/federate`
+//! and scrape endpoints such as `/metrics` (`text/plain; version=0.0.4`).
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::storage::{Label, Sample};
+
+/// Content type of the Prometheus text exposition format.
+pub const TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Render samples in the Prometheus text format, grouped by metric name.
+///
+/// Each sample is written with its timestamp, and every metric family is
+/// preceded by an `untyped` TYPE line, matching Prometheus federation output.
+///
+/// # Parameters
+///
+/// - `samples` - Pairs of series labels and the sample to expose for them
+///
+/// # Returns
+///
+/// Returns the rendered exposition text.
+pub fn render_samples(samples: &[(Vec<Label>, Sample)]) -> String {
+    // Group by metric name, keeping families and series in a deterministic order
+    let mut families: BTreeMap<&str, Vec<(Vec<&Label>, &Sample)>> = BTreeMap::new();
+    for (labels, sample) in samples {
+        let name = labels.iter().find(|l| l.name == "__name__").map_or("", |l| l.value.as_str());
+        let mut rest: Vec<&Label> = labels.iter().filter(|l| l.name != "__name__").collect();
+        rest.sort();
+        families.entry(name).or_default().push((rest, sample));
+    }
+
+    let mut out = String::new();
+    for (name, mut series) in families {
+        series.sort_by(|a, b| a.0.cmp(&b.0));
+        if !name.is_empty() {
+            let _ = writeln!(out, "# TYPE {name} untyped");
+        }
+        for (labels, sample) in series {
+            write_series(&mut out, name, &labels);
+            let _ = writeln!(out, " {} {}", format_value(sample.value), sample.timestamp);
+        }
+    }
+    out
+}
+
+/// A metric family of the server's own metrics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricFamily {
+    /// Metric name
+    pub name: &'static str,
+    /// Help text
+    pub help: &'static str,
+    /// Metric type, e.g. `counter` or `gauge`
+    pub kind: &'static str,
+    /// Label sets (without `__name__`) and current values
+    pub series: Vec<(Vec<Label>, f64)>,
+}
+
+/// Render metric families with HELP and TYPE lines and without timestamps.
+///
+/// # Parameters
+///
+/// - `families` - Metric families in output order
+///
+/// # Returns
+///
+/// Returns the rendered exposition text.
+pub fn render_families(families: &[MetricFamily]) -> String {
+    let mut out = String::new();
+    for family in families {
+        let _ = writeln!(out, "# HELP {} {}", family.name, family.help);
+        let _ = writeln!(out, "# TYPE {} {}", family.name, family.kind);
+        for (labels, value) in &family.series {
+            let mut labels: Vec<&Label> = labels.iter().collect();
+            labels.sort();
+            write_series(&mut out, family.name, &labels);
+            let _ = writeln!(out, " {}", format_value(*value));
+        }
+    }
+    out
+}
+
+/// Write a metric name and its label set, e.g. `up{job="api"}`.
+fn write_series(out: &mut String, name: &str, labels: &[&Label]) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        out.push('{');
+        for (i, label) in labels.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{}=\"{}\"", label.name, escape_label_value(&label.value));
+        }
+        out.push('}');
+    }
+}
+
+/// Format a sample value the way Prometheus does for special floats.
+pub fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escape backslashes, double quotes and newlines in a label value.
+fn escape_label_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test rendering groups series by metric name with TYPE lines and timestamps.
+    #[test]
+    fn test_render_samples() {
+        let samples = vec![
+            (vec![Label::new("__name__", "up"), Label::new("job", "web")], Sample::new(1000, 0.0)),
+            (vec![Label::new("job", "api"), Label::new("__name__", "up")], Sample::new(2000, 1.0)),
+            (vec![Label::new("__name__", "cpu_usage")], Sample::new(3000, 0.25)),
+        ];
+
+        let text = render_samples(&samples);
+        assert_eq!(
+            text,
+            "# TYPE cpu_usage untyped\n\
+             cpu_usage 0.25 3000\n\
+             # TYPE up untyped\n\
+             up{job=\"api\"} 1 2000\n\
+             up{job=\"web\"} 0 1000\n"
+        );
+    }
+
+    /// Test special float values and label escaping.
+    #[test]
+    fn test_format_value_and_escaping() {
+        assert_eq!(format_value(f64::NAN), "NaN");
+        assert_eq!(format_value(f64::INFINITY), "+Inf");
+        assert_eq!(format_value(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(format_value(42.5), "42.5");
+
+        assert_eq!(escape_label_value(r#"a"b\c"#), r#"a\"b\\c"#);
+        assert_eq!(escape_label_value("line\nbreak"), "line\\nbreak");
+    }
+
+    /// Test rendering metric families with HELP and TYPE lines.
+    #[test]
+    fn test_render_families() {
+        let families = vec![
+            MetricFamily {
+                name: "requests_total",
+                help: "Requests handled.",
+                kind: "counter",
+                series: vec![
+                    (vec![Label::new("code", "200")], 3.0),
+                    (vec![Label::new("code", "500")], 1.0),
+                ],
+            },
+            MetricFamily {
+                name: "series",
+                help: "Stored series.",
+                kind: "gauge",
+                series: vec![(vec![], 7.0)],
+            },
+        ];
+
+        assert_eq!(
+            render_families(&families),
+            "# HELP requests_total Requests handled.\n\
+             # TYPE requests_total counter\n\
+             requests_total{code=\"200\"} 3\n\
+             requests_total{code=\"500\"} 1\n\
+             # HELP series Stored series.\n\
+             # TYPE series gauge\n\
+             series 7\n"
+        );
+    }
+
+    /// Test rendering of an empty sample set.
+    #[test]
+    fn test_render_empty() {
+        assert_eq!(render_samples(&[]), "");
+    }
+}
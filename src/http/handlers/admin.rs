@@ -0,0 +1,271 @@
+//! TSDB admin API handlers for deleting stored data and taking snapshots.
+//!
+//! All endpoints answer 503 `unavailable` unless the admin API is enabled,
+//! matching Prometheus without `--web.enable-admin-api`.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+
+use crate::http::handlers::health::maybe_latency_and_error;
+use crate::http::handlers::{api_error, parse_time};
+use crate::http::state::AppState;
+use crate::query_engine::SimpleQueryEngine;
+
+/// Delete stored series matching the `match[]` selectors.
+///
+/// Samples between the optional `start` and `end` parameters (Unix seconds
+/// or RFC3339, both inclusive) are deleted immediately; series left without
+/// samples disappear from storage and the label index.
+///
+/// # Parameters
+///
+/// - `state` - Application state containing storage and admin configuration
+/// - `params` - Raw query parameters, `match[]` may be repeated
+///
+/// # Returns
+///
+/// Returns 204 on success, or 400 if no valid selector or time is given.
+pub async fn delete_series(
+    State(state): State<AppState>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> impl IntoResponse {
+    if let Err(response) = check_admin(&state).await {
+        return response;
+    }
+
+    let selectors: Vec<&str> =
+        params.iter().filter(|(k, _)| k == "match[]").map(|(_, v)| v.as_str()).collect();
+    if selectors.is_empty() {
+        return api_error(StatusCode::BAD_REQUEST, "bad_data", "no match[] parameter provided");
+    }
+
+    let param = |name: &str| params.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+    let (start, end) =
+        match (parse_time(param("start"), i64::MIN), parse_time(param("end"), i64::MAX)) {
+            (Ok(start), Ok(end)) => (start, end),
+            (Err(e), _) | (_, Err(e)) => return api_error(StatusCode::BAD_REQUEST, "bad_data", &e),
+        };
+
+    let mut matcher_sets = Vec::with_capacity(selectors.len());
+    for selector in selectors {
+        match SimpleQueryEngine::parse_matchers(selector) {
+            Ok(matchers) => matcher_sets.push(matchers),
+            Err(e) => return api_error(StatusCode::BAD_REQUEST, "bad_data", &e.to_string()),
+        }
+    }
+
+    let deleted: usize = matcher_sets
+        .iter()
+        .map(|matchers| state.query.storage.delete_series(start, end, matchers))
+        .sum();
+    tracing::info!("admin API deleted {} samples", deleted);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Remove deleted data from disk.
+///
+/// Deletions are applied immediately, so there are never tombstones to
+/// clean up; the endpoint exists for compatibility with Prometheus tooling.
+///
+/// # Parameters
+///
+/// - `state` - Application state containing the admin configuration
+///
+/// # Returns
+///
+/// Returns 204 when the admin API is enabled.
+pub async fn clean_tombstones(State(state): State<AppState>) -> impl IntoResponse {
+    if let Err(response) = check_admin(&state).await {
+        return response;
+    }
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Write a snapshot of all stored data into the admin snapshot directory.
+///
+/// The `skip_head` parameter is accepted but ignored, as all data lives in
+/// memory.
+///
+/// # Parameters
+///
+/// - `state` - Application state containing storage and admin configuration
+///
+/// # Returns
+///
+/// Returns the snapshot file name as `{"name": ...}`, or 500 if writing fails.
+pub async fn snapshot(State(state): State<AppState>) -> impl IntoResponse {
+    if let Err(response) = check_admin(&state).await {
+        return response;
+    }
+
+    let name = snapshot_name(time::OffsetDateTime::now_utc(), rand::random());
+    let dir = state.admin.snapshot_dir.clone();
+    let storage = state.query.storage.clone();
+    let path = dir.join(&name);
+    let result = tokio::task::spawn_blocking(move || {
+        std::fs::create_dir_all(&dir)?;
+        storage.snapshot_to(&path)
+    })
+    .await
+    .unwrap_or_else(|e| Err(std::io::Error::other(e)));
+
+    match result {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "success", "data": { "name": name } })),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::warn!("admin snapshot failed: {}", e);
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "internal", &e.to_string())
+        }
+    }
+}
+
+/// Reject requests while the admin API is disabled, then simulate latency and errors.
+async fn check_admin(state: &AppState) -> Result<(), axum::response::Response> {
+    if !state.admin.enabled {
+        return Err(api_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "unavailable",
+            "admin APIs disabled",
+        ));
+    }
+    maybe_latency_and_error(state).await.map_err(|code| (code, "simulated failure").into_response())
+}
+
+/// Snapshot name in the Prometheus format, e.g. `20220101T000000Z-00000000075bcd15`.
+fn snapshot_name(now: time::OffsetDateTime, suffix: u64) -> String {
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z-{:016x}",
+        now.year(),
+        u8::from(now.month()),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second(),
+        suffix
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::extract::{Query, State};
+
+    use crate::http::state::AppState;
+    use crate::storage::snapshot::SnapshotData;
+    use crate::storage::{Label, MemoryStorage, MetadataStorage, Sample, Storage, TimeSeries};
+
+    use super::*;
+
+    fn create_test_storage() -> Arc<MemoryStorage> {
+        let storage = Arc::new(MemoryStorage::new());
+        for job in ["api", "worker"] {
+            let mut ts =
+                TimeSeries::new(vec![Label::new("__name__", "up"), Label::new("job", job)]);
+            ts.add_sample(Sample::new(1_000, 1.0));
+            ts.add_sample(Sample::new(2_000, 1.0));
+            ts.add_sample(Sample::new(3_000, 1.0));
+            storage.add_series(ts);
+        }
+        storage
+    }
+
+    fn create_admin_state(storage: Arc<MemoryStorage>, snapshot_dir: &std::path::Path) -> AppState {
+        AppState::builder()
+            .with_storage(storage)
+            .with_admin_api(snapshot_dir)
+            .build()
+            .expect("valid configuration")
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> Query<Vec<(String, String)>> {
+        Query(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+    }
+
+    /// Test admin endpoints are unavailable unless enabled.
+    #[tokio::test]
+    async fn test_admin_api_disabled() {
+        let state = AppState::builder()
+            .with_storage(create_test_storage())
+            .build()
+            .expect("valid configuration");
+
+        let response =
+            delete_series(State(state.clone()), params(&[("match[]", "up")])).await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let response = clean_tombstones(State(state.clone())).await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let response = snapshot(State(state)).await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    /// Test deleting a time range keeps the series, deleting everything removes it.
+    #[tokio::test]
+    async fn test_delete_series() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let storage = create_test_storage();
+        let state = create_admin_state(storage.clone(), dir.path());
+
+        let query = params(&[("match[]", r#"up{job="api"}"#), ("start", "1.5"), ("end", "2")]);
+        let response = delete_series(State(state.clone()), query).await.into_response();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let series = storage.query_series(&SimpleQueryEngine::parse_matchers("up").unwrap());
+        let lengths: Vec<usize> = series.iter().map(|s| s.samples.len()).collect();
+        assert_eq!(lengths.iter().sum::<usize>(), 5);
+
+        let query = params(&[("match[]", r#"{job="worker"}"#)]);
+        let response = delete_series(State(state), query).await.into_response();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(storage.series_count(), 1);
+        assert_eq!(storage.label_values("job"), vec!["api".to_string()]);
+    }
+
+    /// Test invalid delete requests are rejected before deleting anything.
+    #[tokio::test]
+    async fn test_delete_series_bad_request() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let storage = create_test_storage();
+        let state = create_admin_state(storage.clone(), dir.path());
+
+        for query in [
+            params(&[]),
+            params(&[("match[]", r#"{job=~".*"}"#)]),
+            params(&[("match[]", "up"), ("start", "yesterday")]),
+        ] {
+            let response = delete_series(State(state.clone()), query).await.into_response();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(storage.series_count(), 2);
+    }
+
+    /// Test the snapshot endpoint writes a readable snapshot and returns its name.
+    #[tokio::test]
+    async fn test_snapshot() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let snapshot_dir = dir.path().join("snapshots");
+        let state = create_admin_state(create_test_storage(), &snapshot_dir);
+
+        let response = snapshot(State(state)).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.expect("body");
+        let json: serde_json::Value = serde_json::from_slice(&bytes).expect("parse JSON");
+        let name = json["data"]["name"].as_str().expect("snapshot name");
+
+        let data = SnapshotData::read_from(&snapshot_dir.join(name)).expect("read snapshot");
+        assert_eq!(data.meta().series, 2);
+    }
+
+    /// Test snapshot names follow the Prometheus format.
+    #[test]
+    fn test_snapshot_name() {
+        let now = time::OffsetDateTime::from_unix_timestamp(1_640_995_200).expect("valid time");
+        assert_eq!(snapshot_name(now, 123_456_789), "20220101T000000Z-00000000075bcd15");
+    }
+}
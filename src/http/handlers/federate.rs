@@ -0,0 +1,180 @@
+//! Federation handler exposing stored series in the text exposition format.
+
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+
+use crate::http::exposition::{render_samples, TEXT_CONTENT_TYPE};
+use crate::http::handlers::health::maybe_latency_and_error;
+use crate::http::state::AppState;
+use crate::storage::{Label, Sample};
+
+/// Convert seconds to milliseconds (Prometheus uses millisecond timestamps).
+const SECONDS_TO_MILLISECONDS: i64 = 1000;
+
+/// Serve the latest sample of every series matching the `match[]` selectors.
+///
+/// Each selector is evaluated through the query engine at the current time
+/// (or `fixed_now`), and series selected by several selectors are only
+/// rendered once.
+///
+/// # Parameters
+///
+/// - `state` - Application state containing the query engine
+/// - `params` - Raw query parameters, `match[]` may be repeated
+///
+/// # Returns
+///
+/// Returns series in Prometheus text format, or 400 if no valid selector is given.
+pub async fn federate(
+    State(state): State<AppState>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> impl IntoResponse {
+    if let Err(code) = maybe_latency_and_error(&state).await {
+        return (code, "simulated failure").into_response();
+    }
+
+    let selectors: Vec<&str> =
+        params.iter().filter(|(k, _)| k == "match[]").map(|(_, v)| v.as_str()).collect();
+    if selectors.is_empty() {
+        return (StatusCode::BAD_REQUEST, "no match[] parameter provided").into_response();
+    }
+
+    let now = state.query.fixed_now.unwrap_or_else(time::OffsetDateTime::now_utc);
+    let timestamp = now.unix_timestamp() * SECONDS_TO_MILLISECONDS;
+
+    // Deduplicate series by their sorted label set
+    let mut latest: BTreeMap<Vec<Label>, Sample> = BTreeMap::new();
+    for selector in selectors {
+        let result = match state.query.query_engine.query_instant(selector, timestamp) {
+            Ok(result) => result,
+            Err(e) => {
+                tracing::warn!("federate selector error: {}", e);
+                return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+            }
+        };
+
+        for mut series in result.series {
+            if let Some(sample) = series.samples.pop() {
+                series.labels.sort();
+                latest.insert(series.labels, sample);
+            }
+        }
+    }
+
+    let samples: Vec<(Vec<Label>, Sample)> = latest.into_iter().collect();
+    (StatusCode::OK, [(header::CONTENT_TYPE, TEXT_CONTENT_TYPE)], render_samples(&samples))
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::extract::{Query, State};
+
+    use crate::http::state::AppState;
+    use crate::storage::{Label, MemoryStorage, Sample, Storage, TimeSeries};
+
+    use super::*;
+
+    fn create_test_state() -> AppState {
+        let storage = Arc::new(MemoryStorage::new());
+
+        let mut ts = TimeSeries::new(vec![
+            Label::new("__name__", "http_requests_total"),
+            Label::new("job", "api"),
+        ]);
+        ts.add_sample(Sample::new(1_640_995_140_000, 5.0));
+        ts.add_sample(Sample::new(1_640_995_170_000, 7.0));
+        storage.add_series(ts);
+
+        let mut ts = TimeSeries::new(vec![Label::new("__name__", "up"), Label::new("job", "api")]);
+        ts.add_sample(Sample::new(1_640_995_200_000, 1.0));
+        storage.add_series(ts);
+
+        // Too old for the lookback window
+        let mut ts = TimeSeries::new(vec![Label::new("__name__", "up"), Label::new("job", "old")]);
+        ts.add_sample(Sample::new(1_640_990_000_000, 1.0));
+        storage.add_series(ts);
+
+        let fixed_now =
+            time::OffsetDateTime::from_unix_timestamp(1_640_995_200).expect("valid timestamp");
+        AppState::builder()
+            .with_storage(storage)
+            .with_fixed_now(fixed_now)
+            .build()
+            .expect("valid configuration")
+    }
+
+    async fn read_body(response: axum::response::Response) -> String {
+        let (_, body) = response.into_parts();
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.expect("read body");
+        String::from_utf8(bytes.to_vec()).expect("utf-8 body")
+    }
+
+    /// Test federation renders the latest sample of each matching series.
+    #[tokio::test]
+    async fn test_federate_renders_latest_samples() {
+        let state = create_test_state();
+        let params = vec![
+            ("match[]".to_string(), r#"{job="api"}"#.to_string()),
+            ("match[]".to_string(), "up".to_string()),
+        ];
+
+        let response = federate(State(state), Query(params)).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], TEXT_CONTENT_TYPE);
+
+        let body = read_body(response).await;
+        assert_eq!(
+            body,
+            "# TYPE http_requests_total untyped\n\
+             http_requests_total{job=\"api\"} 7 1640995170000\n\
+             # TYPE up untyped\n\
+             up{job=\"api\"} 1 1640995200000\n"
+        );
+    }
+
+    /// Test federation without selectors is rejected.
+    #[tokio::test]
+    async fn test_federate_requires_match() {
+        let state = create_test_state();
+
+        let response = federate(State(state), Query(vec![])).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    /// Test federation with an invalid selector is rejected.
+    #[tokio::test]
+    async fn test_federate_invalid_selector() {
+        let state = create_test_state();
+        let params = vec![("match[]".to_string(), r#"up{job="api""#.to_string())];
+
+        let response = federate(State(state), Query(params)).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    /// Test federation over the full router with a repeated `match[]` query string.
+    #[tokio::test]
+    async fn test_federate_route() {
+        let server = axum_test::TestServer::new(crate::http::build_router(create_test_state()))
+            .expect("test server");
+
+        let response = server
+            .get("/federate")
+            .add_query_param("match[]", "up")
+            .add_query_param("match[]", "http_requests_total")
+            .await;
+        response.assert_status_ok();
+
+        let body = response.text();
+        assert!(body.contains("up{job=\"api\"} 1 1640995200000"));
+        assert!(body.contains("http_requests_total{job=\"api\"} 7 1640995170000"));
+        assert!(!body.contains("job=\"old\""));
+    }
+}
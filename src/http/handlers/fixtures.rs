@@ -2,18 +2,25 @@
 
 use axum::{
     extract::{Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
 
-use crate::fixtures::QueryParams as FQueryParams;
+use crate::fixtures::{
+    FixtureBook, FixtureError, QueryParams as FQueryParams, Respond, TemplateContext,
+};
+use crate::http::handlers::api_error;
 use crate::http::handlers::health::maybe_latency_and_error;
 use crate::http::state::AppState;
 use crate::http::types::{PromApiResponse, QueryParams, QueryRangeParams};
+use crate::timeutil::{duration_seconds, unix_seconds};
 
 /// Handle instant query requests using fixtures.
 ///
+/// Templates and generated series are evaluated at the `time` parameter,
+/// or at "now" without one.
+///
 /// # Parameters
 ///
 /// - `state` - Application state containing fixture data
@@ -21,7 +28,8 @@ use crate::http::types::{PromApiResponse, QueryParams, QueryRangeParams};
 ///
 /// # Returns
 ///
-/// Returns fixture response if matching fixture is found, otherwise 404.
+/// Returns fixture response if matching fixture is found, otherwise 404, or
+/// 400 for an invalid `time`.
 pub async fn query(
     State(state): State<AppState>,
     Query(params): Query<QueryParams>,
@@ -30,21 +38,33 @@ pub async fn query(
         return (code, "simulated failure").into_response();
     }
 
-    let qp = FQueryParams { query: params.query.clone(), start: None, end: None, step: None };
-
-    if let Some(resp) = state.mock.fixtures.find_match("/api/v1/query", &qp, state.mock.fixed_now) {
-        let status = state.mock.fixtures.effective_status(resp);
-        return (
-            StatusCode::OK,
-            Json(PromApiResponse {
-                status,
-                data: Some(resp.data.clone()),
-                warnings: resp.warnings.as_ref(),
-                error_type: resp.error_type.as_ref(),
-                error: resp.error.as_ref(),
-            }),
-        )
-            .into_response();
+    let now = state.mock.fixed_now.unwrap_or_else(time::OffsetDateTime::now_utc);
+    let eval_time = match params.time.as_deref() {
+        None => now_seconds(Some(now)),
+        Some(time) => match unix_seconds(time, now) {
+            Some(seconds) => seconds,
+            None => {
+                let error = format!("cannot parse {time:?} to a valid timestamp");
+                return api_error(StatusCode::BAD_REQUEST, "bad_data", &error);
+            }
+        },
+    };
+    let qp = FQueryParams { query: params.query.clone(), ..FQueryParams::default() };
+
+    let fixtures = state.mock.fixtures.load_full();
+    if let Some(resp) = fixtures.next_response(
+        "/api/v1/query",
+        &qp,
+        state.mock.fixed_now,
+        &state.mock.fixture_state,
+    ) {
+        let ctx = TemplateContext {
+            now: eval_time,
+            time: Some(eval_time),
+            query: params.query,
+            ..TemplateContext::default()
+        };
+        return fixture_response(&fixtures, resp, &ctx).await;
     }
 
     // No match found - return 404 in Prometheus style
@@ -88,23 +108,26 @@ pub async fn query_range(
         start: Some(start),
         end: Some(end),
         step: Some(params.step.clone()),
+        matches: Vec::new(),
     };
 
-    if let Some(resp) =
-        state.mock.fixtures.find_match("/api/v1/query_range", &qp, state.mock.fixed_now)
-    {
-        let status = state.mock.fixtures.effective_status(resp);
-        return (
-            StatusCode::OK,
-            Json(PromApiResponse {
-                status,
-                data: Some(resp.data.clone()),
-                warnings: resp.warnings.as_ref(),
-                error_type: resp.error_type.as_ref(),
-                error: resp.error.as_ref(),
-            }),
-        )
-            .into_response();
+    let fixtures = state.mock.fixtures.load_full();
+    if let Some(resp) = fixtures.next_response(
+        "/api/v1/query_range",
+        &qp,
+        state.mock.fixed_now,
+        &state.mock.fixture_state,
+    ) {
+        let now = state.mock.fixed_now.unwrap_or_else(time::OffsetDateTime::now_utc);
+        let ctx = TemplateContext {
+            now: now_seconds(Some(now)),
+            time: None,
+            start: unix_seconds(&params.start, now),
+            end: unix_seconds(&params.end, now),
+            step: duration_seconds(&params.step),
+            query: params.query,
+        };
+        return fixture_response(&fixtures, resp, &ctx).await;
     }
 
     (
@@ -120,6 +143,157 @@ pub async fn query_range(
         .into_response()
 }
 
+/// Answer a metadata request from fixtures if a route matches.
+///
+/// `start` and `end` are resolved like for range queries, and the `match[]`
+/// selectors are passed on for matching.
+///
+/// # Parameters
+///
+/// - `state` - Application state containing fixture data
+/// - `path` - API path of the request
+/// - `params` - Raw query parameters, `match[]` may be repeated
+///
+/// # Returns
+///
+/// Returns the fixture response, or `None` if no route matched.
+pub(crate) async fn metadata_fixture(
+    state: &AppState,
+    path: &str,
+    params: &[(String, String)],
+) -> Option<Response> {
+    let param = |name: &str| params.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+    let qp = FQueryParams {
+        start: param("start").map(|start| stringify_resolved(start, state.mock.fixed_now)),
+        end: param("end").map(|end| stringify_resolved(end, state.mock.fixed_now)),
+        matches: params.iter().filter(|(k, _)| k == "match[]").map(|(_, v)| v.clone()).collect(),
+        ..FQueryParams::default()
+    };
+
+    let fixtures = state.mock.fixtures.load_full();
+    let resp =
+        fixtures.next_response(path, &qp, state.mock.fixed_now, &state.mock.fixture_state)?;
+    let now = state.mock.fixed_now.unwrap_or_else(time::OffsetDateTime::now_utc);
+    let ctx = TemplateContext {
+        now: now_seconds(Some(now)),
+        start: param("start").and_then(|start| unix_seconds(start, now)),
+        end: param("end").and_then(|end| unix_seconds(end, now)),
+        ..TemplateContext::default()
+    };
+    Some(fixture_response(&fixtures, resp, &ctx).await)
+}
+
+/// Build the HTTP response for a matched fixture.
+///
+/// Waits for the fixture's delay and may simulate a failure first. The
+/// response data is rendered for `ctx`; if that fails, the fixture is broken
+/// or the range parameters unusable and the client gets a 500 explaining why.
+async fn fixture_response(
+    fixtures: &FixtureBook,
+    resp: &Respond,
+    ctx: &TemplateContext,
+) -> Response {
+    if let Some(delay) = &resp.delay {
+        tokio::time::sleep(delay.sample()).await;
+    }
+    if resp.failure_rate.is_some_and(|rate| rand::random::<f32>() < rate) {
+        return (StatusCode::SERVICE_UNAVAILABLE, "simulated failure").into_response();
+    }
+
+    let data = match resp.render_data(ctx) {
+        Ok(data) => data,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(PromApiResponse {
+                    status: "error",
+                    data: None,
+                    warnings: None,
+                    error_type: Some(&"internal".to_string()),
+                    error: Some(&format!("fixture template: {e}")),
+                }),
+            )
+                .into_response()
+        }
+    };
+
+    let status = fixtures.effective_status(resp);
+    let code =
+        StatusCode::from_u16(resp.status_code(status)).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let mut response = (
+        code,
+        Json(PromApiResponse {
+            status,
+            data: Some(data),
+            warnings: resp.warnings.as_ref(),
+            error_type: resp.error_type.as_ref(),
+            error: resp.error.as_ref(),
+        }),
+    )
+        .into_response();
+    for (name, value) in resp.headers.iter().flatten() {
+        match (HeaderName::try_from(name.as_str()), HeaderValue::try_from(value.as_str())) {
+            (Ok(name), Ok(value)) => {
+                response.headers_mut().insert(name, value);
+            }
+            _ => tracing::warn!("skipping invalid fixture header {:?}", name),
+        }
+    }
+    response
+}
+
+/// Reset fixture sequences and scenarios to their initial state.
+///
+/// Like the health check, this endpoint is not subject to simulated latency
+/// or errors, so test setup can always rely on it.
+///
+/// # Parameters
+///
+/// - `state` - Application state containing fixture state
+///
+/// # Returns
+///
+/// Returns 204 once the state is reset.
+pub async fn reset_fixtures(State(state): State<AppState>) -> impl IntoResponse {
+    state.mock.fixture_state.reset();
+    StatusCode::NO_CONTENT
+}
+
+/// Reload the fixture file, like Prometheus' `/-/reload`.
+///
+/// If the file cannot be loaded, the previous fixtures stay in use.
+///
+/// # Parameters
+///
+/// - `state` - Application state containing the fixtures
+///
+/// # Returns
+///
+/// Returns 200 once reloaded, 404 if no fixture file is configured, or 500
+/// with the error if loading failed.
+pub async fn reload_fixtures(State(state): State<AppState>) -> impl IntoResponse {
+    let mock = state.mock.clone();
+    let result = tokio::task::spawn_blocking(move || mock.reload_fixtures())
+        .await
+        .unwrap_or_else(|e| Err(std::io::Error::other(e).into()));
+    match result {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => {
+            let status = match e {
+                FixtureError::NoFixtureFile => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            (status, format!("failed to reload fixtures: {e}")).into_response()
+        }
+    }
+}
+
+/// Current time in UNIX seconds, honoring a fixed "now".
+fn now_seconds(fixed_now: Option<time::OffsetDateTime>) -> f64 {
+    let now = fixed_now.unwrap_or_else(time::OffsetDateTime::now_utc);
+    now.unix_timestamp_nanos() as f64 / 1e9
+}
+
 /// Convert relative time parameters to string format.
 fn stringify_resolved(input: &str, now: Option<time::OffsetDateTime>) -> String {
     match crate::timeutil::resolve_relative(input, now) {
@@ -135,7 +309,7 @@ mod tests {
 
     use axum::extract::{Query, State};
 
-    use crate::fixtures::{FixtureBook, Matcher, Respond, Route};
+    use crate::fixtures::{FixtureBook, Matcher, Respond, Route, Sequence, SequenceStep};
     use crate::http::state::AppState;
     use crate::storage::MemoryStorage;
 
@@ -152,8 +326,9 @@ mod tests {
                 start: None,
                 end: None,
                 step: None,
+                ..Matcher::default()
             },
-            respond: Respond {
+            respond: Some(Respond {
                 status: None,
                 data: serde_json::json!({
                     "resultType": "vector",
@@ -167,7 +342,9 @@ mod tests {
                 warnings: None,
                 error_type: None,
                 error: None,
-            },
+                ..Respond::default()
+            }),
+            ..Route::default()
         };
 
         // Add test fixture for range query
@@ -178,8 +355,9 @@ mod tests {
                 start: Some("1640995200".to_string()),
                 end: Some("1640998800".to_string()),
                 step: Some("30s".to_string()),
+                ..Matcher::default()
             },
-            respond: Respond {
+            respond: Some(Respond {
                 status: None,
                 data: serde_json::json!({
                     "resultType": "matrix",
@@ -196,7 +374,9 @@ mod tests {
                 warnings: None,
                 error_type: None,
                 error: None,
-            },
+                ..Respond::default()
+            }),
+            ..Route::default()
         };
 
         fixtures.routes = vec![query_fixture, range_fixture];
@@ -222,7 +402,7 @@ mod tests {
     #[tokio::test]
     async fn test_query_with_matching_fixture() {
         let state = create_test_state_with_fixtures();
-        let params = QueryParams { query: "up".to_string() };
+        let params = QueryParams { query: "up".to_string(), time: None };
 
         let response = query(State(state), Query(params)).await;
         let response = response.into_response();
@@ -242,7 +422,7 @@ mod tests {
     #[tokio::test]
     async fn test_query_without_matching_fixture() {
         let state = create_test_state_empty_fixtures();
-        let params = QueryParams { query: "nonexistent_metric".to_string() };
+        let params = QueryParams { query: "nonexistent_metric".to_string(), time: None };
 
         let response = query(State(state), Query(params)).await;
         let response = response.into_response();
@@ -333,7 +513,7 @@ mod tests {
             .build()
             .expect("valid configuration");
 
-        let params = QueryParams { query: "up".to_string() };
+        let params = QueryParams { query: "up".to_string(), time: None };
 
         let response = query(State(state), Query(params)).await;
         let response = response.into_response();
@@ -376,14 +556,17 @@ mod tests {
                 start: None,
                 end: None,
                 step: None,
+                ..Matcher::default()
             },
-            respond: Respond {
+            respond: Some(Respond {
                 status: None,
                 data: serde_json::json!({"resultType": "vector", "result": []}),
                 warnings: Some(vec!["This is a warning".to_string()]),
                 error_type: None,
                 error: None,
-            },
+                ..Respond::default()
+            }),
+            ..Route::default()
         };
 
         fixtures.routes = vec![warning_fixture];
@@ -395,7 +578,7 @@ mod tests {
             .build()
             .expect("valid configuration");
 
-        let params = QueryParams { query: "warning_metric".to_string() };
+        let params = QueryParams { query: "warning_metric".to_string(), time: None };
 
         let response = query(State(state), Query(params)).await;
         let response = response.into_response();
@@ -410,4 +593,248 @@ mod tests {
         assert!(json["warnings"].is_array());
         assert_eq!(json["warnings"][0], "This is a warning");
     }
+
+    /// Test sequence responses advance per request until reset.
+    #[tokio::test]
+    async fn test_reset_fixtures() {
+        let step = |value: &str| SequenceStep {
+            respond: Respond {
+                data: serde_json::json!({"resultType": "scalar", "result": [0, value]}),
+                ..Respond::default()
+            },
+            times: None,
+        };
+        let route = Route {
+            matcher: Matcher {
+                path: "/api/v1/query".to_string(),
+                query: Some("up".to_string()),
+                ..Matcher::default()
+            },
+            sequence: Some(Sequence {
+                responses: vec![step("1"), step("2")],
+                ..Sequence::default()
+            }),
+            ..Route::default()
+        };
+        let fixtures = FixtureBook { routes: vec![route], ..FixtureBook::default() };
+        let state = AppState::builder()
+            .with_storage(Arc::new(MemoryStorage::new()))
+            .with_fixtures(fixtures)
+            .build()
+            .expect("valid configuration");
+
+        let next_value = |state: AppState| async move {
+            let params = QueryParams { query: "up".to_string(), time: None };
+            let response = query(State(state), Query(params)).await.into_response();
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.expect("body");
+            let json: serde_json::Value = serde_json::from_slice(&bytes).expect("parse JSON");
+            json["data"]["result"][1].as_str().map(str::to_string)
+        };
+        assert_eq!(next_value(state.clone()).await.as_deref(), Some("1"));
+        assert_eq!(next_value(state.clone()).await.as_deref(), Some("2"));
+        assert_eq!(next_value(state.clone()).await.as_deref(), Some("2"));
+
+        let response = reset_fixtures(State(state.clone())).await.into_response();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(next_value(state).await.as_deref(), Some("1"));
+    }
+
+    fn create_test_state_with_respond(respond: Respond) -> AppState {
+        let route = Route {
+            matcher: Matcher { path: "/api/v1/query".to_string(), ..Matcher::default() },
+            respond: Some(respond),
+            ..Route::default()
+        };
+        AppState::builder()
+            .with_storage(Arc::new(MemoryStorage::new()))
+            .with_fixtures(FixtureBook { routes: vec![route], ..FixtureBook::default() })
+            .build()
+            .expect("valid configuration")
+    }
+
+    /// Test error fixtures answer with the error type's HTTP status and extra headers.
+    #[tokio::test]
+    async fn test_fixture_http_status_and_headers() {
+        let state = create_test_state_with_respond(Respond {
+            status: Some("error".to_string()),
+            error_type: Some("bad_data".to_string()),
+            error: Some("parse error".to_string()),
+            headers: Some([("Retry-After".to_string(), "5".to_string())].into()),
+            ..Respond::default()
+        });
+        let params = QueryParams { query: "up".to_string(), time: None };
+        let response = query(State(state), Query(params)).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()["retry-after"], "5");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.expect("body");
+        let json: serde_json::Value = serde_json::from_slice(&bytes).expect("parse JSON");
+        assert_eq!(json["errorType"], "bad_data");
+
+        let state = create_test_state_with_respond(Respond {
+            http_status: Some(202),
+            ..Respond::default()
+        });
+        let params = QueryParams { query: "up".to_string(), time: None };
+        let response = query(State(state), Query(params)).await.into_response();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+    }
+
+    /// Test per-fixture delays and failures.
+    #[tokio::test]
+    async fn test_fixture_delay_and_failure_rate() {
+        let delay = std::time::Duration::from_millis(20);
+        let state = create_test_state_with_respond(Respond {
+            delay: Some(crate::fixtures::Delay::Fixed(delay)),
+            ..Respond::default()
+        });
+        let start = std::time::Instant::now();
+        let params = QueryParams { query: "up".to_string(), time: None };
+        let response = query(State(state), Query(params)).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(start.elapsed() >= delay);
+
+        let state = create_test_state_with_respond(Respond {
+            failure_rate: Some(1.0),
+            ..Respond::default()
+        });
+        let params = QueryParams { query: "up".to_string(), time: None };
+        let response = query(State(state), Query(params)).await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    /// Test templated data is rendered against the request and clock.
+    #[tokio::test]
+    async fn test_templated_range_fixture() {
+        let route = Route {
+            matcher: Matcher { path: "/api/v1/query_range".to_string(), ..Matcher::default() },
+            respond: Some(Respond {
+                data: serde_json::json!({"resultType": "matrix", "result": [{
+                    "metric": {"query": "{{ query }}"},
+                    "values": {"$steps": ["{{ t }}", "{{ now - t | string }}"]}
+                }]}),
+                template: true,
+                ..Respond::default()
+            }),
+            ..Route::default()
+        };
+        let state = AppState::builder()
+            .with_storage(Arc::new(MemoryStorage::new()))
+            .with_fixtures(FixtureBook { routes: vec![route], ..FixtureBook::default() })
+            .with_fixed_now(time::macros::datetime!(2022-01-01 01:00:00 UTC))
+            .build()
+            .expect("valid configuration");
+
+        let params = QueryRangeParams {
+            query: "up".to_string(),
+            start: "now-2m".to_string(),
+            end: "now".to_string(),
+            step: "1m".to_string(),
+        };
+        let response = query_range(State(state), Query(params)).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.expect("body");
+        let json: serde_json::Value = serde_json::from_slice(&bytes).expect("parse JSON");
+        assert_eq!(
+            json["data"]["result"][0],
+            serde_json::json!({
+                "metric": {"query": "up"},
+                "values": [[1640998680, "120"], [1640998740, "60"], [1640998800, "0"]]
+            })
+        );
+    }
+
+    /// Test generated series answer range queries per step and instant queries at their time.
+    #[tokio::test]
+    async fn test_generated_series_fixture() {
+        let respond: Respond = serde_yaml::from_str(
+            "generate:\n  - {metric: {__name__: load}, type: steps, values: [1, 2], every: 1m}\n",
+        )
+        .expect("valid respond");
+        let routes = ["/api/v1/query", "/api/v1/query_range"].map(|path| Route {
+            matcher: Matcher { path: path.to_string(), ..Matcher::default() },
+            respond: Some(respond.clone()),
+            ..Route::default()
+        });
+        let state = AppState::builder()
+            .with_storage(Arc::new(MemoryStorage::new()))
+            .with_fixtures(FixtureBook { routes: routes.to_vec(), ..FixtureBook::default() })
+            .with_fixed_now(time::macros::datetime!(2022-01-01 00:01:00 UTC))
+            .build()
+            .expect("valid configuration");
+        let body = |response: Response| async move {
+            assert_eq!(response.status(), StatusCode::OK);
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.expect("body");
+            serde_json::from_slice::<serde_json::Value>(&bytes).expect("parse JSON")
+        };
+
+        let params = QueryParams { query: "load".to_string(), time: None };
+        let json = body(query(State(state.clone()), Query(params)).await.into_response()).await;
+        assert_eq!(json["data"]["result"][0]["value"], serde_json::json!([1640995260, "2"]));
+
+        // An explicit evaluation time replaces now
+        for time in ["1640995230", "now-30s", "2022-01-01T00:00:30Z"] {
+            let params = QueryParams { query: "load".to_string(), time: Some(time.to_string()) };
+            let json = body(query(State(state.clone()), Query(params)).await.into_response()).await;
+            assert_eq!(json["data"]["result"][0]["value"], serde_json::json!([1640995230, "1"]));
+        }
+        let params = QueryParams { query: "load".to_string(), time: Some("soon".to_string()) };
+        let response = query(State(state.clone()), Query(params)).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let params = QueryRangeParams {
+            query: "load".to_string(),
+            start: "now-90s".to_string(),
+            end: "now".to_string(),
+            step: "30s".to_string(),
+        };
+        let json = body(query_range(State(state), Query(params)).await.into_response()).await;
+        assert_eq!(
+            json["data"]["result"][0]["values"],
+            serde_json::json!([
+                [1640995170, "2"],
+                [1640995200, "1"],
+                [1640995230, "1"],
+                [1640995260, "2"]
+            ])
+        );
+    }
+
+    /// Test the reload endpoint swaps fixtures and reports broken files.
+    #[tokio::test]
+    async fn test_reload_fixtures() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("fixtures.yaml");
+        std::fs::write(&path, "routes: []\n").expect("write fixtures");
+        let state = AppState::builder()
+            .with_storage(Arc::new(MemoryStorage::new()))
+            .with_fixtures_path(&path)
+            .build()
+            .expect("valid configuration");
+
+        let ask_up = |state: AppState| async move {
+            let params = QueryParams { query: "up".to_string(), time: None };
+            query(State(state), Query(params)).await.into_response().status()
+        };
+        assert_eq!(ask_up(state.clone()).await, StatusCode::NOT_FOUND);
+
+        let yaml = "routes:\n  - match: {path: /api/v1/query, query: up}\n    respond: {data: 1}\n";
+        std::fs::write(&path, yaml).expect("write fixtures");
+        let response = reload_fixtures(State(state.clone())).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(ask_up(state.clone()).await, StatusCode::OK);
+
+        std::fs::write(&path, "routes: [").expect("write fixtures");
+        let response = reload_fixtures(State(state.clone())).await.into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(ask_up(state).await, StatusCode::OK);
+
+        // Fixtures passed in directly have no file to reload
+        let state = AppState::builder()
+            .with_storage(Arc::new(MemoryStorage::new()))
+            .with_fixtures(FixtureBook::default())
+            .build()
+            .expect("valid configuration");
+        let response = reload_fixtures(State(state)).await.into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
@@ -40,7 +40,7 @@ mod tests {
     use std::time::Duration;
 
     use crate::fixtures::FixtureBook;
-    use crate::http::state::{MockConfig, QueryConfig};
+    use crate::http::state::{AdminConfig, MockConfig, QueryConfig};
     use crate::query_engine::SimpleQueryEngine;
     use crate::storage::MemoryStorage;
 
@@ -66,9 +66,15 @@ mod tests {
             mock: MockConfig {
                 latency: Duration::from_millis(10),
                 error_rate: 0.0,
-                fixtures: std::sync::Arc::new(FixtureBook::default()),
+                fixtures: std::sync::Arc::new(arc_swap::ArcSwap::from_pointee(
+                    FixtureBook::default(),
+                )),
+                fixtures_path: None,
+                fixture_state: std::sync::Arc::default(),
                 fixed_now: None,
+                follows_clock_anchor: false,
             },
+            admin: AdminConfig::default(),
         };
 
         let start = std::time::Instant::now();
@@ -93,9 +99,15 @@ mod tests {
             mock: MockConfig {
                 latency: Duration::ZERO,
                 error_rate: 0.0,
-                fixtures: std::sync::Arc::new(FixtureBook::default()),
+                fixtures: std::sync::Arc::new(arc_swap::ArcSwap::from_pointee(
+                    FixtureBook::default(),
+                )),
+                fixtures_path: None,
+                fixture_state: std::sync::Arc::default(),
                 fixed_now: None,
+                follows_clock_anchor: false,
             },
+            admin: AdminConfig::default(),
         };
 
         let result = maybe_latency_and_error(&state).await;
@@ -116,9 +128,15 @@ mod tests {
             mock: MockConfig {
                 latency: Duration::ZERO,
                 error_rate: 1.0,
-                fixtures: std::sync::Arc::new(FixtureBook::default()),
+                fixtures: std::sync::Arc::new(arc_swap::ArcSwap::from_pointee(
+                    FixtureBook::default(),
+                )),
+                fixtures_path: None,
+                fixture_state: std::sync::Arc::default(),
                 fixed_now: None,
+                follows_clock_anchor: false,
             },
+            admin: AdminConfig::default(),
         };
 
         let result = maybe_latency_and_error(&state).await;
@@ -1,41 +1,63 @@
-//! Metadata API handlers for series, labels, and label values.
+//! Metadata API handlers for series, labels, label values and metric metadata.
+//!
+//! Series, labels and label values read their parameters from the query
+//! string of GET requests and the form body of POST requests.
+
+use std::collections::BTreeSet;
 
 use axum::{
-    extract::{Path, State},
+    extract::{Form, Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     Json,
 };
 
+use crate::http::handlers::fixtures::metadata_fixture;
 use crate::http::handlers::health::maybe_latency_and_error;
+use crate::http::handlers::{api_error, parse_time};
 use crate::http::state::AppState;
 use crate::http::types::PromApiResponse;
+use crate::query_engine::SimpleQueryEngine;
+use crate::storage::Label;
 
 /// Get series matching label selectors.
 ///
+/// A matching fixture route answers first; otherwise storage is used, see
+/// [`select_series`].
+///
 /// # Parameters
 ///
-/// - `state` - Application state containing storage
+/// - `state` - Application state containing fixtures and storage
+/// - `params` - Raw query or form parameters (`match[]`, `start`, `end`)
 ///
 /// # Returns
 ///
-/// Returns series data from storage as JSON response.
-pub async fn series(State(state): State<AppState>) -> impl IntoResponse {
+/// Returns series data from fixtures or storage as JSON response, or 400 for
+/// an invalid selector or time.
+pub async fn series(
+    State(state): State<AppState>,
+    Form(params): Form<Vec<(String, String)>>,
+) -> impl IntoResponse {
     if let Err(code) = maybe_latency_and_error(&state).await {
         return (code, "simulated failure").into_response();
     }
+    if let Some(response) = metadata_fixture(&state, "/api/v1/series", &params).await {
+        return response;
+    }
 
-    let series = state.query.storage.query_series(&[]);
-    let series_data: Vec<serde_json::Value> = series
-        .iter()
-        .map(|ts| {
-            let mut labels = serde_json::Map::new();
-            for label in &ts.labels {
-                labels.insert(label.name.clone(), serde_json::Value::String(label.value.clone()));
-            }
-            serde_json::Value::Object(labels)
-        })
-        .collect();
+    let series_data = match select_series(&state, &params) {
+        Ok(selected) => selected
+            .into_iter()
+            .map(|labels| {
+                let labels = labels
+                    .into_iter()
+                    .map(|label| (label.name, serde_json::Value::String(label.value)))
+                    .collect();
+                serde_json::Value::Object(labels)
+            })
+            .collect(),
+        Err(e) => return api_error(StatusCode::BAD_REQUEST, "bad_data", &e),
+    };
 
     (
         StatusCode::OK,
@@ -52,19 +74,42 @@ pub async fn series(State(state): State<AppState>) -> impl IntoResponse {
 
 /// Get all label names.
 ///
+/// A matching fixture route answers first; otherwise storage is used. With
+/// `match[]`, `start` or `end` only the names of the series
+/// [`select_series`] selects are returned.
+///
 /// # Parameters
 ///
-/// - `state` - Application state containing storage
+/// - `state` - Application state containing fixtures and storage
+/// - `params` - Raw query or form parameters (`match[]`, `start`, `end`)
 ///
 /// # Returns
 ///
-/// Returns array of label names as JSON response.
-pub async fn labels(State(state): State<AppState>) -> impl IntoResponse {
+/// Returns array of label names as JSON response, or 400 for an invalid
+/// selector or time.
+pub async fn labels(
+    State(state): State<AppState>,
+    Form(params): Form<Vec<(String, String)>>,
+) -> impl IntoResponse {
     if let Err(code) = maybe_latency_and_error(&state).await {
         return (code, "simulated failure").into_response();
     }
+    if let Some(response) = metadata_fixture(&state, "/api/v1/labels", &params).await {
+        return response;
+    }
 
-    let names = state.query.storage.label_names();
+    let names = if has_series_filter(&params) {
+        match select_series(&state, &params) {
+            Ok(selected) => {
+                let names: BTreeSet<String> =
+                    selected.into_iter().flatten().map(|label| label.name).collect();
+                names.into_iter().collect()
+            }
+            Err(e) => return api_error(StatusCode::BAD_REQUEST, "bad_data", &e),
+        }
+    } else {
+        state.query.storage.label_names()
+    };
     (
         StatusCode::OK,
         Json(PromApiResponse {
@@ -82,23 +127,49 @@ pub async fn labels(State(state): State<AppState>) -> impl IntoResponse {
 
 /// Get values for a specific label.
 ///
+/// A matching fixture route answers first; otherwise storage is used. With
+/// `match[]`, `start` or `end` only the values of the series
+/// [`select_series`] selects are returned.
+///
 /// # Parameters
 ///
-/// - `state` - Application state containing storage
+/// - `state` - Application state containing fixtures and storage
 /// - `label_name` - Name of the label to get values for
+/// - `params` - Raw query or form parameters (`match[]`, `start`, `end`)
 ///
 /// # Returns
 ///
-/// Returns array of label values as JSON response.
+/// Returns array of label values as JSON response, or 400 for an invalid
+/// selector or time.
 pub async fn label_values(
     State(state): State<AppState>,
     Path(label_name): Path<String>,
+    Form(params): Form<Vec<(String, String)>>,
 ) -> impl IntoResponse {
     if let Err(code) = maybe_latency_and_error(&state).await {
         return (code, "simulated failure").into_response();
     }
+    let path = format!("/api/v1/label/{label_name}/values");
+    if let Some(response) = metadata_fixture(&state, &path, &params).await {
+        return response;
+    }
 
-    let values = state.query.storage.label_values(&label_name);
+    let values = if has_series_filter(&params) {
+        match select_series(&state, &params) {
+            Ok(selected) => {
+                let values: BTreeSet<String> = selected
+                    .into_iter()
+                    .flatten()
+                    .filter(|label| label.name == label_name)
+                    .map(|label| label.value)
+                    .collect();
+                values.into_iter().collect()
+            }
+            Err(e) => return api_error(StatusCode::BAD_REQUEST, "bad_data", &e),
+        }
+    } else {
+        state.query.storage.label_values(&label_name)
+    };
     (
         StatusCode::OK,
         Json(PromApiResponse {
@@ -114,11 +185,103 @@ pub async fn label_values(
         .into_response()
 }
 
+/// Get the metadata (type, help and unit) of metrics.
+///
+/// A matching fixture route answers first. Remote write does not store
+/// metric metadata, so without one the response is an empty object.
+///
+/// # Parameters
+///
+/// - `state` - Application state containing fixtures
+/// - `params` - Raw query parameters, passed on for fixture matching
+///
+/// # Returns
+///
+/// Returns metric metadata from fixtures, or no metadata, as JSON response.
+pub async fn metric_metadata(
+    State(state): State<AppState>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> impl IntoResponse {
+    if let Err(code) = maybe_latency_and_error(&state).await {
+        return (code, "simulated failure").into_response();
+    }
+    if let Some(response) = metadata_fixture(&state, "/api/v1/metadata", &params).await {
+        return response;
+    }
+
+    (
+        StatusCode::OK,
+        Json(PromApiResponse {
+            status: "success",
+            data: Some(serde_json::Value::Object(serde_json::Map::new())),
+            warnings: None,
+            error_type: None,
+            error: None,
+        }),
+    )
+        .into_response()
+}
+
+/// Whether the request narrows the series metadata is taken from.
+fn has_series_filter(params: &[(String, String)]) -> bool {
+    params.iter().any(|(k, _)| matches!(k.as_str(), "match[]" | "start" | "end"))
+}
+
+/// Select the stored series for a metadata request.
+///
+/// Series match any of the `match[]` selectors, or all series without one,
+/// and need a sample between the optional `start` and `end` (Unix seconds or
+/// RFC3339, both inclusive).
+///
+/// # Parameters
+///
+/// - `state` - Application state containing storage
+/// - `params` - Raw query parameters, `match[]` may be repeated
+///
+/// # Returns
+///
+/// Returns the label sets of the selected series, sorted and without
+/// duplicates, or why a selector or time is invalid.
+fn select_series(
+    state: &AppState,
+    params: &[(String, String)],
+) -> Result<BTreeSet<Vec<Label>>, String> {
+    let param = |name: &str| params.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+    let (start, end) =
+        match (parse_time(param("start"), i64::MIN), parse_time(param("end"), i64::MAX)) {
+            (Ok(start), Ok(end)) => (start, end),
+            (Err(e), _) | (_, Err(e)) => return Err(e),
+        };
+
+    let mut matcher_sets = Vec::new();
+    for (_, selector) in params.iter().filter(|(k, _)| k == "match[]") {
+        match SimpleQueryEngine::parse_matchers(selector) {
+            Ok(matchers) => matcher_sets.push(matchers),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    if matcher_sets.is_empty() {
+        matcher_sets.push(Vec::new());
+    }
+
+    // Only labels are needed, so select without copying any samples
+    let mut selected = BTreeSet::new();
+    for matchers in &matcher_sets {
+        let mut set = state.query.storage.select(start, end, matchers);
+        while let Some(view) = set.next() {
+            if !view.samples.is_empty() {
+                selected.insert(view.labels.to_vec());
+            }
+        }
+    }
+    Ok(selected)
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
-    use axum::extract::{Path, State};
+    use axum::extract::{Form, Path, State};
 
     use crate::fixtures::FixtureBook;
     use crate::http::state::AppState;
@@ -169,7 +332,7 @@ mod tests {
     async fn test_series_with_data() {
         let state = create_test_state_with_data();
 
-        let response = series(State(state)).await;
+        let response = series(State(state), Form(vec![])).await;
         let response = response.into_response();
 
         assert_eq!(response.status(), axum::http::StatusCode::OK);
@@ -199,7 +362,7 @@ mod tests {
     async fn test_series_empty() {
         let state = create_test_state_empty();
 
-        let response = series(State(state)).await;
+        let response = series(State(state), Form(vec![])).await;
         let response = response.into_response();
 
         assert_eq!(response.status(), axum::http::StatusCode::OK);
@@ -220,7 +383,7 @@ mod tests {
     async fn test_labels_with_data() {
         let state = create_test_state_with_data();
 
-        let response = labels(State(state)).await;
+        let response = labels(State(state), Form(vec![])).await;
         let response = response.into_response();
 
         assert_eq!(response.status(), axum::http::StatusCode::OK);
@@ -251,7 +414,7 @@ mod tests {
     async fn test_labels_empty() {
         let state = create_test_state_empty();
 
-        let response = labels(State(state)).await;
+        let response = labels(State(state), Form(vec![])).await;
         let response = response.into_response();
 
         assert_eq!(response.status(), axum::http::StatusCode::OK);
@@ -272,7 +435,7 @@ mod tests {
     async fn test_label_values_existing_label() {
         let state = create_test_state_with_data();
 
-        let response = label_values(State(state), Path("job".to_string())).await;
+        let response = label_values(State(state), Path("job".to_string()), Form(vec![])).await;
         let response = response.into_response();
 
         assert_eq!(response.status(), axum::http::StatusCode::OK);
@@ -299,7 +462,8 @@ mod tests {
     async fn test_label_values_nonexistent_label() {
         let state = create_test_state_with_data();
 
-        let response = label_values(State(state), Path("nonexistent_label".to_string())).await;
+        let response =
+            label_values(State(state), Path("nonexistent_label".to_string()), Form(vec![])).await;
         let response = response.into_response();
 
         assert_eq!(response.status(), axum::http::StatusCode::OK);
@@ -320,7 +484,7 @@ mod tests {
     async fn test_label_values_metric_names() {
         let state = create_test_state_with_data();
 
-        let response = label_values(State(state), Path("__name__".to_string())).await;
+        let response = label_values(State(state), Path("__name__".to_string()), Form(vec![])).await;
         let response = response.into_response();
 
         assert_eq!(response.status(), axum::http::StatusCode::OK);
@@ -353,17 +517,17 @@ mod tests {
             .expect("valid configuration");
 
         // Test series endpoint
-        let response = series(State(state.clone())).await;
+        let response = series(State(state.clone()), Form(vec![])).await;
         let response = response.into_response();
         assert_eq!(response.status(), axum::http::StatusCode::SERVICE_UNAVAILABLE);
 
         // Test labels endpoint
-        let response = labels(State(state.clone())).await;
+        let response = labels(State(state.clone()), Form(vec![])).await;
         let response = response.into_response();
         assert_eq!(response.status(), axum::http::StatusCode::SERVICE_UNAVAILABLE);
 
         // Test label_values endpoint
-        let response = label_values(State(state), Path("job".to_string())).await;
+        let response = label_values(State(state), Path("job".to_string()), Form(vec![])).await;
         let response = response.into_response();
         assert_eq!(response.status(), axum::http::StatusCode::SERVICE_UNAVAILABLE);
     }
@@ -373,7 +537,7 @@ mod tests {
     async fn test_label_values_empty_storage() {
         let state = create_test_state_empty();
 
-        let response = label_values(State(state), Path("job".to_string())).await;
+        let response = label_values(State(state), Path("job".to_string()), Form(vec![])).await;
         let response = response.into_response();
 
         assert_eq!(response.status(), axum::http::StatusCode::OK);
@@ -388,4 +552,138 @@ mod tests {
         let values_data = json["data"].as_array().expect("data is array");
         assert_eq!(values_data.len(), 0);
     }
+
+    /// Test fixture routes answer metadata requests before storage.
+    #[tokio::test]
+    async fn test_metadata_fixtures_with_storage_fallback() {
+        let fixtures: FixtureBook = serde_yaml::from_str(
+            r#"
+routes:
+  - match: {path: /api/v1/label/job/values, "match[]": ['up']}
+    respond: {data: ["fixture-job"]}
+  - match: {path: /api/v1/labels}
+    respond: {data: ["__name__", "team"]}
+"#,
+        )
+        .expect("valid fixtures");
+        let state = create_test_state_with_data();
+        state.mock.fixtures.store(Arc::new(fixtures));
+        let data = |response: axum::response::Response| async move {
+            assert_eq!(response.status(), StatusCode::OK);
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.expect("body");
+            serde_json::from_slice::<serde_json::Value>(&bytes).expect("parse JSON")["data"].clone()
+        };
+
+        let params = vec![("match[]".to_string(), "up".to_string())];
+        let response = label_values(State(state.clone()), Path("job".to_string()), Form(params))
+            .await
+            .into_response();
+        assert_eq!(data(response).await, serde_json::json!(["fixture-job"]));
+
+        // Without the selector the fixture does not match and storage answers
+        let response = label_values(State(state.clone()), Path("job".to_string()), Form(vec![]))
+            .await
+            .into_response();
+        assert_eq!(data(response).await, serde_json::json!(["api", "worker"]));
+
+        let response = labels(State(state.clone()), Form(vec![])).await.into_response();
+        assert_eq!(data(response).await, serde_json::json!(["__name__", "team"]));
+
+        state.mock.fixtures.store(Arc::new(FixtureBook::default()));
+        let response = series(State(state), Form(vec![])).await.into_response();
+        assert_eq!(data(response).await.as_array().map(Vec::len), Some(2));
+    }
+
+    /// Test the storage fallback only answers with series selected by `match[]`, `start` and `end`.
+    #[tokio::test]
+    async fn test_storage_fallback_filters_series() {
+        let state = create_test_state_with_data();
+        let data = |response: axum::response::Response| async move {
+            assert_eq!(response.status(), StatusCode::OK);
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.expect("body");
+            serde_json::from_slice::<serde_json::Value>(&bytes).expect("parse JSON")["data"].clone()
+        };
+        let params = |pairs: &[(&str, &str)]| {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<Vec<_>>()
+        };
+
+        let response =
+            series(State(state.clone()), Form(params(&[("match[]", "test_metric")]))).await;
+        assert_eq!(
+            data(response.into_response()).await,
+            serde_json::json!([
+                {"__name__": "test_metric", "instance": "localhost:8080", "job": "api"}
+            ])
+        );
+
+        // Selectors are combined, series matching several are listed once
+        let selectors =
+            params(&[("match[]", r#"{job=~"api|worker"}"#), ("match[]", "test_metric")]);
+        let response = series(State(state.clone()), Form(selectors)).await;
+        assert_eq!(data(response.into_response()).await.as_array().map(Vec::len), Some(2));
+
+        let response =
+            labels(State(state.clone()), Form(params(&[("match[]", r#"{job="worker"}"#)]))).await;
+        assert_eq!(
+            data(response.into_response()).await,
+            serde_json::json!(["__name__", "environment", "instance", "job"])
+        );
+        let selector = params(&[("match[]", r#"{environment="prod"}"#)]);
+        let response = label_values(State(state.clone()), Path("job".to_string()), Form(selector));
+        assert_eq!(data(response.await.into_response()).await, serde_json::json!(["worker"]));
+
+        // Both samples are at 1640995200
+        let window = params(&[("start", "1640995201"), ("end", "2022-01-02T00:00:00Z")]);
+        let response = series(State(state.clone()), Form(window)).await;
+        assert_eq!(data(response.into_response()).await, serde_json::json!([]));
+        let window = params(&[("start", "1640995200"), ("end", "1640995200")]);
+        let response = label_values(State(state.clone()), Path("job".to_string()), Form(window));
+        assert_eq!(
+            data(response.await.into_response()).await,
+            serde_json::json!(["api", "worker"])
+        );
+
+        for bad in [params(&[("match[]", "up{job=")]), params(&[("match[]", "up"), ("end", "x")])] {
+            let response = series(State(state.clone()), Form(bad)).await.into_response();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        let response = labels(State(state), Form(params(&[("match[]", r#"{job=""}"#)]))).await;
+        assert_eq!(response.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    /// Test series, labels and label values accept POST form bodies, and metric metadata is served.
+    #[tokio::test]
+    async fn test_metadata_routes() {
+        let state = create_test_state_with_data();
+        let fixtures: FixtureBook = serde_yaml::from_str(
+            r#"
+routes:
+  - match: {path: /api/v1/metadata}
+    respond:
+      data: {up: [{type: gauge, help: "Target is up.", unit: ""}]}
+"#,
+        )
+        .expect("valid fixtures");
+        let server = axum_test::TestServer::new(crate::http::build_router(state.clone()))
+            .expect("test server");
+        let data = |response: axum_test::TestResponse| {
+            response.assert_status_ok();
+            response.json::<serde_json::Value>()["data"].clone()
+        };
+
+        let response = server.post("/api/v1/series").form(&[("match[]", "test_metric")]).await;
+        assert_eq!(data(response).as_array().map(Vec::len), Some(1));
+        let response = server.post("/api/v1/labels").form(&[("match[]", "test_metric")]).await;
+        assert_eq!(data(response), serde_json::json!(["__name__", "instance", "job"]));
+        let response =
+            server.post("/api/v1/label/job/values").form(&[("match[]", "another_metric")]).await;
+        assert_eq!(data(response), serde_json::json!(["worker"]));
+
+        assert_eq!(data(server.get("/api/v1/metadata").await), serde_json::json!({}));
+        state.mock.fixtures.store(Arc::new(fixtures));
+        assert_eq!(
+            data(server.get("/api/v1/metadata").await),
+            serde_json::json!({"up": [{"type": "gauge", "help": "Target is up.", "unit": ""}]})
+        );
+    }
 }
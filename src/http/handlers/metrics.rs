@@ -0,0 +1,104 @@
+//! Self-metrics handler exposing ingestion counters in the text format.
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+
+use crate::http::exposition::{render_families, MetricFamily, TEXT_CONTENT_TYPE};
+use crate::http::state::AppState;
+use crate::storage::Label;
+
+/// Serve the server's own metrics for scraping.
+///
+/// Like the health check, this endpoint is not subject to simulated latency
+/// or errors.
+///
+/// # Parameters
+///
+/// - `state` - Application state containing storage
+///
+/// # Returns
+///
+/// Returns storage ingestion counters in Prometheus text format.
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let stats = state.query.storage.ingest_stats();
+    let reason = |reason: &str| vec![Label::new("reason", reason)];
+    let families = [
+        MetricFamily {
+            name: "prom_mock_storage_series",
+            help: "Number of series in storage.",
+            kind: "gauge",
+            series: vec![(vec![], stats.series as f64)],
+        },
+        MetricFamily {
+            name: "prom_mock_storage_samples_appended_total",
+            help: "Total number of samples written to storage.",
+            kind: "counter",
+            series: vec![(vec![], stats.samples_appended as f64)],
+        },
+        MetricFamily {
+            name: "prom_mock_storage_samples_rejected_total",
+            help: "Total number of samples rejected by storage.",
+            kind: "counter",
+            series: vec![
+                (reason("duplicate"), stats.duplicate_samples as f64),
+                (reason("out_of_bounds"), stats.out_of_bounds_samples as f64),
+                (reason("out_of_order"), stats.out_of_order_samples as f64),
+            ],
+        },
+        MetricFamily {
+            name: "prom_mock_storage_series_rejected_total",
+            help: "Total number of new series rejected by the series limit.",
+            kind: "counter",
+            series: vec![(vec![], stats.rejected_series as f64)],
+        },
+    ];
+
+    (StatusCode::OK, [(header::CONTENT_TYPE, TEXT_CONTENT_TYPE)], render_families(&families))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::extract::State;
+
+    use crate::http::state::AppState;
+    use crate::storage::{IngestPolicy, MemoryStorage, Sample, Storage, TimeSeries};
+
+    use super::*;
+
+    /// Test rejected samples show up in the self-metrics.
+    #[tokio::test]
+    async fn test_metrics_count_rejections() {
+        let policy = IngestPolicy::Reject { out_of_order_window: Duration::ZERO };
+        let storage = Arc::new(MemoryStorage::new().with_ingest_policy(policy));
+        let ts = |samples: &[(i64, f64)]| TimeSeries {
+            labels: vec![Label::new("__name__", "up")],
+            samples: samples.iter().map(|(t, v)| Sample::new(*t, *v)).collect(),
+        };
+        assert!(storage.try_add_series(ts(&[(1000, 1.0), (2000, 1.0)])).is_ok());
+        assert!(storage.try_add_series(ts(&[(2000, 2.0), (500, 1.0), (3000, 1.0)])).is_err());
+
+        let state = AppState::builder().with_storage(storage).build().expect("valid configuration");
+        let response = metrics(State(state)).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], TEXT_CONTENT_TYPE);
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.expect("body");
+        let body = String::from_utf8(bytes.to_vec()).expect("utf-8 body");
+        for line in [
+            "prom_mock_storage_series 1\n",
+            "prom_mock_storage_samples_appended_total 3\n",
+            "prom_mock_storage_samples_rejected_total{reason=\"duplicate\"} 1\n",
+            "prom_mock_storage_samples_rejected_total{reason=\"out_of_bounds\"} 0\n",
+            "prom_mock_storage_samples_rejected_total{reason=\"out_of_order\"} 1\n",
+            "prom_mock_storage_series_rejected_total 0\n",
+        ] {
+            assert!(body.contains(line), "missing {line:?} in:\n{body}");
+        }
+    }
+}
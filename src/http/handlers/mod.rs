@@ -1,14 +1,76 @@
 //! HTTP handlers for different API endpoints.
 
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use time::format_description::well_known::Rfc3339;
+
+pub mod admin;
+pub mod federate;
 pub mod fixtures;
 pub mod health;
 pub mod metadata;
+pub mod metrics;
 pub mod query;
 pub mod remote_write;
+pub mod status;
 
 // Re-export handlers for easier access
-pub use fixtures::{query, query_range};
+pub use admin::{clean_tombstones, delete_series, snapshot};
+pub use federate::federate;
+pub use fixtures::{query, query_range, reload_fixtures, reset_fixtures};
 pub use health::healthz;
-pub use metadata::{label_values, labels, series};
+pub use metadata::{label_values, labels, metric_metadata, series};
+pub use metrics::metrics;
 pub use query::{query_range_simple, query_simple};
 pub use remote_write::remote_write;
+pub use status::tsdb_status;
+
+/// Convert seconds to milliseconds (Prometheus uses millisecond timestamps).
+const SECONDS_TO_MILLISECONDS: f64 = 1000.0;
+
+/// Build a Prometheus API error response.
+pub(crate) fn api_error(
+    status: StatusCode,
+    error_type: &str,
+    error: &str,
+) -> axum::response::Response {
+    let body = serde_json::json!({ "status": "error", "errorType": error_type, "error": error });
+    (status, Json(body)).into_response()
+}
+
+/// Parse an optional Unix seconds or RFC3339 time into milliseconds.
+pub(crate) fn parse_time(value: Option<&str>, default: i64) -> Result<i64, String> {
+    let Some(value) = value else {
+        return Ok(default);
+    };
+    let invalid = || format!("cannot parse {value:?} to a valid timestamp");
+    if let Ok(seconds) = value.parse::<f64>() {
+        // NaN and infinities would silently become 0 and the i64 bounds
+        if !seconds.is_finite() {
+            return Err(invalid());
+        }
+        return Ok((seconds * SECONDS_TO_MILLISECONDS).round() as i64);
+    }
+    time::OffsetDateTime::parse(value, &Rfc3339)
+        .map(|t| (t.unix_timestamp_nanos() / 1_000_000) as i64)
+        .map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test parsing API request times.
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time(None, i64::MIN), Ok(i64::MIN));
+        assert_eq!(parse_time(Some("1640995200"), 0), Ok(1_640_995_200_000));
+        assert_eq!(parse_time(Some("1.5"), 0), Ok(1_500));
+        assert_eq!(parse_time(Some("2022-01-01T00:00:00Z"), 0), Ok(1_640_995_200_000));
+        for invalid in ["soon", "NaN", "inf", "-inf", "infinity"] {
+            assert_eq!(
+                parse_time(Some(invalid), 0),
+                Err(format!("cannot parse {invalid:?} to a valid timestamp"))
+            );
+        }
+    }
+}
@@ -21,10 +21,6 @@ const SECONDS_TO_MILLISECONDS: i64 = 1000;
 /// Convert milliseconds to seconds (Prometheus API returns seconds in JSON).
 const MILLISECONDS_TO_SECONDS: i64 = 1000;
 
-/// Default query lookback period in milliseconds (5 minutes).
-/// When no specific time range is provided, we look back this far from current time.
-const DEFAULT_LOOKBACK_MS: i64 = 5 * 60 * 1000; // 5 minutes * 60 seconds * 1000 ms
-
 /// Simple query using in-memory storage.
 ///
 /// # Parameters
@@ -46,8 +42,7 @@ pub async fn query_simple(
     let now = state.query.fixed_now.unwrap_or_else(time::OffsetDateTime::now_utc);
     let timestamp = now.unix_timestamp() * SECONDS_TO_MILLISECONDS;
 
-    let query_result =
-        state.query.query_engine.query(&params.query, timestamp - DEFAULT_LOOKBACK_MS, timestamp);
+    let query_result = state.query.query_engine.query_instant(&params.query, timestamp);
 
     match query_result {
         Ok(result) => build_vector_response(result, timestamp),
@@ -249,7 +244,7 @@ mod tests {
     #[tokio::test]
     async fn test_query_simple_with_data() {
         let state = create_test_state_with_data();
-        let params = QueryParams { query: "test_metric".to_string() };
+        let params = QueryParams { query: "test_metric".to_string(), time: None };
 
         let response = query_simple(State(state), Query(params)).await;
         let response = response.into_response();
@@ -261,7 +256,7 @@ mod tests {
     #[tokio::test]
     async fn test_query_simple_empty() {
         let state = create_test_state_empty();
-        let params = QueryParams { query: "nonexistent_metric".to_string() };
+        let params = QueryParams { query: "nonexistent_metric".to_string(), time: None };
 
         let response = query_simple(State(state), Query(params)).await;
         let response = response.into_response();
@@ -452,7 +447,7 @@ mod tests {
             .build()
             .expect("valid configuration");
 
-        let params = QueryParams { query: "test_metric".to_string() };
+        let params = QueryParams { query: "test_metric".to_string(), time: None };
 
         let response = query_simple(State(state), Query(params)).await;
         let response = response.into_response();
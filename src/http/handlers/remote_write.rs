@@ -17,7 +17,8 @@ use tracing::{debug, warn};
 use crate::http::handlers::health::maybe_latency_and_error;
 use crate::http::state::AppState;
 use crate::storage::{
-    FullStorage, Label as StorageLabel, Sample as StorageSample, TimeSeries as StorageTimeSeries,
+    FullStorage, Label as StorageLabel, Sample as StorageSample, StorageError,
+    TimeSeries as StorageTimeSeries,
 };
 
 // Include the generated protobuf code
@@ -58,6 +59,9 @@ pub async fn remote_write(
 /// # Returns
 ///
 /// Returns HTTP 204 on success, or error status with message on failure.
+/// Data rejected by storage limits is reported after the rest of the request
+/// was written: 429 when the series limit is reached, 400 for out-of-bounds,
+/// out-of-order and duplicate samples.
 fn handle_remote_write_impl(
     State(storage): State<Arc<dyn FullStorage>>,
     headers: &HeaderMap,
@@ -86,18 +90,42 @@ fn handle_remote_write_impl(
 
     debug!("received remote write request with {} series", write_request.timeseries.len());
 
-    // Convert protobuf to our internal format and store
+    // Convert protobuf to our internal format and store, keeping the first
+    // rejection unless a later write failed to persist, which senders retry
+    let mut rejection: Option<StorageError> = None;
     for proto_ts in write_request.timeseries {
         let labels: Vec<StorageLabel> =
             proto_ts.labels.into_iter().map(|l| StorageLabel::new(l.name, l.value)).collect();
 
-        let mut ts = StorageTimeSeries::new(labels);
-
-        for proto_sample in proto_ts.samples {
-            ts.add_sample(StorageSample::new(proto_sample.timestamp, proto_sample.value));
+        // Samples are passed on in request order, so storage sees duplicates
+        let samples = proto_ts
+            .samples
+            .into_iter()
+            .map(|s| StorageSample::new(s.timestamp, s.value))
+            .collect();
+        let ts = StorageTimeSeries { labels, samples };
+
+        match storage.try_add_series(ts) {
+            Ok(()) => {}
+            Err(e @ StorageError::Wal(_)) => rejection = Some(e),
+            Err(e) => {
+                if !matches!(rejection, Some(StorageError::Wal(_))) {
+                    rejection.get_or_insert(e);
+                }
+            }
         }
+    }
 
-        storage.add_series(ts);
+    if let Some(e) = rejection {
+        warn!("remote write partially rejected: {}", e);
+        let status = match e {
+            StorageError::TooManySeries { .. } => StatusCode::TOO_MANY_REQUESTS,
+            StorageError::OutOfBounds { .. }
+            | StorageError::OutOfOrder { .. }
+            | StorageError::DuplicateSample { .. } => StatusCode::BAD_REQUEST,
+            StorageError::Wal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        return (status, e.to_string()).into_response();
     }
 
     // Return 204 No Content on success (standard for remote write)
@@ -333,4 +361,122 @@ mod tests {
         assert_eq!(series.len(), 1);
         assert_eq!(series[0].samples[0].value, 42.0);
     }
+
+    /// Test staleness markers survive remote write with their exact bit pattern.
+    #[test]
+    fn test_handle_remote_write_impl_stale_marker() {
+        let storage: Arc<dyn FullStorage> = Arc::new(MemoryStorage::new());
+        let labels = vec![Label { name: "__name__".to_string(), value: "up".to_string() }];
+
+        let write_request = WriteRequest {
+            timeseries: vec![TimeSeries {
+                labels,
+                samples: vec![
+                    Sample { timestamp: 1640995200000, value: 1.0 },
+                    Sample {
+                        timestamp: 1640995230000,
+                        value: f64::from_bits(crate::storage::STALE_NAN_BITS),
+                    },
+                ],
+            }],
+        };
+
+        let mut buf = Vec::new();
+        write_request.encode(&mut buf).expect("encode protobuf");
+
+        let response =
+            handle_remote_write_impl(State(storage.clone()), &HeaderMap::new(), buf.into());
+        assert_eq!(response.into_response().status(), axum::http::StatusCode::NO_CONTENT);
+
+        let series = storage.query_series(&[]);
+        assert_eq!(series[0].samples.len(), 2);
+        assert!(!series[0].samples[0].is_stale());
+        assert!(series[0].samples[1].is_stale());
+    }
+
+    fn encode_series(series: &[(&str, i64)]) -> Bytes {
+        let write_request = WriteRequest {
+            timeseries: series
+                .iter()
+                .map(|(name, timestamp)| TimeSeries {
+                    labels: vec![Label { name: "__name__".to_string(), value: name.to_string() }],
+                    samples: vec![Sample { timestamp: *timestamp, value: 1.0 }],
+                })
+                .collect(),
+        };
+        let mut buf = Vec::new();
+        write_request.encode(&mut buf).expect("encode protobuf");
+        buf.into()
+    }
+
+    async fn read_body(response: axum::response::Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.expect("body");
+        String::from_utf8(bytes.to_vec()).expect("utf-8 body")
+    }
+
+    /// Test the series limit rejects new series with 429 after storing the rest.
+    #[tokio::test]
+    async fn test_handle_remote_write_impl_series_limit() {
+        use crate::storage::StorageLimits;
+
+        let limits = StorageLimits { max_series: Some(2), ..Default::default() };
+        let storage: Arc<dyn FullStorage> = Arc::new(MemoryStorage::new().with_limits(limits));
+
+        let body = encode_series(&[("a", 1000), ("b", 1000), ("c", 1000)]);
+        let response = handle_remote_write_impl(State(storage.clone()), &HeaderMap::new(), body)
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(read_body(response).await, "too many series: limit of 2 active series reached");
+        assert_eq!(storage.query_series(&[]).len(), 2);
+
+        // Existing series still accept samples
+        let body = encode_series(&[("a", 2000)]);
+        let response = handle_remote_write_impl(State(storage.clone()), &HeaderMap::new(), body);
+        assert_eq!(response.into_response().status(), axum::http::StatusCode::NO_CONTENT);
+    }
+
+    /// Test samples outside the retention window are rejected with 400.
+    #[tokio::test]
+    async fn test_handle_remote_write_impl_out_of_bounds() {
+        use crate::storage::StorageLimits;
+
+        let limits = StorageLimits {
+            retention: Some(std::time::Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let storage: Arc<dyn FullStorage> = Arc::new(MemoryStorage::new().with_limits(limits));
+
+        let body = encode_series(&[("a", 1_000_000)]);
+        let response = handle_remote_write_impl(State(storage.clone()), &HeaderMap::new(), body);
+        assert_eq!(response.into_response().status(), axum::http::StatusCode::NO_CONTENT);
+
+        let body = encode_series(&[("a", 900_000), ("b", 990_000)]);
+        let response = handle_remote_write_impl(State(storage.clone()), &HeaderMap::new(), body)
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+        assert!(read_body(response).await.starts_with("out of bounds"));
+        assert_eq!(storage.query_series(&[]).len(), 2);
+    }
+
+    /// Test the reject policy answers 400 for out-of-order samples after storing the rest.
+    #[tokio::test]
+    async fn test_handle_remote_write_impl_out_of_order() {
+        use crate::storage::IngestPolicy;
+
+        let policy = IngestPolicy::Reject { out_of_order_window: std::time::Duration::ZERO };
+        let storage: Arc<dyn FullStorage> =
+            Arc::new(MemoryStorage::new().with_ingest_policy(policy));
+
+        let body = encode_series(&[("a", 2_000)]);
+        let response = handle_remote_write_impl(State(storage.clone()), &HeaderMap::new(), body);
+        assert_eq!(response.into_response().status(), axum::http::StatusCode::NO_CONTENT);
+
+        let body = encode_series(&[("a", 1_000), ("b", 1_000)]);
+        let response = handle_remote_write_impl(State(storage.clone()), &HeaderMap::new(), body)
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+        assert!(read_body(response).await.starts_with("out of order sample"));
+        assert_eq!(storage.query_series(&[]).len(), 2);
+        assert_eq!(storage.ingest_stats().out_of_order_samples, 1);
+    }
 }
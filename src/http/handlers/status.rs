@@ -0,0 +1,172 @@
+//! Status API handlers reporting on stored data.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+
+use crate::http::handlers::api_error;
+use crate::http::handlers::health::maybe_latency_and_error;
+use crate::http::state::AppState;
+use crate::storage::Stat;
+
+/// Number of entries per statistics list when no `limit` is given.
+const DEFAULT_STATS_LIMIT: usize = 10;
+
+/// Get cardinality statistics of the stored series.
+///
+/// # Parameters
+///
+/// - `state` - Application state containing storage
+/// - `params` - Raw query parameters, `limit` caps each list (default 10)
+///
+/// # Returns
+///
+/// Returns the statistics as JSON, or 400 if `limit` is not a positive number.
+pub async fn tsdb_status(
+    State(state): State<AppState>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> impl IntoResponse {
+    if let Err(code) = maybe_latency_and_error(&state).await {
+        return (code, "simulated failure").into_response();
+    }
+
+    let limit = match params.iter().find(|(k, _)| k == "limit") {
+        None => DEFAULT_STATS_LIMIT,
+        Some((_, value)) => match value.parse::<usize>() {
+            Ok(limit) if limit > 0 => limit,
+            _ => {
+                return api_error(
+                    StatusCode::BAD_REQUEST,
+                    "bad_data",
+                    "limit must be a positive number",
+                )
+            }
+        },
+    };
+
+    let stats = state.query.storage.tsdb_stats(limit);
+    let body = serde_json::json!({
+        "status": "success",
+        "data": {
+            "headStats": {
+                "numSeries": stats.head.num_series,
+                "chunkCount": stats.head.chunk_count,
+                "minTime": stats.head.min_time,
+                "maxTime": stats.head.max_time
+            },
+            "seriesCountByMetricName": build_stats(&stats.series_count_by_metric_name),
+            "labelValueCountByLabelName": build_stats(&stats.label_value_count_by_label_name),
+            "memoryInBytesByLabelName": build_stats(&stats.memory_in_bytes_by_label_name),
+            "seriesCountByLabelValuePair": build_stats(&stats.series_count_by_label_value_pair)
+        }
+    });
+    (StatusCode::OK, Json(body)).into_response()
+}
+
+/// Convert a statistics list to `[{"name": ..., "value": ...}]`.
+fn build_stats(stats: &[Stat]) -> serde_json::Value {
+    stats.iter().map(|s| serde_json::json!({ "name": s.name, "value": s.value })).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::extract::{Query, State};
+
+    use crate::http::state::AppState;
+    use crate::storage::{Label, MemoryStorage, Sample, Storage, TimeSeries};
+
+    use super::*;
+
+    fn create_test_state() -> AppState {
+        let storage = Arc::new(MemoryStorage::new().with_shard_count(4));
+        for (name, instance) in
+            [("up", "a"), ("up", "b"), ("up", "c"), ("http_requests_total", "a")]
+        {
+            let mut ts = TimeSeries::new(vec![
+                Label::new("__name__", name),
+                Label::new("instance", instance),
+            ]);
+            ts.add_sample(Sample::new(1_000, 1.0));
+            ts.add_sample(Sample::new(5_000, 1.0));
+            storage.add_series(ts);
+        }
+        AppState::builder().with_storage(storage).build().expect("valid configuration")
+    }
+
+    async fn get_json(
+        state: AppState,
+        params: Vec<(String, String)>,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = tsdb_status(State(state), Query(params)).await.into_response();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.expect("body");
+        (status, serde_json::from_slice(&bytes).expect("parse JSON"))
+    }
+
+    /// Test statistics are merged across shards and ranked by value.
+    #[tokio::test]
+    async fn test_tsdb_status() {
+        let (status, json) = get_json(create_test_state(), vec![]).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let data = &json["data"];
+        assert_eq!(
+            data["headStats"],
+            serde_json::json!({"numSeries": 4, "chunkCount": 4, "minTime": 1000, "maxTime": 5000})
+        );
+        assert_eq!(
+            data["seriesCountByMetricName"],
+            serde_json::json!([
+                {"name": "up", "value": 3},
+                {"name": "http_requests_total", "value": 1}
+            ])
+        );
+        assert_eq!(
+            data["labelValueCountByLabelName"],
+            serde_json::json!([
+                {"name": "instance", "value": 3},
+                {"name": "__name__", "value": 2}
+            ])
+        );
+        assert_eq!(
+            data["memoryInBytesByLabelName"],
+            serde_json::json!([
+                {"name": "__name__", "value": 21},
+                {"name": "instance", "value": 3}
+            ])
+        );
+        assert_eq!(
+            data["seriesCountByLabelValuePair"][0],
+            serde_json::json!({"name": "__name__=up", "value": 3})
+        );
+        assert_eq!(data["seriesCountByLabelValuePair"].as_array().map(Vec::len), Some(5));
+    }
+
+    /// Test the limit parameter caps every list and must be positive.
+    #[tokio::test]
+    async fn test_tsdb_status_limit() {
+        let params = vec![("limit".to_string(), "1".to_string())];
+        let (status, json) = get_json(create_test_state(), params).await;
+        assert_eq!(status, StatusCode::OK);
+        for list in [
+            "seriesCountByMetricName",
+            "labelValueCountByLabelName",
+            "memoryInBytesByLabelName",
+            "seriesCountByLabelValuePair",
+        ] {
+            assert_eq!(json["data"][list].as_array().map(Vec::len), Some(1), "{list}");
+        }
+
+        for limit in ["0", "-1", "ten"] {
+            let params = vec![("limit".to_string(), limit.to_string())];
+            let (status, json) = get_json(create_test_state(), params).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(json["errorType"], "bad_data");
+        }
+    }
+}
@@ -1,5 +1,6 @@
 //! HTTP server with Prometheus-compatible API endpoints and configurable mock behavior.
 
+pub mod exposition;
 pub mod handlers;
 pub mod routes;
 pub mod state;
@@ -20,17 +20,29 @@ use crate::http::state::AppState;
 pub fn build_router(state: AppState) -> Router {
     Router::new()
         .route("/healthz", get(healthz))
+        .route("/metrics", get(metrics))
+        // Fixture state control
+        .route("/-/reload", post(reload_fixtures).put(reload_fixtures))
+        .route("/-/fixtures/reset", post(reset_fixtures))
         // Prometheus Query API (original fixture-based)
         .route("/api/v1/query", get(query))
         .route("/api/v1/query_range", get(query_range))
         // Additional Prometheus API endpoints
-        .route("/api/v1/series", get(series))
-        .route("/api/v1/labels", get(labels))
-        .route("/api/v1/label/{name}/values", get(label_values))
+        .route("/api/v1/series", get(series).post(series))
+        .route("/api/v1/labels", get(labels).post(labels))
+        .route("/api/v1/label/{name}/values", get(label_values).post(label_values))
+        .route("/api/v1/metadata", get(metric_metadata))
+        .route("/api/v1/status/tsdb", get(tsdb_status))
         // Remote Write API
         .route("/api/v1/write", post(remote_write))
+        // Federation of stored series in text exposition format
+        .route("/federate", get(federate))
         // Query API with in-memory storage fallback
         .route("/api/v1/query_simple", get(query_simple))
         .route("/api/v1/query_range_simple", get(query_range_simple))
+        // TSDB admin API, answers 503 unless enabled
+        .route("/api/v1/admin/tsdb/delete_series", post(delete_series).put(delete_series))
+        .route("/api/v1/admin/tsdb/clean_tombstones", post(clean_tombstones).put(clean_tombstones))
+        .route("/api/v1/admin/tsdb/snapshot", post(snapshot).put(snapshot))
         .with_state(state)
 }
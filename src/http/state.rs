@@ -1,11 +1,16 @@
 //! Application state and configuration for the HTTP server.
 
 use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use crate::fixtures::FixtureBook;
+use arc_swap::ArcSwap;
+
+use crate::fixtures::{FixtureBook, FixtureError, FixtureState};
 use crate::query_engine::SimpleQueryEngine;
 use crate::storage::FullStorage;
+use crate::timeutil::ClockAnchor;
 
 /// Query-related configuration and dependencies.
 ///
@@ -27,14 +32,33 @@ pub struct QueryConfig {
 /// separated from core query functionality.
 #[derive(Clone, Debug)]
 pub struct MockConfig {
-    /// Fixture data for predefined responses
-    pub fixtures: Arc<FixtureBook>,
+    /// Fixture data for predefined responses, replaced on reload
+    pub fixtures: Arc<ArcSwap<FixtureBook>>,
+    /// File the fixtures were loaded from, if any
+    pub fixtures_path: Option<PathBuf>,
+    /// Sequence positions and scenario states of the fixtures
+    pub fixture_state: Arc<FixtureState>,
     /// Artificial delay added to all responses
     pub latency: std::time::Duration,
     /// Probability (0.0-1.0) of returning 503 errors
     pub error_rate: f32,
     /// Fixed timestamp for deterministic responses (testing only)
     pub fixed_now: Option<time::OffsetDateTime>,
+    /// Whether `fixed_now` was resolved from the fixtures' `clock_anchor`,
+    /// which reloads then must not change
+    pub follows_clock_anchor: bool,
+}
+
+/// TSDB admin API configuration.
+///
+/// The admin endpoints modify or copy stored data, so like in Prometheus
+/// they are disabled unless explicitly enabled.
+#[derive(Clone, Debug, Default)]
+pub struct AdminConfig {
+    /// Whether the `/api/v1/admin/tsdb/*` endpoints are enabled
+    pub enabled: bool,
+    /// Directory admin snapshots are written to
+    pub snapshot_dir: PathBuf,
 }
 
 /// Application state shared across all HTTP handlers.
@@ -47,6 +71,8 @@ pub struct AppState {
     pub query: QueryConfig,
     /// Mock behavior configuration
     pub mock: MockConfig,
+    /// Admin API configuration
+    pub admin: AdminConfig,
 }
 
 impl QueryConfig {
@@ -83,7 +109,121 @@ impl MockConfig {
         error_rate: f32,
         fixed_now: Option<time::OffsetDateTime>,
     ) -> Self {
-        Self { fixtures: Arc::new(fixtures), latency, error_rate, fixed_now }
+        Self {
+            fixtures: Arc::new(ArcSwap::from_pointee(fixtures)),
+            fixtures_path: None,
+            fixture_state: Arc::new(FixtureState::new()),
+            latency,
+            error_rate,
+            fixed_now,
+            follows_clock_anchor: false,
+        }
+    }
+}
+
+impl MockConfig {
+    /// Reload the fixtures from their file.
+    ///
+    /// On success the new fixtures replace the old ones for all following
+    /// requests and sequences and scenarios start over. On failure the
+    /// previous fixtures stay in use.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FixtureError::NoFixtureFile` if the fixtures were not loaded
+    /// from a file, `FixtureError::ClockAnchorChanged` if `now` follows the
+    /// fixtures' `clock_anchor` and the new fixtures change it, or another
+    /// `FixtureError` if the file cannot be read or parsed.
+    pub fn reload_fixtures(&self) -> Result<(), FixtureError> {
+        let Some(path) = &self.fixtures_path else {
+            return Err(FixtureError::NoFixtureFile);
+        };
+        let loaded = FixtureBook::load_from_path(path).and_then(|book| {
+            self.check_clock_anchor(&book)?;
+            Ok(book)
+        });
+        match loaded {
+            Ok(book) => {
+                let routes = book.routes.len();
+                self.fixtures.store(Arc::new(book));
+                self.fixture_state.reset();
+                tracing::info!("reloaded {} fixture routes from {}", routes, path.display());
+                Ok(())
+            }
+            Err(e) => {
+                tracing::error!("failed to reload fixtures, keeping previous ones: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Check reloaded fixtures keep the `clock_anchor` `fixed_now` was resolved from.
+    ///
+    /// `fixed_now` is resolved once at startup, so a changed anchor would
+    /// silently not apply.
+    fn check_clock_anchor(&self, book: &FixtureBook) -> Result<(), FixtureError> {
+        if !self.follows_clock_anchor {
+            return Ok(());
+        }
+        let current = self.fixtures.load();
+        if current.clock_anchor()? == book.clock_anchor()? {
+            return Ok(());
+        }
+        let anchor = |book: &FixtureBook| {
+            let anchor = book.defaults.as_ref().and_then(|d| d.clock_anchor.as_deref());
+            anchor.unwrap_or("none").to_string()
+        };
+        Err(FixtureError::ClockAnchorChanged { from: anchor(&current), to: anchor(book) })
+    }
+
+    /// Reload the fixtures in the background whenever their files change.
+    ///
+    /// The modification time and size of the fixture path and of every file
+    /// and directory the fixtures were loaded from are polled every `interval`.
+    ///
+    /// # Parameters
+    ///
+    /// - `interval` - Time between checks for changes
+    ///
+    /// # Returns
+    ///
+    /// Returns the handle of the spawned task, or `None` without a fixture file.
+    pub fn spawn_fixture_watcher(&self, interval: Duration) -> Option<tokio::task::JoinHandle<()>> {
+        let path = self.fixtures_path.clone()?;
+        let mock = self.clone();
+        // Read the current version now, so changes made right after count
+        let mut last = mock.fixtures_version(&path);
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let current = mock.fixtures_version(&path);
+                if current.is_none() || current == last {
+                    continue;
+                }
+                let reload = mock.clone();
+                // Errors are logged, the previous fixtures stay in use
+                let _ = tokio::task::spawn_blocking(move || reload.reload_fixtures()).await;
+                // A reload may change the set of source files
+                last = mock.fixtures_version(&path);
+            }
+        }))
+    }
+
+    /// Modification times and sizes of the fixture path and source files.
+    ///
+    /// Returns `None` while the fixture path itself is missing, e.g. while
+    /// an editor replaces the file.
+    fn fixtures_version(&self, path: &Path) -> Option<Vec<Option<(SystemTime, u64)>>> {
+        let modified = |path: &Path| -> Option<(SystemTime, u64)> {
+            let meta = std::fs::metadata(path).ok()?;
+            Some((meta.modified().ok()?, meta.len()))
+        };
+        modified(path)?;
+        let book = self.fixtures.load();
+        let sources = book.sources.iter().map(PathBuf::as_path);
+        Some(std::iter::once(path).chain(sources).map(modified).collect())
     }
 }
 
@@ -110,7 +250,7 @@ impl AppState {
     ) -> Self {
         let query = QueryConfig::new(storage, fixed_now);
         let mock = MockConfig::new(fixtures, latency, error_rate, fixed_now);
-        Self { query, mock }
+        Self { query, mock, admin: AdminConfig::default() }
     }
 
     /// Get a builder for configuring application state step by step.
@@ -131,9 +271,12 @@ impl AppState {
 pub struct AppStateBuilder {
     storage: Option<Arc<dyn FullStorage>>,
     fixtures: Option<FixtureBook>,
+    fixtures_path: Option<PathBuf>,
     fixed_now: Option<time::OffsetDateTime>,
+    clock_anchor: Option<ClockAnchor>,
     latency: Option<std::time::Duration>,
     error_rate: Option<f32>,
+    admin_snapshot_dir: Option<PathBuf>,
 }
 
 impl AppStateBuilder {
@@ -173,6 +316,23 @@ impl AppStateBuilder {
         self
     }
 
+    /// Load the fixtures from a file that can be reloaded at runtime.
+    ///
+    /// The file is read by [`AppStateBuilder::build`] and replaces fixtures
+    /// set with [`AppStateBuilder::with_fixtures`].
+    ///
+    /// # Parameters
+    ///
+    /// - `path` - Path to the YAML fixtures file
+    ///
+    /// # Returns
+    ///
+    /// Returns the builder for method chaining.
+    pub fn with_fixtures_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.fixtures_path = Some(path.into());
+        self
+    }
+
     /// Set a fixed timestamp for deterministic testing.
     ///
     /// # Parameters
@@ -187,6 +347,22 @@ impl AppStateBuilder {
         self
     }
 
+    /// Set the reference time, overriding the fixtures' `clock_anchor`.
+    ///
+    /// A time set with [`AppStateBuilder::with_fixed_now`] takes precedence.
+    ///
+    /// # Parameters
+    ///
+    /// - `anchor` - Live clock, clock frozen at build time, or a fixed time
+    ///
+    /// # Returns
+    ///
+    /// Returns the builder for method chaining.
+    pub fn with_clock_anchor(mut self, anchor: ClockAnchor) -> Self {
+        self.clock_anchor = Some(anchor);
+        self
+    }
+
     /// Set artificial latency for response simulation.
     ///
     /// # Parameters
@@ -215,6 +391,20 @@ impl AppStateBuilder {
         self
     }
 
+    /// Enable the TSDB admin API.
+    ///
+    /// # Parameters
+    ///
+    /// - `snapshot_dir` - Directory the snapshot endpoint writes to
+    ///
+    /// # Returns
+    ///
+    /// Returns the builder for method chaining.
+    pub fn with_admin_api(mut self, snapshot_dir: impl Into<PathBuf>) -> Self {
+        self.admin_snapshot_dir = Some(snapshot_dir.into());
+        self
+    }
+
     /// Build the final AppState with validation.
     ///
     /// # Returns
@@ -223,7 +413,8 @@ impl AppStateBuilder {
     ///
     /// # Errors
     ///
-    /// Returns error if storage is not provided or if error_rate is invalid.
+    /// Returns error if storage is not provided, if error_rate is invalid,
+    /// if the fixture file cannot be loaded or if its clock anchor is invalid.
     pub fn build(self) -> io::Result<AppState> {
         // Validate required dependencies
         let storage = self.storage.ok_or(io::Error::new(
@@ -242,11 +433,33 @@ impl AppStateBuilder {
         }
 
         // Use defaults for optional values
-        let fixtures = self.fixtures.unwrap_or_default();
+        let fixtures = match &self.fixtures_path {
+            Some(path) => FixtureBook::load_from_path(path).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("failed to load fixtures: {e}"))
+            })?,
+            None => self.fixtures.unwrap_or_default(),
+        };
         let latency = self.latency.unwrap_or_default();
         let error_rate = self.error_rate.unwrap_or(0.0);
 
-        Ok(AppState::new(fixtures, self.fixed_now, latency, error_rate, storage))
+        // An explicit fixed now or anchor overrides the fixtures' clock_anchor
+        let anchor = match self.clock_anchor {
+            Some(anchor) => Some(anchor),
+            None => fixtures
+                .clock_anchor()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?,
+        };
+        let startup = time::OffsetDateTime::now_utc();
+        let startup = startup.replace_nanosecond(0).unwrap_or(startup);
+        let fixed_now = self.fixed_now.or_else(|| anchor.and_then(|a| a.fixed_now(startup)));
+
+        let mut state = AppState::new(fixtures, fixed_now, latency, error_rate, storage);
+        state.mock.fixtures_path = self.fixtures_path;
+        state.mock.follows_clock_anchor = self.fixed_now.is_none() && self.clock_anchor.is_none();
+        if let Some(snapshot_dir) = self.admin_snapshot_dir {
+            state.admin = AdminConfig { enabled: true, snapshot_dir };
+        }
+        Ok(state)
     }
 }
 
@@ -317,6 +530,22 @@ mod tests {
         assert!(builder.fixed_now.is_none());
         assert!(builder.latency.is_none());
         assert!(builder.error_rate.is_none());
+        assert!(builder.admin_snapshot_dir.is_none());
+    }
+
+    /// Test the admin API is disabled unless enabled on the builder.
+    #[test]
+    fn test_app_state_builder_with_admin_api() {
+        let state = AppStateBuilder::new().with_storage(create_test_storage()).build().unwrap();
+        assert!(!state.admin.enabled);
+
+        let state = AppStateBuilder::new()
+            .with_storage(create_test_storage())
+            .with_admin_api("snapshots")
+            .build()
+            .unwrap();
+        assert!(state.admin.enabled);
+        assert_eq!(state.admin.snapshot_dir, PathBuf::from("snapshots"));
     }
 
     /// Test AppStateBuilder with_storage.
@@ -467,4 +696,159 @@ mod tests {
         assert_eq!(state.mock.error_rate, 0.1);
         assert_eq!(state.mock.fixed_now, Some(now));
     }
+
+    /// Test the fixtures' clock anchor sets fixed now unless overridden.
+    #[test]
+    fn test_app_state_builder_clock_anchor() {
+        let book = |anchor: &str| FixtureBook {
+            defaults: Some(crate::fixtures::Defaults {
+                status: None,
+                clock_anchor: Some(anchor.to_string()),
+            }),
+            ..FixtureBook::default()
+        };
+        let anchored = time::macros::datetime!(2025-08-03 00:00:00 UTC);
+        let builder = |anchor: &str| {
+            AppState::builder().with_storage(create_test_storage()).with_fixtures(book(anchor))
+        };
+
+        let state = builder("2025-08-03T00:00:00Z").build().expect("valid configuration");
+        assert_eq!(state.mock.fixed_now, Some(anchored));
+        assert_eq!(state.query.fixed_now, Some(anchored));
+
+        let state = builder("now").build().expect("valid configuration");
+        assert_eq!(state.mock.fixed_now, None);
+
+        let before = time::OffsetDateTime::now_utc() - Duration::from_secs(1);
+        let state = builder("startup").build().expect("valid configuration");
+        assert!(state.mock.fixed_now.is_some_and(|now| now >= before));
+
+        let state = builder("2025-08-03T00:00:00Z")
+            .with_clock_anchor(ClockAnchor::Now)
+            .build()
+            .expect("valid configuration");
+        assert_eq!(state.mock.fixed_now, None);
+
+        let fixed = time::macros::datetime!(2022-01-01 00:00:00 UTC);
+        let state = builder("startup")
+            .with_clock_anchor(ClockAnchor::Startup)
+            .with_fixed_now(fixed)
+            .build()
+            .expect("valid configuration");
+        assert_eq!(state.mock.fixed_now, Some(fixed));
+
+        assert!(builder("yesterday").build().is_err());
+    }
+
+    fn write_fixtures(path: &std::path::Path, queries: &[&str]) {
+        let routes: String = queries
+            .iter()
+            .map(|q| {
+                format!(
+                    "  - match: {{path: /api/v1/query, query: {q}}}\n    respond: {{data: 1}}\n"
+                )
+            })
+            .collect();
+        std::fs::write(path, format!("routes:\n{routes}")).expect("write fixtures");
+    }
+
+    fn build_with_fixtures_path(path: &std::path::Path) -> AppState {
+        AppState::builder()
+            .with_storage(create_test_storage())
+            .with_fixtures_path(path)
+            .build()
+            .expect("valid configuration")
+    }
+
+    /// Test reloading replaces the fixtures and keeps them when the file is broken.
+    #[test]
+    fn test_reload_fixtures() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("fixtures.yaml");
+        write_fixtures(&path, &["up"]);
+        let state = build_with_fixtures_path(&path);
+        assert_eq!(state.mock.fixtures.load().routes.len(), 1);
+
+        write_fixtures(&path, &["up", "down"]);
+        state.mock.reload_fixtures().expect("reload");
+        assert_eq!(state.mock.fixtures.load().routes.len(), 2);
+
+        std::fs::write(&path, "routes: [").expect("write fixtures");
+        assert!(matches!(
+            state.mock.reload_fixtures(),
+            Err(FixtureError::InFile { source, .. }) if matches!(*source, FixtureError::Yaml(_))
+        ));
+        assert_eq!(state.mock.fixtures.load().routes.len(), 2);
+
+        // The anchor now was resolved from must stay, unless it is overridden
+        let anchored = |anchor: &str| format!("defaults: {{clock_anchor: {anchor:?}}}\n");
+        std::fs::write(&path, anchored("2025-08-03T00:00:00Z")).expect("write fixtures");
+        let state = build_with_fixtures_path(&path);
+        write_fixtures(&path, &["up"]);
+        assert!(matches!(
+            state.mock.reload_fixtures(),
+            Err(FixtureError::ClockAnchorChanged { ref from, ref to })
+                if from == "2025-08-03T00:00:00Z" && to == "none"
+        ));
+        std::fs::write(&path, anchored("2025-08-04T00:00:00Z")).expect("write fixtures");
+        assert!(state.mock.reload_fixtures().is_err());
+        std::fs::write(&path, anchored(" 2025-08-03T00:00:00Z")).expect("write fixtures");
+        state.mock.reload_fixtures().expect("same anchor");
+        let overridden = AppState::builder()
+            .with_storage(create_test_storage())
+            .with_fixtures_path(&path)
+            .with_clock_anchor(ClockAnchor::Startup)
+            .build()
+            .expect("valid configuration");
+        write_fixtures(&path, &["up"]);
+        overridden.mock.reload_fixtures().expect("anchor is overridden");
+
+        // A missing file fails the build instead of starting without fixtures
+        let missing = AppState::builder()
+            .with_storage(create_test_storage())
+            .with_fixtures_path(dir.path().join("missing.yaml"))
+            .build();
+        assert_eq!(missing.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+    }
+
+    /// Test the watcher picks up changes to the fixture file.
+    #[tokio::test]
+    async fn test_fixture_watcher() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("fixtures.yaml");
+        write_fixtures(&path, &["up"]);
+        let state = build_with_fixtures_path(&path);
+        let watcher =
+            state.mock.spawn_fixture_watcher(Duration::from_millis(10)).expect("fixture file");
+
+        write_fixtures(&path, &["up", "down", "sideways"]);
+        let reloaded = tokio::time::timeout(Duration::from_secs(5), async {
+            while state.mock.fixtures.load().routes.len() != 3 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        watcher.abort();
+        assert!(reloaded.is_ok(), "fixtures were not reloaded");
+
+        // Changes to included files are picked up as well
+        let included = dir.path().join("included.yaml");
+        write_fixtures(&included, &["included"]);
+        std::fs::write(&path, "include: [included.yaml]\n").expect("write fixtures");
+        state.mock.reload_fixtures().expect("reload");
+        let watcher =
+            state.mock.spawn_fixture_watcher(Duration::from_millis(10)).expect("fixture file");
+        write_fixtures(&included, &["included", "twice"]);
+        let reloaded = tokio::time::timeout(Duration::from_secs(5), async {
+            while state.mock.fixtures.load().routes.len() != 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        watcher.abort();
+        assert!(reloaded.is_ok(), "included fixtures were not reloaded");
+
+        let no_file = AppState::builder().with_storage(create_test_storage()).build().unwrap();
+        assert!(no_file.mock.spawn_fixture_watcher(Duration::from_millis(10)).is_none());
+    }
 }
@@ -7,6 +7,8 @@ use serde::{Deserialize, Serialize};
 pub struct QueryParams {
     /// PromQL query string
     pub query: String,
+    /// Evaluation time (Unix timestamp, RFC3339 or relative), "now" if unset
+    pub time: Option<String>,
 }
 
 /// Query range parameters for the `/api/v1/query_range` endpoint.
@@ -39,4 +39,4 @@ pub mod timeutil;
 pub use fixtures::FixtureBook;
 pub use matchers::{EqualMatcher, LabelMatcher, NotEqualMatcher, NotRegexMatcher, RegexMatcher};
 pub use query_engine::SimpleQueryEngine;
-pub use storage::{Label, MemoryStorage, Sample, Storage, TimeSeries};
+pub use storage::{Label, MemoryStorage, Sample, SeriesSet, SeriesView, Storage, TimeSeries};
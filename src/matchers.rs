@@ -32,6 +32,37 @@ pub trait LabelMatcher: Send + Sync + std::fmt::Debug {
     ///
     /// Returns the name of the label this matcher filters on.
     fn label_name(&self) -> &str;
+
+    /// Describe how this matcher can be resolved against a label index.
+    ///
+    /// Storage backends use the hint to look up postings instead of scanning
+    /// every series. Custom matchers keep the default and are evaluated with
+    /// [`LabelMatcher::matches`] on the remaining candidates.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `IndexHint` for this matcher.
+    fn index_hint(&self) -> IndexHint<'_> {
+        IndexHint::Unsupported
+    }
+}
+
+/// Index lookup strategy for a label matcher.
+///
+/// Each hint must select exactly the series accepted by the matcher's
+/// [`LabelMatcher::matches`] implementation.
+#[derive(Debug, Clone, Copy)]
+pub enum IndexHint<'a> {
+    /// Series having the label with exactly this value.
+    Equal(&'a str),
+    /// Series not having the label with this value.
+    NotEqual(&'a str),
+    /// Series having the label with a value matching the pattern.
+    Regex(&'a Regex),
+    /// Series not having the label with a value matching the pattern.
+    NotRegex(&'a Regex),
+    /// The matcher cannot be resolved through the index.
+    Unsupported,
 }
 
 /// Equality matcher for exact label value matching.
@@ -65,6 +96,10 @@ impl LabelMatcher for EqualMatcher {
     fn label_name(&self) -> &str {
         &self.name
     }
+
+    fn index_hint(&self) -> IndexHint<'_> {
+        IndexHint::Equal(&self.value)
+    }
 }
 
 /// Not-equality matcher for excluding specific label values.
@@ -98,6 +133,10 @@ impl LabelMatcher for NotEqualMatcher {
     fn label_name(&self) -> &str {
         &self.name
     }
+
+    fn index_hint(&self) -> IndexHint<'_> {
+        IndexHint::NotEqual(&self.value)
+    }
 }
 
 /// Regex matcher for pattern-based label value matching.
@@ -131,6 +170,10 @@ impl LabelMatcher for RegexMatcher {
     fn label_name(&self) -> &str {
         &self.name
     }
+
+    fn index_hint(&self) -> IndexHint<'_> {
+        IndexHint::Regex(&self.pattern)
+    }
 }
 
 /// Not-regex matcher for excluding pattern-based label values.
@@ -164,6 +207,10 @@ impl LabelMatcher for NotRegexMatcher {
     fn label_name(&self) -> &str {
         &self.name
     }
+
+    fn index_hint(&self) -> IndexHint<'_> {
+        IndexHint::NotRegex(&self.pattern)
+    }
 }
 
 #[cfg(test)]
@@ -254,6 +301,22 @@ mod tests {
         assert_eq!(not_regex_matcher.label_name(), "not_regex_label");
     }
 
+    /// Test index hints for the built-in matcher types.
+    #[test]
+    fn test_index_hints() {
+        let matcher = EqualMatcher::new("job", "api");
+        assert!(matches!(matcher.index_hint(), IndexHint::Equal("api")));
+
+        let matcher = NotEqualMatcher::new("job", "api");
+        assert!(matches!(matcher.index_hint(), IndexHint::NotEqual("api")));
+
+        let matcher = RegexMatcher::new("job", Regex::new("a.*").expect("valid regex"));
+        assert!(matches!(matcher.index_hint(), IndexHint::Regex(re) if re.as_str() == "a.*"));
+
+        let matcher = NotRegexMatcher::new("job", Regex::new("a.*").expect("valid regex"));
+        assert!(matches!(matcher.index_hint(), IndexHint::NotRegex(re) if re.as_str() == "a.*"));
+    }
+
     /// Test complex regex patterns and special cases.
     #[test]
     fn test_complex_regex_patterns() {
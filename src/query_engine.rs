@@ -1,8 +1,11 @@
 //! Simple query engine for basic metric selectors without full `PromQL`.
 //!
 //! This module provides a basic query parser and executor that supports
-//! simple metric selectors like `metric{label="value"}` without requiring
-//! a full `PromQL` implementation.
+//! simple metric selectors like `metric{label="value"}` and `<agg>_over_time`
+//! range functions without requiring a full `PromQL` implementation.
+//!
+//! Prometheus staleness markers are honored: they end a series for instant
+//! evaluation and are never returned as sample values.
 
 use std::io;
 use std::sync::Arc;
@@ -10,7 +13,10 @@ use std::sync::Arc;
 use regex::Regex;
 
 use crate::matchers::{EqualMatcher, LabelMatcher, NotEqualMatcher, NotRegexMatcher, RegexMatcher};
-use crate::storage::Storage;
+use crate::storage::{Label, Sample, Storage};
+
+/// Lookback window for instant evaluation in milliseconds (5 minutes, as in Prometheus).
+pub const LOOKBACK_DELTA_MS: i64 = 5 * 60 * 1000;
 
 /// Simple query parser for basic selectors like: metric{a="b",c!="d",e=~"regex"}.
 #[derive(Clone)]
@@ -32,25 +38,145 @@ impl SimpleQueryEngine {
         Self { storage }
     }
 
-    /// Parse and execute a simple metric selector query
+    /// Parse and execute a simple metric selector query.
+    ///
+    /// Staleness markers are excluded from the returned samples, as they are
+    /// from Prometheus range vectors.
     pub fn query(&self, query: &str, start: i64, end: i64) -> io::Result<QueryResult> {
-        let selector = Self::parse_selector(query)?;
-        let series = self.storage.query_series(&selector.matchers);
+        let selector = match Self::parse_expr(query)? {
+            Expr::Selector(selector) => selector,
+            Expr::OverTime { .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "range functions are only supported in instant queries",
+                ))
+            }
+        };
 
+        // Only the requested window is copied out of storage
         let mut result_series = Vec::new();
-        for ts in series {
-            let samples = ts.samples_in_range(start, end);
+        let mut set = self.storage.select(start, end, &selector.matchers);
+        while let Some(view) = set.next() {
+            let samples: Vec<Sample> =
+                view.samples.iter().filter(|s| !s.is_stale()).cloned().collect();
             if !samples.is_empty() {
-                result_series.push(QueryResultSeries {
-                    labels: ts.labels.clone(),
-                    samples: samples.into_iter().cloned().collect(),
-                });
+                result_series.push(QueryResultSeries { labels: view.labels.to_vec(), samples });
+            }
+        }
+
+        Ok(QueryResult { series: result_series })
+    }
+
+    /// Evaluate a selector or `<aggregation>_over_time` expression at a single point in time.
+    ///
+    /// Plain selectors reduce every matching series to its latest sample within
+    /// [`LOOKBACK_DELTA_MS`] before `time`; a series whose latest sample is a
+    /// staleness marker is absent from the result. Range functions aggregate
+    /// the non-stale samples in `(time - range, time]`.
+    ///
+    /// # Parameters
+    ///
+    /// - `query` - Expression to evaluate
+    /// - `time` - Evaluation timestamp in milliseconds
+    ///
+    /// # Returns
+    ///
+    /// Returns a `QueryResult` where each series holds exactly one sample.
+    pub fn query_instant(&self, query: &str, time: i64) -> io::Result<QueryResult> {
+        let mut result_series = Vec::new();
+
+        match Self::parse_expr(query)? {
+            Expr::Selector(selector) => {
+                let mint = time - LOOKBACK_DELTA_MS + 1;
+                let mut set = self.storage.select(mint, time, &selector.matchers);
+                while let Some(view) = set.next() {
+                    let Some(sample) = view.samples.last() else { continue };
+                    if sample.is_stale() {
+                        continue;
+                    }
+                    let samples = vec![sample.clone()];
+                    result_series.push(QueryResultSeries { labels: view.labels.to_vec(), samples });
+                }
+            }
+            Expr::OverTime { function, selector, range } => {
+                let mut set = self.storage.select(time - range + 1, time, &selector.matchers);
+                while let Some(view) = set.next() {
+                    let values: Vec<f64> =
+                        view.samples.iter().filter(|s| !s.is_stale()).map(|s| s.value).collect();
+                    let Some(value) = function.apply(&values) else { continue };
+                    let labels =
+                        view.labels.iter().filter(|l| l.name != "__name__").cloned().collect();
+                    let samples = vec![Sample::new(time, value)];
+                    result_series.push(QueryResultSeries { labels, samples });
+                }
             }
         }
 
         Ok(QueryResult { series: result_series })
     }
 
+    /// Parse a series selector like `metric{a="b"}` into label matchers.
+    ///
+    /// # Parameters
+    ///
+    /// - `selector` - Series selector, as used in `match[]` parameters
+    ///
+    /// # Returns
+    ///
+    /// Returns the label matchers of the selector.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the selector is invalid or selects nothing but
+    /// empty label values, which would match every series.
+    pub fn parse_matchers(selector: &str) -> io::Result<Vec<Arc<dyn LabelMatcher>>> {
+        let matchers = Self::parse_selector(selector)?.matchers;
+        if matchers.iter().all(|m| m.matches(&[Label::new(m.label_name(), "")])) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "match[] must contain at least one non-empty matcher",
+            ));
+        }
+        Ok(matchers)
+    }
+
+    /// Parse an expression: a selector or `<aggregation>_over_time(selector[range])`.
+    fn parse_expr(query: &str) -> io::Result<Expr> {
+        let query = query.trim();
+
+        // Function calls look like `<name>_over_time(...)`; anything else is a selector
+        let call = query.find('(').map(|pos| (query[..pos].trim(), &query[pos..]));
+        let Some((name, args)) = call.filter(|(name, _)| name.ends_with("_over_time")) else {
+            return Self::parse_selector(query).map(Expr::Selector);
+        };
+        let function =
+            RangeFunction::from_name(name.trim_end_matches("_over_time")).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("unknown function: {name}"))
+            })?;
+
+        let inner = args.strip_prefix('(').and_then(|r| r.strip_suffix(')')).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "invalid function call syntax")
+        })?;
+        let inner = inner.trim();
+        let (selector, range) =
+            inner.strip_suffix(']').and_then(|r| r.rsplit_once('[')).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "expected range vector selector")
+            })?;
+        let range = humantime::parse_duration(range.trim()).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("invalid range duration: {e}"))
+        })?;
+        let range = i64::try_from(range.as_millis())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "range duration too large"))?;
+        if range <= 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "range duration must be positive",
+            ));
+        }
+
+        Ok(Expr::OverTime { function, selector: Self::parse_selector(selector)?, range })
+    }
+
     /// Parse a simple selector like: metric{a="b",c!="d",e=~"regex"}
     fn parse_selector(query: &str) -> io::Result<MetricSelector> {
         let query = query.trim();
@@ -200,6 +326,56 @@ struct MetricSelector {
     matchers: Vec<Arc<dyn LabelMatcher>>,
 }
 
+/// Parsed query expression.
+#[derive(Debug)]
+enum Expr {
+    /// Plain metric selector
+    Selector(MetricSelector),
+    /// `<function>_over_time(selector[range])`
+    OverTime { function: RangeFunction, selector: MetricSelector, range: i64 },
+}
+
+/// Aggregations available as `<name>_over_time` range functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RangeFunction {
+    Avg,
+    Min,
+    Max,
+    Sum,
+    Count,
+    Last,
+}
+
+impl RangeFunction {
+    /// Look up a range function by the prefix of its `_over_time` name.
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "avg" => Some(Self::Avg),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "sum" => Some(Self::Sum),
+            "count" => Some(Self::Count),
+            "last" => Some(Self::Last),
+            _ => None,
+        }
+    }
+
+    /// Aggregate sample values, returning `None` for an empty range.
+    #[allow(clippy::cast_precision_loss)]
+    fn apply(self, values: &[f64]) -> Option<f64> {
+        let last = *values.last()?;
+        let value = match self {
+            Self::Avg => values.iter().sum::<f64>() / values.len() as f64,
+            Self::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            Self::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Self::Sum => values.iter().sum(),
+            Self::Count => values.len() as f64,
+            Self::Last => last,
+        };
+        Some(value)
+    }
+}
+
 /// Query result containing time series.
 #[derive(Debug)]
 pub struct QueryResult {
@@ -209,13 +385,13 @@ pub struct QueryResult {
 #[derive(Debug)]
 pub struct QueryResultSeries {
     pub labels: Vec<crate::storage::Label>,
-    pub samples: Vec<crate::storage::Sample>,
+    pub samples: Vec<Sample>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::storage::{Label, MemoryStorage, Sample, TimeSeries};
+    use crate::storage::{Label, MemoryStorage, TimeSeries};
 
     /// Test parsing of simple metric selectors with and without labels.
     #[test]
@@ -342,6 +518,18 @@ mod tests {
         assert!(result.is_err());
     }
 
+    /// Test parsing match[] selectors rejects selectors matching everything.
+    #[test]
+    fn test_parse_matchers() {
+        let matchers = SimpleQueryEngine::parse_matchers(r#"up{job="api"}"#).expect("valid");
+        assert_eq!(matchers.len(), 2);
+        assert!(SimpleQueryEngine::parse_matchers(r#"{job=~".+"}"#).is_ok());
+
+        assert!(SimpleQueryEngine::parse_matchers("{}").is_err());
+        assert!(SimpleQueryEngine::parse_matchers(r#"{job=~".*"}"#).is_err());
+        assert!(SimpleQueryEngine::parse_matchers(r#"{job=""}"#).is_err());
+    }
+
     /// Test regex matchers (=~ and !~).
     #[test]
     fn test_regex_matchers() {
@@ -425,6 +613,29 @@ mod tests {
         assert_eq!(result.series.len(), 0); // No samples in range, so no series
     }
 
+    /// Test instant evaluation picks the latest sample inside the lookback window.
+    #[test]
+    fn test_query_instant() {
+        let storage = Arc::new(MemoryStorage::new());
+        let engine = SimpleQueryEngine::new(storage.clone());
+
+        let mut ts = TimeSeries::new(vec![Label::new("__name__", "cpu_usage")]);
+        ts.add_sample(Sample::new(1_000_000, 10.0));
+        ts.add_sample(Sample::new(1_060_000, 20.0));
+        ts.add_sample(Sample::new(1_120_000, 30.0));
+        storage.add_series(ts);
+
+        // Evaluation between samples uses the latest preceding one
+        let result = engine.query_instant("cpu_usage", 1_090_000).expect("valid query");
+        assert_eq!(result.series.len(), 1);
+        assert_eq!(result.series[0].samples, vec![Sample::new(1_060_000, 20.0)]);
+
+        // Samples older than the lookback window are ignored
+        let result =
+            engine.query_instant("cpu_usage", 1_120_000 + LOOKBACK_DELTA_MS + 1).expect("valid");
+        assert!(result.series.is_empty());
+    }
+
     /// Test query with complex selector and multiple series.
     #[test]
     fn test_complex_query() {
@@ -474,4 +685,153 @@ mod tests {
             engine.query(r#"http_requests{job=~".*api.*"}"#, 0, 2000).expect("valid query");
         assert_eq!(result.series.len(), 2); // Both API series (GET and POST)
     }
+
+    fn add_samples(storage: &MemoryStorage, labels: Vec<Label>, samples: &[Sample]) {
+        let mut ts = TimeSeries::new(labels);
+        for sample in samples {
+            ts.add_sample(sample.clone());
+        }
+        storage.add_series(ts);
+    }
+
+    /// Test that a series disappears from instant results after a staleness marker.
+    #[test]
+    fn test_instant_query_honors_staleness() {
+        let storage = Arc::new(MemoryStorage::new());
+        let engine = SimpleQueryEngine::new(storage.clone());
+
+        add_samples(
+            &storage,
+            vec![Label::new("__name__", "up"), Label::new("job", "api")],
+            &[Sample::new(10_000, 1.0), Sample::new(20_000, 1.0), Sample::stale_marker(30_000)],
+        );
+        add_samples(
+            &storage,
+            vec![Label::new("__name__", "up"), Label::new("job", "web")],
+            &[Sample::new(10_000, 1.0), Sample::new(40_000, 1.0)],
+        );
+
+        // Before the marker both series are present
+        let result = engine.query_instant("up", 25_000).expect("valid query");
+        assert_eq!(result.series.len(), 2);
+
+        // From the marker on the series is gone, even though older samples are within lookback
+        let result = engine.query_instant("up", 30_000).expect("valid query");
+        assert_eq!(result.series.len(), 1);
+        assert_eq!(result.series[0].labels[1], Label::new("job", "web"));
+        let result = engine.query_instant("up", 45_000).expect("valid query");
+        assert_eq!(result.series.len(), 1);
+
+        // A new sample after the marker brings the series back
+        add_samples(
+            &storage,
+            vec![Label::new("__name__", "up"), Label::new("job", "api")],
+            &[Sample::new(50_000, 2.0)],
+        );
+        let result = engine.query_instant(r#"up{job="api"}"#, 55_000).expect("valid query");
+        assert_eq!(result.series.len(), 1);
+        assert_eq!(result.series[0].samples, vec![Sample::new(50_000, 2.0)]);
+    }
+
+    /// Test that ordinary NaN values are not treated as staleness markers.
+    #[test]
+    fn test_instant_query_keeps_plain_nan() {
+        let storage = Arc::new(MemoryStorage::new());
+        let engine = SimpleQueryEngine::new(storage.clone());
+
+        add_samples(
+            &storage,
+            vec![Label::new("__name__", "ratio")],
+            &[Sample::new(1000, f64::NAN)],
+        );
+
+        let result = engine.query_instant("ratio", 2000).expect("valid query");
+        assert_eq!(result.series.len(), 1);
+        assert!(result.series[0].samples[0].value.is_nan());
+    }
+
+    /// Test that range queries drop staleness markers from returned samples.
+    #[test]
+    fn test_range_query_excludes_stale_markers() {
+        let storage = Arc::new(MemoryStorage::new());
+        let engine = SimpleQueryEngine::new(storage.clone());
+
+        add_samples(
+            &storage,
+            vec![Label::new("__name__", "up")],
+            &[Sample::new(1000, 1.0), Sample::stale_marker(2000), Sample::new(3000, 1.0)],
+        );
+        add_samples(
+            &storage,
+            vec![Label::new("__name__", "up"), Label::new("job", "gone")],
+            &[Sample::new(500, 1.0), Sample::stale_marker(1500)],
+        );
+
+        let result = engine.query("up", 0, 4000).expect("valid query");
+        assert_eq!(result.series.len(), 2);
+        for series in &result.series {
+            assert!(series.samples.iter().all(|s| !s.is_stale()));
+        }
+
+        // A range holding only a staleness marker yields no series
+        let result = engine.query("up", 1500, 2500).expect("valid query");
+        assert!(result.series.is_empty());
+    }
+
+    /// Test `_over_time` functions skip staleness markers.
+    #[test]
+    fn test_over_time_excludes_stale_markers() {
+        let storage = Arc::new(MemoryStorage::new());
+        let engine = SimpleQueryEngine::new(storage.clone());
+
+        add_samples(
+            &storage,
+            vec![Label::new("__name__", "queue_depth"), Label::new("job", "api")],
+            &[
+                Sample::new(10_000, 2.0),
+                Sample::new(20_000, 4.0),
+                Sample::stale_marker(30_000),
+                Sample::new(40_000, 6.0),
+            ],
+        );
+
+        let cases = [
+            ("count_over_time", 3.0),
+            ("sum_over_time", 12.0),
+            ("avg_over_time", 4.0),
+            ("min_over_time", 2.0),
+            ("max_over_time", 6.0),
+            ("last_over_time", 6.0),
+        ];
+        for (function, expected) in cases {
+            let query = format!("{function}(queue_depth[1m])");
+            let result = engine.query_instant(&query, 45_000).expect("valid query");
+            assert_eq!(result.series.len(), 1, "{function}");
+            assert_eq!(result.series[0].samples, vec![Sample::new(45_000, expected)], "{function}");
+            // Functions drop the metric name
+            assert_eq!(result.series[0].labels, vec![Label::new("job", "api")]);
+        }
+
+        // A window holding only a staleness marker produces no output
+        let result =
+            engine.query_instant("count_over_time(queue_depth[5s])", 32_000).expect("valid");
+        assert!(result.series.is_empty());
+    }
+
+    /// Test parsing errors for range function expressions.
+    #[test]
+    fn test_over_time_parse_errors() {
+        let engine = SimpleQueryEngine::new(Arc::new(MemoryStorage::new()));
+
+        assert!(engine.query_instant("rate_over_time(up[5m])", 0).is_err());
+        assert!(engine.query_instant("avg_over_time(up)", 0).is_err());
+        assert!(engine.query_instant("avg_over_time(up[abc])", 0).is_err());
+        assert!(engine.query_instant("avg_over_time(up[5m]", 0).is_err());
+
+        // Range functions need an evaluation instant
+        assert!(engine.query("avg_over_time(up[5m])", 0, 1000).is_err());
+
+        // Parentheses inside label values are not function calls
+        assert!(engine.query_instant(r#"up{job=~"(api|web)"}"#, 0).is_ok());
+    }
 }
@@ -0,0 +1,605 @@
+//! Gorilla-style compressed sample chunks.
+//!
+//! Timestamps are stored as delta-of-deltas and values as XOR against the
+//! previous value, following the scheme from Facebook's Gorilla paper that
+//! Prometheus also uses for its head chunks. Regularly scraped series with
+//! slowly changing values compress to one or two bytes per sample instead
+//! of sixteen.
+
+use crate::storage::Sample;
+
+/// Maximum number of samples in a single chunk before a new one is cut.
+pub const SAMPLES_PER_CHUNK: usize = 120;
+
+/// Delta-of-delta buckets: (prefix bits, prefix length, payload bits).
+const DOD_BUCKETS: [(u64, u8, u8); 3] = [(0b10, 2, 14), (0b110, 3, 17), (0b1110, 4, 20)];
+
+/// Append-only bit stream.
+#[derive(Debug, Clone, Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Number of bits used in the last byte (0 means a new byte is needed).
+    used: u8,
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.used == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            let last = self.bytes.len() - 1;
+            self.bytes[last] |= 0x80 >> self.used;
+        }
+        self.used = (self.used + 1) % 8;
+    }
+
+    fn write_bits(&mut self, value: u64, count: u8) {
+        for i in (0..count).rev() {
+            self.write_bit((value >> i) & 1 == 1);
+        }
+    }
+}
+
+/// Reader over a bit stream produced by `BitWriter`.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn read_bit(&mut self) -> Option<bool> {
+        let byte = self.bytes.get(self.pos / 8)?;
+        let bit = byte & (0x80 >> (self.pos % 8)) != 0;
+        self.pos += 1;
+        Some(bit)
+    }
+
+    fn read_bits(&mut self, count: u8) -> Option<u64> {
+        let mut value = 0u64;
+        for _ in 0..count {
+            value = (value << 1) | u64::from(self.read_bit()?);
+        }
+        Some(value)
+    }
+}
+
+/// A compressed chunk of samples with strictly increasing timestamps.
+#[derive(Debug, Clone)]
+pub struct XorChunk {
+    stream: BitWriter,
+    count: usize,
+    min_time: i64,
+    max_time: i64,
+    prev_delta: i64,
+    prev_value: u64,
+    leading: u8,
+    trailing: u8,
+}
+
+impl XorChunk {
+    /// Create a chunk holding a single sample.
+    ///
+    /// # Parameters
+    ///
+    /// - `sample` - First sample of the chunk
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `XorChunk` instance.
+    pub fn new(sample: &Sample) -> Self {
+        let mut stream = BitWriter::default();
+        #[allow(clippy::cast_sign_loss)]
+        stream.write_bits(sample.timestamp as u64, 64);
+        stream.write_bits(sample.value.to_bits(), 64);
+        Self {
+            stream,
+            count: 1,
+            min_time: sample.timestamp,
+            max_time: sample.timestamp,
+            prev_delta: 0,
+            prev_value: sample.value.to_bits(),
+            leading: u8::MAX,
+            trailing: 0,
+        }
+    }
+
+    /// Number of samples in the chunk.
+    pub const fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Whether the chunk holds no samples (never true for a constructed chunk).
+    pub const fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Timestamp of the first sample.
+    pub const fn min_time(&self) -> i64 {
+        self.min_time
+    }
+
+    /// Timestamp of the last sample.
+    pub const fn max_time(&self) -> i64 {
+        self.max_time
+    }
+
+    /// Size of the encoded data in bytes.
+    pub fn encoded_len(&self) -> usize {
+        self.stream.bytes.len()
+    }
+
+    /// Append a sample newer than every sample in the chunk.
+    ///
+    /// # Parameters
+    ///
+    /// - `sample` - Sample with a timestamp greater than [`XorChunk::max_time`]
+    pub fn append(&mut self, sample: &Sample) {
+        debug_assert!(sample.timestamp > self.max_time, "chunk appends must be in order");
+
+        let delta = sample.timestamp - self.max_time;
+        self.write_dod(delta - self.prev_delta);
+        self.write_value(sample.value.to_bits());
+
+        self.prev_delta = delta;
+        self.max_time = sample.timestamp;
+        self.count += 1;
+    }
+
+    /// Decode all samples of the chunk.
+    ///
+    /// # Returns
+    ///
+    /// Returns an iterator over the samples in timestamp order.
+    pub fn iter(&self) -> XorIter<'_> {
+        XorIter {
+            reader: BitReader { bytes: &self.stream.bytes, pos: 0 },
+            remaining: self.count,
+            first: true,
+            time: 0,
+            delta: 0,
+            value: 0,
+            leading: 0,
+            trailing: 0,
+        }
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    fn write_dod(&mut self, dod: i64) {
+        if dod == 0 {
+            self.stream.write_bit(false);
+            return;
+        }
+        for (prefix, prefix_len, bits) in DOD_BUCKETS {
+            let limit = 1i64 << (bits - 1);
+            if (-limit..limit).contains(&dod) {
+                self.stream.write_bits(prefix, prefix_len);
+                self.stream.write_bits(dod as u64 & ((1 << bits) - 1), bits);
+                return;
+            }
+        }
+        self.stream.write_bits(0b1111, 4);
+        self.stream.write_bits(dod as u64, 64);
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_value(&mut self, value: u64) {
+        let xor = value ^ self.prev_value;
+        self.prev_value = value;
+        if xor == 0 {
+            self.stream.write_bit(false);
+            return;
+        }
+        self.stream.write_bit(true);
+
+        // Leading zeros are stored in 5 bits, so cap them at 31
+        let leading = (xor.leading_zeros() as u8).min(31);
+        let trailing = xor.trailing_zeros() as u8;
+
+        if self.leading != u8::MAX && leading >= self.leading && trailing >= self.trailing {
+            // Meaningful bits fit into the previous window
+            self.stream.write_bit(false);
+            let significant = 64 - self.leading - self.trailing;
+            self.stream.write_bits(xor >> self.trailing, significant);
+        } else {
+            self.leading = leading;
+            self.trailing = trailing;
+            let significant = 64 - leading - trailing;
+            self.stream.write_bit(true);
+            self.stream.write_bits(u64::from(leading), 5);
+            // 64 significant bits do not fit in 6 bits and are stored as 0
+            self.stream.write_bits(u64::from(significant % 64), 6);
+            self.stream.write_bits(xor >> trailing, significant);
+        }
+    }
+}
+
+/// Iterator decoding the samples of an [`XorChunk`].
+pub struct XorIter<'a> {
+    reader: BitReader<'a>,
+    remaining: usize,
+    first: bool,
+    time: i64,
+    delta: i64,
+    value: u64,
+    leading: u8,
+    trailing: u8,
+}
+
+impl XorIter<'_> {
+    #[allow(clippy::cast_possible_wrap)]
+    fn read_dod(&mut self) -> Option<i64> {
+        if !self.reader.read_bit()? {
+            return Some(0);
+        }
+        for (_, prefix_len, bits) in DOD_BUCKETS {
+            // Each further bucket adds one more leading `1` to the prefix
+            if prefix_len == 4 || !self.reader.read_bit()? {
+                if prefix_len == 4 && self.reader.read_bit()? {
+                    return self.reader.read_bits(64).map(|v| v as i64);
+                }
+                let raw = self.reader.read_bits(bits)?;
+                // Sign-extend the payload
+                let shift = 64 - u32::from(bits);
+                return Some(((raw << shift) as i64) >> shift);
+            }
+        }
+        None
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn read_value(&mut self) -> Option<u64> {
+        if !self.reader.read_bit()? {
+            return Some(self.value);
+        }
+        if self.reader.read_bit()? {
+            self.leading = self.reader.read_bits(5)? as u8;
+            let significant = match self.reader.read_bits(6)? as u8 {
+                0 => 64,
+                n => n,
+            };
+            self.trailing = 64 - self.leading - significant;
+        }
+        let significant = 64 - self.leading - self.trailing;
+        let bits = self.reader.read_bits(significant)?;
+        Some(self.value ^ (bits << self.trailing))
+    }
+}
+
+impl Iterator for XorIter<'_> {
+    type Item = Sample;
+
+    #[allow(clippy::cast_possible_wrap)]
+    fn next(&mut self) -> Option<Sample> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        if self.first {
+            self.first = false;
+            self.time = self.reader.read_bits(64)? as i64;
+            self.value = self.reader.read_bits(64)?;
+        } else {
+            self.delta += self.read_dod()?;
+            self.time += self.delta;
+            self.value = self.read_value()?;
+        }
+        Some(Sample::new(self.time, f64::from_bits(self.value)))
+    }
+}
+
+/// Samples of one series stored as a sequence of compressed chunks.
+///
+/// Appends of newer samples go straight into the open (last) chunk; older
+/// or duplicate timestamps fall back to decoding and re-encoding the series,
+/// which keeps the same replace-on-duplicate semantics as `TimeSeries`.
+#[derive(Debug, Clone, Default)]
+pub struct XorSeries {
+    chunks: Vec<XorChunk>,
+}
+
+impl XorSeries {
+    /// Create an empty compressed series.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of samples stored.
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(XorChunk::len).sum()
+    }
+
+    /// Whether no samples are stored.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Number of chunks.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Approximate heap size of the encoded samples in bytes.
+    pub fn encoded_bytes(&self) -> usize {
+        self.chunks.iter().map(|c| c.encoded_len() + std::mem::size_of::<XorChunk>()).sum()
+    }
+
+    /// Timestamp of the first sample, if any.
+    pub fn min_time(&self) -> Option<i64> {
+        self.chunks.first().map(XorChunk::min_time)
+    }
+
+    /// Timestamp of the last sample, if any.
+    pub fn max_time(&self) -> Option<i64> {
+        self.chunks.last().map(XorChunk::max_time)
+    }
+
+    /// Add a sample, replacing an existing sample at the same timestamp.
+    ///
+    /// # Parameters
+    ///
+    /// - `sample` - Sample to add
+    pub fn add(&mut self, sample: &Sample) {
+        match self.chunks.last_mut() {
+            Some(head) if sample.timestamp > head.max_time() => {
+                if head.len() >= SAMPLES_PER_CHUNK {
+                    self.chunks.push(XorChunk::new(sample));
+                } else {
+                    head.append(sample);
+                }
+            }
+            Some(_) => {
+                // Out of order or duplicate: rebuild from decoded samples
+                let mut samples: Vec<Sample> = self.iter().collect();
+                match samples.binary_search_by_key(&sample.timestamp, |s| s.timestamp) {
+                    Ok(pos) => samples[pos] = sample.clone(),
+                    Err(pos) => samples.insert(pos, sample.clone()),
+                }
+                *self = samples.iter().collect();
+            }
+            None => self.chunks.push(XorChunk::new(sample)),
+        }
+    }
+
+    /// Drop all samples older than `mint`.
+    ///
+    /// Whole chunks are dropped without decoding; only a chunk straddling
+    /// `mint` is re-encoded.
+    ///
+    /// # Parameters
+    ///
+    /// - `mint` - Oldest timestamp to keep
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of dropped samples.
+    pub fn drop_before(&mut self, mint: i64) -> usize {
+        let before = self.len();
+        self.chunks.retain(|c| c.max_time() >= mint);
+        if let Some(first) = self.chunks.first() {
+            if first.min_time() < mint {
+                let kept: Vec<Sample> = first.iter().filter(|s| s.timestamp >= mint).collect();
+                let mut rebuilt = XorChunk::new(&kept[0]);
+                for sample in &kept[1..] {
+                    rebuilt.append(sample);
+                }
+                self.chunks[0] = rebuilt;
+            }
+        }
+        before - self.len()
+    }
+
+    /// Delete all samples in time range [mint, maxt] (inclusive).
+    ///
+    /// The series is only re-encoded if a chunk overlaps the range.
+    ///
+    /// # Parameters
+    ///
+    /// - `mint` - Start of the range in milliseconds
+    /// - `maxt` - End of the range in milliseconds
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of deleted samples.
+    pub fn delete_range(&mut self, mint: i64, maxt: i64) -> usize {
+        if !self.chunks.iter().any(|c| c.min_time() <= maxt && c.max_time() >= mint) {
+            return 0;
+        }
+        let before = self.len();
+        let kept: Vec<Sample> =
+            self.iter().filter(|s| s.timestamp < mint || s.timestamp > maxt).collect();
+        *self = kept.iter().collect();
+        before - self.len()
+    }
+
+    /// Drop the oldest samples so that at most `count` remain.
+    ///
+    /// # Parameters
+    ///
+    /// - `count` - Number of newest samples to keep
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of dropped samples.
+    pub fn keep_last(&mut self, count: usize) -> usize {
+        let len = self.len();
+        if len <= count {
+            return 0;
+        }
+        if count == 0 {
+            self.chunks.clear();
+            return len;
+        }
+        let mint = self.iter().nth(len - count).expect("sample within length").timestamp;
+        self.drop_before(mint)
+    }
+
+    /// Decode all samples in timestamp order.
+    pub fn iter(&self) -> impl Iterator<Item = Sample> + '_ {
+        self.chunks.iter().flat_map(XorChunk::iter)
+    }
+
+    /// Decode the samples in time range [start, end] (inclusive) into `out`.
+    ///
+    /// Chunks entirely outside the range are skipped without decoding.
+    ///
+    /// # Parameters
+    ///
+    /// - `start` - Start timestamp (inclusive)
+    /// - `end` - End timestamp (inclusive)
+    /// - `out` - Buffer to append decoded samples to
+    pub fn decode_range(&self, start: i64, end: i64, out: &mut Vec<Sample>) {
+        for chunk in &self.chunks {
+            if chunk.max_time() < start || chunk.min_time() > end {
+                continue;
+            }
+            out.extend(chunk.iter().filter(|s| s.timestamp >= start && s.timestamp <= end));
+        }
+    }
+}
+
+impl<'a> FromIterator<&'a Sample> for XorSeries {
+    fn from_iter<I: IntoIterator<Item = &'a Sample>>(iter: I) -> Self {
+        let mut series = Self::new();
+        for sample in iter {
+            series.add(sample);
+        }
+        series
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(samples: &[Sample]) -> Vec<Sample> {
+        let series: XorSeries = samples.iter().collect();
+        series.iter().collect()
+    }
+
+    /// Test encoding and decoding regular samples.
+    #[test]
+    fn test_roundtrip_regular() {
+        let samples: Vec<Sample> =
+            (0..500).map(|i| Sample::new(1_700_000_000_000 + i * 15_000, (i % 7) as f64)).collect();
+        assert_eq!(roundtrip(&samples), samples);
+    }
+
+    /// Test encoding irregular timestamps and awkward values.
+    #[test]
+    fn test_roundtrip_irregular() {
+        let values = [
+            0.0,
+            -0.0,
+            1.5,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            -123_456.789,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            1e-300,
+            42.0,
+        ];
+        let offsets =
+            [0, 1, 3, 100_000, 100_001, 5_000_000, 5_000_002, 9_000_000_000, 9_000_000_001];
+        let mut time = -1_000;
+        let mut samples = Vec::new();
+        for (i, value) in values.iter().cycle().take(300).enumerate() {
+            time += offsets[i % offsets.len()] + 1;
+            samples.push(Sample::new(time, *value));
+        }
+
+        let decoded = roundtrip(&samples);
+        assert_eq!(decoded.len(), samples.len());
+        for (a, b) in decoded.iter().zip(&samples) {
+            assert_eq!(a.timestamp, b.timestamp);
+            assert_eq!(a.value.to_bits(), b.value.to_bits());
+        }
+    }
+
+    /// Test NaN bit patterns, including staleness markers, are preserved exactly.
+    #[test]
+    fn test_roundtrip_nan_bits() {
+        let samples =
+            vec![Sample::new(1000, 1.0), Sample::stale_marker(2000), Sample::new(3000, f64::NAN)];
+        let decoded = roundtrip(&samples);
+        assert!(!decoded[0].is_stale());
+        assert!(decoded[1].is_stale());
+        assert!(decoded[2].value.is_nan() && !decoded[2].is_stale());
+    }
+
+    /// Test chunks are cut at the configured size.
+    #[test]
+    fn test_chunk_cutting() {
+        let samples: Vec<Sample> = (0..250).map(|i| Sample::new(i * 1000, 1.0)).collect();
+        let series: XorSeries = samples.iter().collect();
+        assert_eq!(series.chunk_count(), 3);
+        assert_eq!(series.len(), 250);
+        assert_eq!(series.max_time(), Some(249_000));
+    }
+
+    /// Test out-of-order and duplicate samples keep sorted, replace-on-duplicate semantics.
+    #[test]
+    fn test_out_of_order_and_duplicates() {
+        let mut series = XorSeries::new();
+        series.add(&Sample::new(3000, 30.0));
+        series.add(&Sample::new(1000, 10.0));
+        series.add(&Sample::new(2000, 20.0));
+        series.add(&Sample::new(2000, 25.0));
+
+        let decoded: Vec<Sample> = series.iter().collect();
+        assert_eq!(
+            decoded,
+            vec![Sample::new(1000, 10.0), Sample::new(2000, 25.0), Sample::new(3000, 30.0)]
+        );
+    }
+
+    /// Test range decoding.
+    #[test]
+    fn test_decode_range() {
+        let samples: Vec<Sample> = (0..400).map(|i| Sample::new(i * 1000, i as f64)).collect();
+        let series: XorSeries = samples.iter().collect();
+
+        let mut out = Vec::new();
+        series.decode_range(150_000, 155_000, &mut out);
+        assert_eq!(out, samples[150..=155].to_vec());
+
+        out.clear();
+        series.decode_range(500_000, 600_000, &mut out);
+        assert!(out.is_empty());
+    }
+
+    /// Test dropping old samples, within and across chunk boundaries.
+    #[test]
+    fn test_drop_before_and_keep_last() {
+        let samples: Vec<Sample> = (0..300).map(|i| Sample::new(i * 1000, i as f64)).collect();
+
+        let mut series: XorSeries = samples.iter().collect();
+        assert_eq!(series.drop_before(130_000), 130);
+        assert_eq!(series.chunk_count(), 2);
+        assert_eq!(series.iter().collect::<Vec<_>>(), samples[130..].to_vec());
+
+        // Appends keep working after the first chunk was rebuilt
+        series.add(&Sample::new(300_000, 1.0));
+        assert_eq!(series.len(), 171);
+
+        let mut series: XorSeries = samples.iter().collect();
+        assert_eq!(series.keep_last(10), 290);
+        assert_eq!(series.iter().collect::<Vec<_>>(), samples[290..].to_vec());
+        assert_eq!(series.keep_last(20), 0);
+        assert_eq!(series.keep_last(0), 10);
+        assert!(series.is_empty());
+    }
+
+    /// Test regular data compresses well below the raw 16 bytes per sample.
+    #[test]
+    fn test_compression_ratio() {
+        let samples: Vec<Sample> =
+            (0..1200).map(|i| Sample::new(i * 15_000, 100.0 + (i % 3) as f64)).collect();
+        let series: XorSeries = samples.iter().collect();
+
+        let raw = samples.len() * std::mem::size_of::<Sample>();
+        assert!(series.encoded_bytes() * 4 < raw, "{} vs {raw}", series.encoded_bytes());
+    }
+}
@@ -0,0 +1,439 @@
+//! Inverted label index for resolving label matchers to series.
+//!
+//! The index maps every label name and value to a postings list: the sorted
+//! identifiers of all series carrying that label pair. Matchers are resolved
+//! by intersecting, merging and subtracting postings lists instead of
+//! scanning every stored series.
+//!
+//! Label names and values are kept as [`Symbol`]s from the symbol table
+//! shared with the series they index.
+
+use std::sync::Arc;
+
+use fnv::FnvHashMap;
+
+use crate::matchers::{IndexHint, LabelMatcher};
+use crate::storage::symbols::{Symbol, SymbolLabel, SymbolTable};
+use crate::storage::Label;
+
+/// Label index mapping `name -> value -> postings`.
+#[derive(Debug, Default)]
+pub struct LabelIndex {
+    postings: FnvHashMap<Symbol, FnvHashMap<Symbol, Vec<u64>>>,
+}
+
+/// Result of resolving matchers against a [`LabelIndex`].
+#[derive(Debug)]
+pub struct Selection<'m> {
+    /// Candidate series ids, sorted; `None` means every series is a candidate.
+    pub candidates: Option<Vec<u64>>,
+    /// Series ids excluded by negative matchers, sorted.
+    pub excluded: Vec<u64>,
+    /// Matchers the index could not resolve, to be checked on each candidate.
+    pub residual: Vec<&'m Arc<dyn LabelMatcher>>,
+}
+
+impl LabelIndex {
+    /// Create a new empty label index.
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `LabelIndex` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a series under all of its labels.
+    ///
+    /// # Parameters
+    ///
+    /// - `labels` - Interned labels of the series
+    /// - `id` - Series identifier to add to the postings lists
+    pub fn add(&mut self, labels: &[SymbolLabel], id: u64) {
+        for label in labels {
+            self.add_posting(*label, id);
+        }
+    }
+
+    /// Add a series to the postings list of a single label pair.
+    ///
+    /// # Parameters
+    ///
+    /// - `label` - Interned label pair
+    /// - `id` - Series identifier
+    pub fn add_posting(&mut self, label: SymbolLabel, id: u64) {
+        let list = self.postings.entry(label.name).or_default().entry(label.value).or_default();
+        if let Err(pos) = list.binary_search(&id) {
+            list.insert(pos, id);
+        }
+    }
+
+    /// Iterate over all postings lists.
+    ///
+    /// # Returns
+    ///
+    /// Returns an iterator of label pairs and their sorted series identifiers.
+    pub fn postings(&self) -> impl Iterator<Item = (SymbolLabel, &[u64])> {
+        self.postings.iter().flat_map(|(name, values)| {
+            values
+                .iter()
+                .map(|(value, list)| (SymbolLabel { name: *name, value: *value }, list.as_slice()))
+        })
+    }
+
+    /// Remove a series from the postings lists of all of its labels.
+    ///
+    /// Label names and values left without any series are dropped.
+    ///
+    /// # Parameters
+    ///
+    /// - `labels` - Interned labels of the series
+    /// - `id` - Series identifier to remove
+    pub fn remove(&mut self, labels: &[SymbolLabel], id: u64) {
+        for label in labels {
+            let Some(name_map) = self.postings.get_mut(&label.name) else {
+                continue;
+            };
+            if let Some(list) = name_map.get_mut(&label.value) {
+                if let Ok(pos) = list.binary_search(&id) {
+                    list.remove(pos);
+                }
+                if list.is_empty() {
+                    name_map.remove(&label.value);
+                }
+            }
+            if name_map.is_empty() {
+                self.postings.remove(&label.name);
+            }
+        }
+    }
+
+    /// Get all label names, sorted.
+    ///
+    /// # Parameters
+    ///
+    /// - `symbols` - Symbol table the index was built with
+    ///
+    /// # Returns
+    ///
+    /// Returns a vector of all indexed label names.
+    pub fn names(&self, symbols: &SymbolTable) -> Vec<String> {
+        let mut names: Vec<String> =
+            self.postings.keys().map(|s| symbols.resolve(*s).to_string()).collect();
+        names.sort();
+        names
+    }
+
+    /// Get all values of a label, sorted.
+    ///
+    /// # Parameters
+    ///
+    /// - `symbols` - Symbol table the index was built with
+    /// - `name` - Label name to get values for
+    ///
+    /// # Returns
+    ///
+    /// Returns a vector of values, or an empty vector if the label is unknown.
+    pub fn values(&self, symbols: &SymbolTable, name: &str) -> Vec<String> {
+        symbols
+            .get(name)
+            .and_then(|name| self.postings.get(&name))
+            .map(|name_map| {
+                let mut values: Vec<String> =
+                    name_map.keys().map(|s| symbols.resolve(*s).to_string()).collect();
+                values.sort();
+                values
+            })
+            .unwrap_or_default()
+    }
+
+    /// Resolve label matchers into candidate and excluded postings.
+    ///
+    /// Equality matchers intersect their postings list, regex matchers expand
+    /// to the union of postings of every matching value, and negative matchers
+    /// are collected for subtraction.
+    ///
+    /// # Parameters
+    ///
+    /// - `symbols` - Symbol table the index was built with
+    /// - `matchers` - Label matchers that all have to match (AND semantics)
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Selection` describing the matching series.
+    pub fn select<'m>(
+        &self,
+        symbols: &SymbolTable,
+        matchers: &'m [Arc<dyn LabelMatcher>],
+    ) -> Selection<'m> {
+        let mut candidates: Option<Vec<u64>> = None;
+        let mut excluded: Vec<u64> = Vec::new();
+        let mut residual = Vec::new();
+
+        for matcher in matchers {
+            let name = matcher.label_name();
+            match matcher.index_hint() {
+                IndexHint::Equal(value) => {
+                    let list = self.postings_for(symbols, name, value);
+                    candidates = Some(match candidates {
+                        Some(current) => intersect(&current, list),
+                        None => list.to_vec(),
+                    });
+                }
+                IndexHint::Regex(pattern) => {
+                    let list = self.postings_matching(symbols, name, |v| pattern.is_match(v));
+                    candidates = Some(match candidates {
+                        Some(current) => intersect(&current, &list),
+                        None => list,
+                    });
+                }
+                IndexHint::NotEqual(value) => {
+                    excluded = union(&excluded, self.postings_for(symbols, name, value));
+                }
+                IndexHint::NotRegex(pattern) => {
+                    let list = self.postings_matching(symbols, name, |v| pattern.is_match(v));
+                    excluded = union(&excluded, &list);
+                }
+                IndexHint::Unsupported => residual.push(matcher),
+            }
+
+            // Nothing can match once an intersection becomes empty
+            if candidates.as_ref().is_some_and(Vec::is_empty) {
+                break;
+            }
+        }
+
+        Selection { candidates, excluded, residual }
+    }
+
+    /// Get the postings list for a single label pair.
+    fn postings_for(&self, symbols: &SymbolTable, name: &str, value: &str) -> &[u64] {
+        let (Some(name), Some(value)) = (symbols.get(name), symbols.get(value)) else {
+            return &[];
+        };
+        self.postings.get(&name).and_then(|m| m.get(&value)).map_or(&[], Vec::as_slice)
+    }
+
+    /// Merge the postings lists of every value of `name` accepted by `accept`.
+    fn postings_matching(
+        &self,
+        symbols: &SymbolTable,
+        name: &str,
+        accept: impl Fn(&str) -> bool,
+    ) -> Vec<u64> {
+        let Some(name_map) = symbols.get(name).and_then(|name| self.postings.get(&name)) else {
+            return Vec::new();
+        };
+        name_map
+            .iter()
+            .filter(|(value, _)| accept(symbols.resolve(**value)))
+            .fold(Vec::new(), |acc, (_, list)| union(&acc, list))
+    }
+}
+
+impl Selection<'_> {
+    /// Check whether a candidate series passes the exclusions and residual matchers.
+    ///
+    /// # Parameters
+    ///
+    /// - `id` - Series identifier
+    /// - `labels` - Produces the labels of the series, only called if residual
+    ///   matchers need them
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the series matches all matchers.
+    pub fn accepts(&self, id: u64, labels: impl FnOnce() -> Vec<Label>) -> bool {
+        if self.excluded.binary_search(&id).is_ok() {
+            return false;
+        }
+        if self.residual.is_empty() {
+            return true;
+        }
+        let labels = labels();
+        self.residual.iter().all(|matcher| matcher.matches(&labels))
+    }
+}
+
+/// Intersect two sorted postings lists.
+pub fn intersect(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut out = Vec::with_capacity(a.len().min(b.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Merge two sorted postings lists without duplicates.
+pub fn union(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut out = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => {
+                out.push(a[i]);
+                i += 1;
+            }
+            std::cmp::Ordering::Greater => {
+                out.push(b[j]);
+                j += 1;
+            }
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out.extend_from_slice(&a[i..]);
+    out.extend_from_slice(&b[j..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use crate::matchers::{EqualMatcher, NotEqualMatcher, NotRegexMatcher, RegexMatcher};
+
+    use super::*;
+
+    /// Custom matcher without an index hint.
+    #[derive(Debug)]
+    struct LabelCountMatcher(usize);
+
+    impl LabelMatcher for LabelCountMatcher {
+        fn matches(&self, labels: &[Label]) -> bool {
+            labels.len() == self.0
+        }
+
+        fn label_name(&self) -> &str {
+            ""
+        }
+    }
+
+    fn create_test_index() -> (LabelIndex, SymbolTable) {
+        let mut symbols = SymbolTable::new();
+        let mut index = LabelIndex::new();
+        let series: [(&[Label], u64); 4] = [
+            (&[Label::new("job", "api"), Label::new("method", "GET")], 3),
+            (&[Label::new("job", "api"), Label::new("method", "POST")], 1),
+            (&[Label::new("job", "web"), Label::new("method", "GET")], 2),
+            (&[Label::new("job", "worker")], 4),
+        ];
+        for (labels, id) in series {
+            index.add(&symbols.intern_labels(labels), id);
+        }
+        (index, symbols)
+    }
+
+    /// Test sorted postings list operations.
+    #[test]
+    fn test_postings_operations() {
+        assert_eq!(intersect(&[1, 3, 5, 7], &[3, 4, 5]), vec![3, 5]);
+        assert_eq!(intersect(&[1, 2], &[]), Vec::<u64>::new());
+        assert_eq!(union(&[1, 3, 5], &[2, 3, 6]), vec![1, 2, 3, 5, 6]);
+        assert_eq!(union(&[], &[4]), vec![4]);
+    }
+
+    /// Test postings stay sorted and duplicate-free.
+    #[test]
+    fn test_add_keeps_postings_sorted() {
+        let (mut index, mut symbols) = create_test_index();
+        index.add(&symbols.intern_labels(&[Label::new("job", "api")]), 1);
+
+        assert_eq!(index.postings_for(&symbols, "job", "api"), &[1, 3]);
+        assert_eq!(index.names(&symbols), vec!["job", "method"]);
+        assert_eq!(index.values(&symbols, "job"), vec!["api", "web", "worker"]);
+        assert!(index.values(&symbols, "missing").is_empty());
+    }
+
+    /// Test removing series drops empty postings, values and names.
+    #[test]
+    fn test_remove() {
+        let (mut index, mut symbols) = create_test_index();
+        index.remove(&symbols.intern_labels(&[Label::new("job", "worker")]), 4);
+        index.remove(
+            &symbols.intern_labels(&[Label::new("job", "api"), Label::new("method", "POST")]),
+            1,
+        );
+
+        assert_eq!(index.postings_for(&symbols, "job", "api"), &[3]);
+        assert_eq!(index.values(&symbols, "job"), vec!["api", "web"]);
+        assert_eq!(index.values(&symbols, "method"), vec!["GET"]);
+
+        index.remove(
+            &symbols.intern_labels(&[Label::new("job", "api"), Label::new("method", "GET")]),
+            3,
+        );
+        index.remove(
+            &symbols.intern_labels(&[Label::new("job", "web"), Label::new("method", "GET")]),
+            2,
+        );
+        assert!(index.names(&symbols).is_empty());
+    }
+
+    /// Test resolving equality and regex matchers into candidates.
+    #[test]
+    fn test_select_positive_matchers() {
+        let (index, symbols) = create_test_index();
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> = vec![
+            Arc::new(EqualMatcher::new("job", "api")),
+            Arc::new(EqualMatcher::new("method", "GET")),
+        ];
+        let selection = index.select(&symbols, &matchers);
+        assert_eq!(selection.candidates, Some(vec![3]));
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> =
+            vec![Arc::new(RegexMatcher::new("job", Regex::new("^w").expect("valid regex")))];
+        let selection = index.select(&symbols, &matchers);
+        assert_eq!(selection.candidates, Some(vec![2, 4]));
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> =
+            vec![Arc::new(EqualMatcher::new("job", "missing"))];
+        let selection = index.select(&symbols, &matchers);
+        assert_eq!(selection.candidates, Some(vec![]));
+    }
+
+    /// Test negative matchers are collected for subtraction.
+    #[test]
+    fn test_select_negative_matchers() {
+        let (index, symbols) = create_test_index();
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> = vec![
+            Arc::new(NotEqualMatcher::new("method", "POST")),
+            Arc::new(NotRegexMatcher::new("job", Regex::new("web|worker").expect("valid regex"))),
+        ];
+        let selection = index.select(&symbols, &matchers);
+        assert_eq!(selection.candidates, None);
+        assert_eq!(selection.excluded, vec![1, 2, 4]);
+        assert!(selection.accepts(3, Vec::new));
+        assert!(!selection.accepts(1, Vec::new));
+    }
+
+    /// Test matchers without an index hint are kept as residual filters.
+    #[test]
+    fn test_select_residual_matchers() {
+        let (index, symbols) = create_test_index();
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> =
+            vec![Arc::new(EqualMatcher::new("job", "api")), Arc::new(LabelCountMatcher(2))];
+        let selection = index.select(&symbols, &matchers);
+        assert_eq!(selection.candidates, Some(vec![1, 3]));
+        assert_eq!(selection.residual.len(), 1);
+        assert!(
+            selection.accepts(3, || vec![Label::new("job", "api"), Label::new("method", "GET")])
+        );
+        assert!(!selection.accepts(3, || vec![Label::new("job", "api")]));
+    }
+}
@@ -2,69 +2,1202 @@
 //!
 //! This module provides a basic time series database that stores metrics
 //! in memory and supports simple label-based querying with indexing.
+//!
+//! Series are identified by their canonical (sorted) label set. Label sets
+//! are bucketed by hash, and every lookup compares full label sets, so hash
+//! collisions never merge unrelated series. Label strings are interned in a
+//! per-shard symbol table shared by the series and their postings index.
+//!
+//! Samples are kept either as plain vectors or, with
+//! [`SampleEncoding::Xor`], as Gorilla-compressed chunks (see
+//! [`crate::storage::chunk`]) that trade some CPU for far less memory.
+//!
+//! With [`MemoryStorage::open_wal`], every series creation and sample
+//! append is first recorded in a write-ahead log (see
+//! [`crate::storage::wal`]) so that data survives a crash. Writes are
+//! prepared under a shard's read lock, logged, and then applied under its
+//! write lock, so readers never wait on log I/O.
+
+use std::collections::BTreeSet;
+use std::hash::Hasher;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+
+use fnv::{FnvHashMap, FnvHasher};
+use tokio::sync::broadcast;
+
+use crate::matchers::LabelMatcher;
+use crate::storage::chunk::XorSeries;
+use crate::storage::chunk::SAMPLES_PER_CHUNK;
+use crate::storage::index::LabelIndex;
+use crate::storage::notify::{IngestEvent, INGEST_EVENT_CAPACITY};
+use crate::storage::snapshot::{
+    SnapshotData, SnapshotError, SnapshotMeta, SnapshotPostings, SnapshotSeries,
+};
+use crate::storage::stats::{HeadStats, IngestStats, TsdbStats, TsdbStatsBuilder};
+use crate::storage::symbols::{SymbolLabel, SymbolTable};
+use crate::storage::wal::{Wal, WalError, WalRecord, DEFAULT_SEGMENT_SIZE};
+use crate::storage::{
+    insert_sample, FullStorage, Label, MetadataStorage, Sample, SeriesSet, SeriesView, Storage,
+    StorageError, TimeSeries,
+};
+
+/// Hash function used to bucket label sets in `MemoryStorage`.
+///
+/// Collisions are allowed: series with equal hashes are told apart by
+/// comparing their labels, so the hasher only affects performance.
+pub trait LabelsHasher: Send + Sync {
+    /// Hash a canonical (sorted) label set.
+    ///
+    /// # Parameters
+    ///
+    /// - `labels` - Labels sorted by name and value
+    ///
+    /// # Returns
+    ///
+    /// Returns the hash of the label set.
+    fn hash_labels(&self, labels: &[Label]) -> u64;
+}
+
+/// Default FNV-1a label set hasher.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FnvLabelsHasher;
+
+impl LabelsHasher for FnvLabelsHasher {
+    fn hash_labels(&self, labels: &[Label]) -> u64 {
+        // Separator bytes keep {a="bc"} and {ab="c"} from hashing identically
+        const SEP: u8 = 0xff;
+        let mut hasher = FnvHasher::default();
+        for label in labels {
+            hasher.write(label.name.as_bytes());
+            hasher.write_u8(SEP);
+            hasher.write(label.value.as_bytes());
+            hasher.write_u8(SEP);
+        }
+        hasher.finish()
+    }
+}
 
-use std::sync::{Arc, RwLock};
+/// How `MemoryStorage` keeps samples in memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SampleEncoding {
+    /// Uncompressed, sorted sample vectors (16 bytes per sample)
+    #[default]
+    Raw,
+    /// Gorilla XOR chunks with delta-of-delta timestamps
+    Xor,
+}
 
-use fnv::FnvHashMap;
+impl FromStr for SampleEncoding {
+    type Err = String;
 
-use crate::matchers::LabelMatcher;
-use crate::storage::{FullStorage, Label, MetadataStorage, Storage, TimeSeries};
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "xor" => Ok(Self::Xor),
+            other => Err(format!("unknown sample encoding: {other} (expected raw or xor)")),
+        }
+    }
+}
+
+/// Reference point for time-based retention.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionAnchor {
+    /// Relative to the newest sample ever ingested (stable for replayed data)
+    #[default]
+    LatestSample,
+    /// Relative to the wall clock
+    Clock,
+}
+
+impl FromStr for RetentionAnchor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "latest-sample" => Ok(Self::LatestSample),
+            "clock" => Ok(Self::Clock),
+            other => {
+                Err(format!("unknown retention anchor: {other} (expected latest-sample or clock)"))
+            }
+        }
+    }
+}
+
+/// How `MemoryStorage` handles samples that do not extend a series in order.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IngestPolicy {
+    /// Accept every sample; a sample at an existing timestamp replaces it
+    #[default]
+    Overwrite,
+    /// Reject like Prometheus: samples repeating a timestamp with a
+    /// different value, and samples older than the series' newest sample
+    /// by more than the out-of-order window
+    Reject {
+        /// How far behind the newest sample of a series samples are still accepted
+        out_of_order_window: Duration,
+    },
+}
+
+impl FromStr for IngestPolicy {
+    type Err = String;
+
+    /// Parse `overwrite` or `reject`; the latter starts with no out-of-order window.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overwrite" => Ok(Self::Overwrite),
+            "reject" => Ok(Self::Reject { out_of_order_window: Duration::ZERO }),
+            other => Err(format!("unknown ingest policy: {other} (expected overwrite or reject)")),
+        }
+    }
+}
+
+/// Limits bounding the memory used by `MemoryStorage`.
+///
+/// All limits are disabled by default.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StorageLimits {
+    /// Drop samples older than this, measured from `retention_anchor`
+    pub retention: Option<Duration>,
+    /// What `retention` is measured from
+    pub retention_anchor: RetentionAnchor,
+    /// Maximum number of active series; new series beyond it are rejected
+    pub max_series: Option<usize>,
+    /// Maximum number of samples per series; the oldest samples are dropped
+    pub max_samples_per_series: Option<usize>,
+}
+
+impl StorageLimits {
+    /// Whether any limit is configured.
+    pub const fn is_enabled(&self) -> bool {
+        self.retention.is_some()
+            || self.max_series.is_some()
+            || self.max_samples_per_series.is_some()
+    }
+}
+
+/// Result of a compaction run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionStats {
+    /// Samples dropped by retention or the per-series sample cap
+    pub removed_samples: usize,
+    /// Series dropped because they had no samples left
+    pub removed_series: usize,
+}
+
+/// Samples of a stored series in the configured encoding.
+enum SampleBuffer {
+    Raw(Vec<Sample>),
+    Xor(XorSeries),
+}
+
+impl SampleBuffer {
+    /// Create an empty buffer for the given encoding.
+    fn new(encoding: SampleEncoding) -> Self {
+        match encoding {
+            SampleEncoding::Raw => Self::Raw(Vec::new()),
+            SampleEncoding::Xor => Self::Xor(XorSeries::new()),
+        }
+    }
+
+    /// Add a sample, replacing an existing sample at the same timestamp.
+    fn add(&mut self, sample: Sample) {
+        match self {
+            Self::Raw(samples) => insert_sample(samples, sample),
+            Self::Xor(series) => series.add(&sample),
+        }
+    }
+
+    /// Number of stored samples.
+    fn len(&self) -> usize {
+        match self {
+            Self::Raw(samples) => samples.len(),
+            Self::Xor(series) => series.len(),
+        }
+    }
+
+    /// Drop samples older than `mint`, returning how many were dropped.
+    fn drop_before(&mut self, mint: i64) -> usize {
+        match self {
+            Self::Raw(samples) => {
+                samples.drain(..samples.partition_point(|s| s.timestamp < mint)).count()
+            }
+            Self::Xor(series) => series.drop_before(mint),
+        }
+    }
+
+    /// Delete samples in [mint, maxt], returning how many were deleted.
+    fn delete_range(&mut self, mint: i64, maxt: i64) -> usize {
+        match self {
+            Self::Raw(samples) => {
+                let before = samples.len();
+                samples.retain(|s| s.timestamp < mint || s.timestamp > maxt);
+                before - samples.len()
+            }
+            Self::Xor(series) => series.delete_range(mint, maxt),
+        }
+    }
+
+    /// Keep only the newest `count` samples, returning how many were dropped.
+    fn keep_last(&mut self, count: usize) -> usize {
+        match self {
+            Self::Raw(samples) => samples.drain(..samples.len().saturating_sub(count)).count(),
+            Self::Xor(series) => series.keep_last(count),
+        }
+    }
+
+    /// Number of chunks, counting raw samples in chunks of [`SAMPLES_PER_CHUNK`].
+    fn chunk_count(&self) -> usize {
+        match self {
+            Self::Raw(samples) => samples.len().div_ceil(SAMPLES_PER_CHUNK),
+            Self::Xor(series) => series.chunk_count(),
+        }
+    }
+
+    /// Timestamps of the first and last sample, if any.
+    fn time_range(&self) -> Option<(i64, i64)> {
+        match self {
+            Self::Raw(samples) => Some((samples.first()?.timestamp, samples.last()?.timestamp)),
+            Self::Xor(series) => Some((series.min_time()?, series.max_time()?)),
+        }
+    }
+
+    /// Get the sample at exactly `timestamp`, if any.
+    fn get(&self, timestamp: i64) -> Option<Sample> {
+        match self {
+            Self::Raw(samples) => samples
+                .binary_search_by_key(&timestamp, |s| s.timestamp)
+                .ok()
+                .map(|pos| samples[pos].clone()),
+            Self::Xor(series) => {
+                let mut buf = Vec::new();
+                series.decode_range(timestamp, timestamp, &mut buf);
+                buf.pop()
+            }
+        }
+    }
+
+    /// Get samples in [mint, maxt], decoding into `buf` when compressed.
+    fn window<'a>(&'a self, mint: i64, maxt: i64, buf: &'a mut Vec<Sample>) -> &'a [Sample] {
+        match self {
+            Self::Raw(samples) => {
+                let start = samples.partition_point(|s| s.timestamp < mint);
+                let end = samples.partition_point(|s| s.timestamp <= maxt);
+                &samples[start..end.max(start)]
+            }
+            Self::Xor(series) => {
+                buf.clear();
+                series.decode_range(mint, maxt, buf);
+                buf
+            }
+        }
+    }
+
+    /// Approximate heap bytes used by the samples.
+    fn heap_bytes(&self) -> usize {
+        match self {
+            Self::Raw(samples) => samples.capacity() * std::mem::size_of::<Sample>(),
+            Self::Xor(series) => series.encoded_bytes(),
+        }
+    }
+}
+
+/// A stored series: interned canonical labels plus encoded samples.
+struct MemSeries {
+    /// Hash of the label set, for removing the series from its hash bucket
+    hash: u64,
+    labels: Box<[SymbolLabel]>,
+    samples: SampleBuffer,
+}
+
+impl MemSeries {
+    /// Copy the full series out as a `TimeSeries`.
+    fn to_time_series(&self, symbols: &SymbolTable) -> TimeSeries {
+        let mut buf = Vec::new();
+        let samples = self.samples.window(i64::MIN, i64::MAX, &mut buf).to_vec();
+        TimeSeries { labels: symbols.materialize(&self.labels), samples }
+    }
+}
+
+/// Series keyed by a stable reference, with hash buckets for label set lookup.
+struct SeriesMap {
+    /// Map from series reference to stored series
+    by_ref: FnvHashMap<u64, MemSeries>,
+    /// Map from label set hash to the references of all series sharing it
+    by_hash: FnvHashMap<u64, Vec<u64>>,
+    /// Next series reference to hand out (monotonically increasing)
+    next_ref: u64,
+    /// Increment between references, so references are unique across shards
+    ref_stride: u64,
+}
+
+impl SeriesMap {
+    /// Create an empty map handing out `first_ref`, `first_ref + stride`, ...
+    fn new(first_ref: u64, ref_stride: u64) -> Self {
+        Self {
+            by_ref: FnvHashMap::default(),
+            by_hash: FnvHashMap::default(),
+            next_ref: first_ref,
+            ref_stride,
+        }
+    }
+
+    /// Find the series with exactly these canonical labels.
+    fn lookup(&self, hash: u64, labels: &[SymbolLabel]) -> Option<u64> {
+        self.by_hash.get(&hash)?.iter().copied().find(|r| *self.by_ref[r].labels == *labels)
+    }
+
+    /// Reference the next inserted series will get.
+    fn peek_ref(&self) -> u64 {
+        self.next_ref
+    }
+
+    /// Insert a new series, returning its reference.
+    fn insert(&mut self, hash: u64, ts: MemSeries) -> u64 {
+        let series_ref = self.next_ref;
+        self.next_ref += self.ref_stride;
+        self.by_hash.entry(hash).or_default().push(series_ref);
+        self.by_ref.insert(series_ref, ts);
+        series_ref
+    }
+
+    /// Remove a series, returning it if it existed.
+    fn remove(&mut self, series_ref: u64) -> Option<MemSeries> {
+        let removed = self.by_ref.remove(&series_ref)?;
+        if let Some(bucket) = self.by_hash.get_mut(&removed.hash) {
+            bucket.retain(|r| *r != series_ref);
+            if bucket.is_empty() {
+                self.by_hash.remove(&removed.hash);
+            }
+        }
+        Some(removed)
+    }
+}
+
+/// One lock domain of `MemoryStorage`: a slice of the series and their postings.
+struct Shard {
+    /// Label strings of this shard's series and postings
+    symbols: SymbolTable,
+    /// Stored series and their hash buckets
+    series: SeriesMap,
+    /// Label index over the series of this shard
+    index: LabelIndex,
+}
+
+impl Shard {
+    /// Resolve matchers to the sorted references of matching series.
+    fn select_refs(&self, matchers: &[Arc<dyn LabelMatcher>]) -> Vec<u64> {
+        let mut selection = self.index.select(&self.symbols, matchers);
+        let candidates = selection.candidates.take().unwrap_or_else(|| {
+            let mut all: Vec<u64> = self.series.by_ref.keys().copied().collect();
+            all.sort_unstable();
+            all
+        });
+        candidates
+            .into_iter()
+            .filter(|r| {
+                self.series
+                    .by_ref
+                    .get(r)
+                    .is_some_and(|s| selection.accepts(*r, || self.symbols.materialize(&s.labels)))
+            })
+            .collect()
+    }
+}
+
+/// A shard with its locks.
+///
+/// Writers hold `writer` from preparing a change until it is applied, so
+/// the shard cannot change between the two. The data lock is only
+/// write-locked to apply prepared changes, never across log appends.
+struct ShardLock {
+    /// Serializes the writers of this shard
+    writer: Mutex<()>,
+    /// Shard contents
+    data: RwLock<Shard>,
+}
+
+impl ShardLock {
+    /// Lock out other writers and get write access to the shard.
+    fn write(&self) -> (MutexGuard<'_, ()>, RwLockWriteGuard<'_, Shard>) {
+        let writer = self.writer.lock().unwrap();
+        (writer, self.data.write().unwrap())
+    }
+}
+
+/// Ingestion counters of `MemoryStorage`, see [`IngestStats`].
+#[derive(Debug, Default)]
+struct IngestCounters {
+    samples_appended: AtomicU64,
+    out_of_order_samples: AtomicU64,
+    duplicate_samples: AtomicU64,
+    out_of_bounds_samples: AtomicU64,
+    rejected_series: AtomicU64,
+}
+
+/// Default number of shards in `MemoryStorage`.
+pub const DEFAULT_SHARD_COUNT: usize = 16;
 
 /// In-memory storage for time series data with label indexing.
+///
+/// Series are spread over independently locked shards by label set hash,
+/// and every shard keeps its own postings index. Writers only lock the
+/// shard owning the series, so concurrent remote-write senders and queries
+/// touching other shards do not wait on each other.
 pub struct MemoryStorage {
-    /// Map from series fingerprint to time series
-    series: RwLock<FnvHashMap<u64, TimeSeries>>,
-    /// Label index for fast lookup
-    label_index: RwLock<FnvHashMap<String, FnvHashMap<String, Vec<u64>>>>,
+    /// Series shards, selected by label set hash
+    shards: Box<[ShardLock]>,
+    /// Hash function for label sets
+    hasher: Arc<dyn LabelsHasher>,
+    /// Encoding used for the samples of new series
+    encoding: SampleEncoding,
+    /// Retention and size limits
+    limits: StorageLimits,
+    /// Handling of duplicate and out-of-order samples
+    ingest_policy: IngestPolicy,
+    /// Ingestion counters for self-metrics
+    counters: IngestCounters,
+    /// Number of stored series across all shards
+    series_count: AtomicUsize,
+    /// Newest sample timestamp ever ingested, `i64::MIN` if none
+    max_time: AtomicI64,
+    /// Write-ahead log, locked after shard writer locks and never while
+    /// holding a shard's data lock for writing
+    wal: Mutex<Option<Wal>>,
+    /// Publisher of ingestion events to subscribers
+    events: broadcast::Sender<IngestEvent>,
 }
 
 impl Default for MemoryStorage {
     fn default() -> Self {
         Self::new()
     }
-}
+}
+
+impl MemoryStorage {
+    /// Create a new empty in-memory storage.
+    ///
+    /// # Returns
+    /// Returns a new `MemoryStorage` instance with empty series and label index.
+    pub fn new() -> Self {
+        Self {
+            shards: Self::create_shards(DEFAULT_SHARD_COUNT),
+            hasher: Arc::new(FnvLabelsHasher),
+            encoding: SampleEncoding::default(),
+            limits: StorageLimits::default(),
+            ingest_policy: IngestPolicy::default(),
+            counters: IngestCounters::default(),
+            series_count: AtomicUsize::new(0),
+            max_time: AtomicI64::new(i64::MIN),
+            wal: Mutex::new(None),
+            events: broadcast::channel(INGEST_EVENT_CAPACITY).0,
+        }
+    }
+
+    /// Bound memory usage with retention and size limits.
+    ///
+    /// Retention and the sample cap are applied on ingestion and by
+    /// [`MemoryStorage::compact`]; see [`MemoryStorage::spawn_compaction`].
+    ///
+    /// # Parameters
+    ///
+    /// - `limits` - Limits to enforce
+    ///
+    /// # Returns
+    ///
+    /// Returns the storage for method chaining.
+    pub fn with_limits(mut self, limits: StorageLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Choose how duplicate and out-of-order samples are handled.
+    ///
+    /// # Parameters
+    ///
+    /// - `policy` - Ingest policy for all series
+    ///
+    /// # Returns
+    ///
+    /// Returns the storage for method chaining.
+    pub fn with_ingest_policy(mut self, policy: IngestPolicy) -> Self {
+        self.ingest_policy = policy;
+        self
+    }
+
+    /// Number of stored series.
+    pub fn series_count(&self) -> usize {
+        self.series_count.load(Ordering::Relaxed)
+    }
+
+    /// Oldest timestamp accepted under the retention limit.
+    ///
+    /// # Returns
+    ///
+    /// Returns the minimum valid timestamp in milliseconds, or `None` if
+    /// retention is disabled or there is nothing to measure it from yet.
+    pub fn min_valid_time(&self) -> Option<i64> {
+        let retention = i64::try_from(self.limits.retention?.as_millis()).unwrap_or(i64::MAX);
+        let anchor = match self.limits.retention_anchor {
+            RetentionAnchor::LatestSample => {
+                let max_time = self.max_time.load(Ordering::Relaxed);
+                (max_time != i64::MIN).then_some(max_time)?
+            }
+            RetentionAnchor::Clock => {
+                let now = time::OffsetDateTime::now_utc();
+                i64::try_from(now.unix_timestamp_nanos() / 1_000_000).ok()?
+            }
+        };
+        Some(anchor.saturating_sub(retention))
+    }
+
+    /// Apply retention and the per-series sample cap to all stored series.
+    ///
+    /// Series left without samples are removed from storage and the index.
+    ///
+    /// # Returns
+    ///
+    /// Returns how many samples and series were dropped.
+    pub fn compact(&self) -> CompactionStats {
+        let mut stats = CompactionStats::default();
+        let min_valid = self.min_valid_time();
+        for shard in self.shards.iter() {
+            let (_writer, mut shard) = shard.write();
+            let Shard { symbols, series, index } = &mut *shard;
+
+            let mut emptied = Vec::new();
+            for (series_ref, stored) in &mut series.by_ref {
+                if let Some(mint) = min_valid {
+                    stats.removed_samples += stored.samples.drop_before(mint);
+                }
+                if let Some(cap) = self.limits.max_samples_per_series {
+                    stats.removed_samples += stored.samples.keep_last(cap);
+                }
+                if stored.samples.len() == 0 {
+                    emptied.push(*series_ref);
+                }
+            }
+
+            for series_ref in emptied {
+                if let Some(removed) = series.remove(series_ref) {
+                    index.remove(&removed.labels, series_ref);
+                    symbols.release_labels(&removed.labels);
+                    self.series_count.fetch_sub(1, Ordering::Relaxed);
+                    stats.removed_series += 1;
+                }
+            }
+        }
+        stats
+    }
+
+    /// Run [`MemoryStorage::compact`] periodically on the tokio runtime.
+    ///
+    /// # Parameters
+    ///
+    /// - `interval` - Time between compaction runs
+    ///
+    /// # Returns
+    ///
+    /// Returns the handle of the background task; abort it to stop compaction.
+    pub fn spawn_compaction(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let storage = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let stats = storage.compact();
+                if stats.removed_samples > 0 || stats.removed_series > 0 {
+                    tracing::debug!(
+                        "compaction removed {} samples and {} series",
+                        stats.removed_samples,
+                        stats.removed_series
+                    );
+                }
+            }
+        })
+    }
+
+    /// Capture all series and the label index in snapshot form.
+    ///
+    /// All shards are read-locked together, so the snapshot is consistent.
+    ///
+    /// # Returns
+    ///
+    /// Returns the snapshot data.
+    pub fn snapshot(&self) -> SnapshotData {
+        let shards: Vec<_> = self.shards.iter().map(|s| s.data.read().unwrap()).collect();
+        self.snapshot_locked(&shards)
+    }
+
+    /// Build snapshot data from read-locked shards.
+    fn snapshot_locked(&self, shards: &[RwLockReadGuard<'_, Shard>]) -> SnapshotData {
+        let now = time::OffsetDateTime::now_utc();
+        let mut data = SnapshotData {
+            created_at: i64::try_from(now.unix_timestamp_nanos() / 1_000_000).unwrap_or_default(),
+            max_time: self.max_time.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+
+        let mut symbol_ids: FnvHashMap<String, u32> = FnvHashMap::default();
+        let mut intern = |symbols: &mut Vec<String>, s: &str| -> u32 {
+            *symbol_ids.entry(s.to_string()).or_insert_with(|| {
+                symbols.push(s.to_string());
+                u32::try_from(symbols.len() - 1).expect("symbol count fits u32")
+            })
+        };
+
+        let mut buf = Vec::new();
+        for shard in shards {
+            let mut refs: Vec<u64> = shard.series.by_ref.keys().copied().collect();
+            refs.sort_unstable();
+
+            // Series positions in the snapshot, in reference order
+            let mut positions: FnvHashMap<u64, u64> = FnvHashMap::default();
+            for series_ref in refs {
+                let stored = &shard.series.by_ref[&series_ref];
+                let labels = stored
+                    .labels
+                    .iter()
+                    .map(|l| {
+                        let name = intern(&mut data.symbols, shard.symbols.resolve(l.name));
+                        (name, intern(&mut data.symbols, shard.symbols.resolve(l.value)))
+                    })
+                    .collect();
+                let samples = stored.samples.window(i64::MIN, i64::MAX, &mut buf).to_vec();
+                positions.insert(series_ref, data.series.len() as u64);
+                data.series.push(SnapshotSeries { labels, samples });
+            }
+
+            for (label, list) in shard.index.postings() {
+                data.postings.push(SnapshotPostings {
+                    name: intern(&mut data.symbols, shard.symbols.resolve(label.name)),
+                    value: intern(&mut data.symbols, shard.symbols.resolve(label.value)),
+                    series: list.iter().map(|r| positions[r]).collect(),
+                });
+            }
+        }
+        data
+    }
+
+    /// Atomically write a snapshot of the storage to a file.
+    ///
+    /// # Parameters
+    ///
+    /// - `path` - Destination file, replaced if it exists
+    ///
+    /// # Returns
+    ///
+    /// Returns the metadata of the written snapshot.
+    ///
+    /// If a write-ahead log is attached, it moves to a new segment at the
+    /// snapshot point and the older segments are deleted once the snapshot
+    /// is on disk.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SnapshotError::Io` if the file cannot be written, or
+    /// `SnapshotError::Wal` if the write-ahead log cannot be rotated.
+    pub fn save_snapshot(&self, path: &Path) -> Result<SnapshotMeta, SnapshotError> {
+        let (data, wal_segment) = {
+            // Writers hold their shard between logging and applying a
+            // write, so no logged but unapplied data is cut off below
+            let _writers = self.lock_writers();
+            let shards: Vec<_> = self.shards.iter().map(|s| s.data.read().unwrap()).collect();
+            let data = self.snapshot_locked(&shards);
+            let mut wal = self.wal.lock().unwrap();
+            let wal_segment = match wal.as_mut() {
+                Some(wal) => {
+                    let segment = wal.rotate()?;
+                    wal.append(&Self::wal_series_records(&shards))?;
+                    Some(segment)
+                }
+                None => None,
+            };
+            (data, wal_segment)
+        };
+
+        data.write_to(path)?;
+        if let Some(segment) = wal_segment {
+            if let Some(wal) = self.wal.lock().unwrap().as_mut() {
+                wal.truncate_before(segment)?;
+            }
+        }
+        Ok(data.meta())
+    }
+
+    /// Replay a write-ahead log and record all further writes in it.
+    ///
+    /// Replayed data is added on top of what is already stored, so a
+    /// snapshot should be restored first. Samples outside the limits are
+    /// dropped during replay like on ingestion. Records are flushed to the
+    /// operating system before their changes are applied, and a write that
+    /// cannot be logged fails with `StorageError::Wal` without being
+    /// applied. This protects against process crashes but not against
+    /// power loss.
+    ///
+    /// # Parameters
+    ///
+    /// - `dir` - Directory holding the log segments, created if missing
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of replayed samples.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `WalError` if the log cannot be read or is corrupt
+    /// anywhere but in its last record.
+    pub fn open_wal(&self, dir: &Path) -> Result<usize, WalError> {
+        let mut wal = Wal::open(dir, DEFAULT_SEGMENT_SIZE)?;
+
+        // References are only meaningful after the series record defining them
+        let mut labels_by_ref: FnvHashMap<u64, Vec<Label>> = FnvHashMap::default();
+        let mut replayed = 0;
+        for record in wal.replay()? {
+            match record {
+                WalRecord::Series { series_ref, labels } => {
+                    labels_by_ref.insert(series_ref, labels);
+                }
+                WalRecord::Samples { series_ref, samples } => {
+                    let Some(labels) = labels_by_ref.get(&series_ref) else {
+                        tracing::warn!("skipping WAL samples of unknown series {series_ref}");
+                        continue;
+                    };
+                    replayed += samples.len();
+                    let ts = TimeSeries { labels: labels.clone(), samples };
+                    if let Err(e) = self.try_add_series(ts) {
+                        tracing::debug!("dropped samples on WAL replay: {}", e);
+                    }
+                }
+                WalRecord::Tombstones { series_ref, mint, maxt } => {
+                    let Some(labels) = labels_by_ref.get_mut(&series_ref) else {
+                        tracing::warn!("skipping WAL tombstones of unknown series {series_ref}");
+                        continue;
+                    };
+                    labels.sort();
+                    let hash = self.hasher.hash_labels(labels);
+                    let (_writer, mut shard) = self.shard_for(hash).write();
+                    let existing = shard
+                        .symbols
+                        .lookup_labels(labels)
+                        .and_then(|l| shard.series.lookup(hash, &l));
+                    if let Some(stored_ref) = existing {
+                        self.delete_refs(&mut shard, &[stored_ref], mint, maxt);
+                    }
+                }
+            }
+        }
+
+        // Redefine the series under their new references before attaching
+        let _writers = self.lock_writers();
+        let shards: Vec<_> = self.shards.iter().map(|s| s.data.read().unwrap()).collect();
+        wal.append(&Self::wal_series_records(&shards))?;
+        *self.wal.lock().unwrap() = Some(wal);
+        Ok(replayed)
+    }
+
+    /// Delete samples in [mint, maxt] from the given series of a shard.
+    ///
+    /// Series left empty are removed from the shard and its index.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of deleted samples.
+    fn delete_refs(&self, shard: &mut Shard, refs: &[u64], mint: i64, maxt: i64) -> usize {
+        let Shard { symbols, series, index } = shard;
+        let mut deleted = 0;
+        for series_ref in refs {
+            let Some(stored) = series.by_ref.get_mut(series_ref) else {
+                continue;
+            };
+            deleted += stored.samples.delete_range(mint, maxt);
+            if stored.samples.len() == 0 {
+                let removed = series.remove(*series_ref).expect("stored series");
+                index.remove(&removed.labels, *series_ref);
+                symbols.release_labels(&removed.labels);
+                self.series_count.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+        deleted
+    }
+
+    /// Series records defining every stored series.
+    fn wal_series_records(shards: &[RwLockReadGuard<'_, Shard>]) -> Vec<WalRecord> {
+        shards
+            .iter()
+            .flat_map(|shard| {
+                shard.series.by_ref.iter().map(|(series_ref, stored)| WalRecord::Series {
+                    series_ref: *series_ref,
+                    labels: shard.symbols.materialize(&stored.labels),
+                })
+            })
+            .collect()
+    }
+
+    /// Append records to the write-ahead log, if one is attached.
+    ///
+    /// Must not be called while holding a shard's data lock.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `WalError` if the records could not be written.
+    fn log_wal(&self, records: impl FnOnce() -> Vec<WalRecord>) -> Result<(), WalError> {
+        match self.wal.lock().unwrap().as_mut() {
+            Some(wal) => wal.append(&records()),
+            None => Ok(()),
+        }
+    }
+
+    /// Lock out the writers of all shards, in shard order.
+    fn lock_writers(&self) -> Vec<MutexGuard<'_, ()>> {
+        self.shards.iter().map(|s| s.writer.lock().unwrap()).collect()
+    }
+
+    /// Load series and the label index from a snapshot file.
+    ///
+    /// Samples are stored in this storage's encoding; limits are applied by
+    /// the next compaction rather than on load.
+    ///
+    /// # Parameters
+    ///
+    /// - `path` - Snapshot file written by [`MemoryStorage::save_snapshot`]
+    ///
+    /// # Returns
+    ///
+    /// Returns the metadata of the loaded snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SnapshotError` if the file is unreadable or corrupt, or if
+    /// the storage already holds series.
+    pub fn restore_snapshot(&self, path: &Path) -> Result<SnapshotMeta, SnapshotError> {
+        let data = SnapshotData::read_from(path)?;
+        self.restore(&data)?;
+        Ok(data.meta())
+    }
+
+    /// Load snapshot data into this (empty) storage.
+    ///
+    /// # Parameters
+    ///
+    /// - `data` - Decoded snapshot
+    ///
+    /// # Errors
+    ///
+    /// Returns `SnapshotError::Corrupt` if series and postings disagree, or
+    /// `SnapshotError::StorageNotEmpty` if series are already stored.
+    pub fn restore(&self, data: &SnapshotData) -> Result<(), SnapshotError> {
+        Self::validate_snapshot(data)?;
+
+        let _writers = self.lock_writers();
+        let mut shards: Vec<_> = self.shards.iter().map(|s| s.data.write().unwrap()).collect();
+        if shards.iter().any(|shard| !shard.series.by_ref.is_empty()) {
+            return Err(SnapshotError::StorageNotEmpty);
+        }
+
+        // Insert series, remembering where each snapshot position went
+        let mut locations = Vec::with_capacity(data.series.len());
+        for series in &data.series {
+            let mut labels: Vec<Label> = series
+                .labels
+                .iter()
+                .map(|(n, v)| Label::new(&data.symbols[*n as usize], &data.symbols[*v as usize]))
+                .collect();
+            labels.sort();
+            let hash = self.hasher.hash_labels(&labels);
+            #[allow(clippy::cast_possible_truncation)]
+            let shard_idx = (hash % shards.len() as u64) as usize;
+            let shard = &mut *shards[shard_idx];
+
+            let mut samples = SampleBuffer::new(self.encoding);
+            for sample in &series.samples {
+                samples.add(sample.clone());
+            }
+            let labels = shard.symbols.intern_labels(&labels);
+            let series_ref = shard.series.insert(hash, MemSeries { hash, labels, samples });
+            locations.push((shard_idx, series_ref));
+        }
+
+        // Rebuild the postings from the snapshot's index; validation ensures
+        // their strings were interned with the series labels above
+        for postings in &data.postings {
+            for position in &postings.series {
+                let (shard_idx, series_ref) = locations[*position as usize];
+                let shard = &mut *shards[shard_idx];
+                let symbol = |s: u32| shard.symbols.get(&data.symbols[s as usize]);
+                let label = SymbolLabel {
+                    name: symbol(postings.name).expect("posting label of its series"),
+                    value: symbol(postings.value).expect("posting label of its series"),
+                };
+                shard.index.add_posting(label, series_ref);
+            }
+        }
+
+        self.series_count.store(data.series.len(), Ordering::Relaxed);
+        self.max_time.fetch_max(data.max_time, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Check that snapshot series are unique and postings match their labels exactly.
+    ///
+    /// Symbol and series indexes are bounds-checked, so that `restore` can
+    /// index the snapshot directly afterwards.
+    fn validate_snapshot(data: &SnapshotData) -> Result<(), SnapshotError> {
+        let symbol = |s: u32| {
+            data.symbols.get(s as usize).map(String::as_str).ok_or_else(|| {
+                SnapshotError::Corrupt(format!(
+                    "symbol {s} out of range, snapshot has {}",
+                    data.symbols.len()
+                ))
+            })
+        };
+
+        let mut seen = std::collections::HashSet::new();
+        for series in &data.series {
+            let mut labels = series
+                .labels
+                .iter()
+                .map(|(n, v)| Ok((symbol(*n)?, symbol(*v)?)))
+                .collect::<Result<Vec<_>, SnapshotError>>()?;
+            labels.sort_unstable();
+            if !seen.insert(labels) {
+                return Err(SnapshotError::Corrupt("duplicate series".to_string()));
+            }
+        }
+
+        let mut posted = 0;
+        for postings in &data.postings {
+            let (name, value) = (symbol(postings.name)?, symbol(postings.value)?);
+            for position in &postings.series {
+                let series = usize::try_from(*position).ok().and_then(|i| data.series.get(i));
+                let Some(series) = series else {
+                    return Err(SnapshotError::Corrupt(format!(
+                        "postings for {name}={value:?} list series {position}, snapshot has {}",
+                        data.series.len()
+                    )));
+                };
+                if !series.labels.contains(&(postings.name, postings.value)) {
+                    return Err(SnapshotError::Corrupt(format!(
+                        "postings for {name}={value:?} list series {position} without that label"
+                    )));
+                }
+                posted += 1;
+            }
+        }
+        let labels: usize = data.series.iter().map(|s| s.labels.len()).sum();
+        if posted != labels {
+            return Err(SnapshotError::Corrupt(
+                "label index does not cover all series".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check a sample against the ingest policy before adding it to a series.
+    ///
+    /// # Parameters
+    ///
+    /// - `stored` - Samples of the series, `None` for a new series
+    /// - `pending` - Samples of the same write already accepted, in order
+    /// - `sample` - Sample to check
+    ///
+    /// # Returns
+    ///
+    /// Returns whether the sample should be added; an exact repeat of an
+    /// existing sample is accepted but not added again.
+    fn check_sample(
+        &self,
+        stored: Option<&SampleBuffer>,
+        pending: &[Sample],
+        sample: &Sample,
+    ) -> Result<bool, StorageError> {
+        let IngestPolicy::Reject { out_of_order_window } = self.ingest_policy else {
+            return Ok(true);
+        };
+        let stored_max = stored.and_then(SampleBuffer::time_range).map(|(_, max)| max);
+        let Some(max_time) = stored_max.max(pending.iter().map(|s| s.timestamp).max()) else {
+            return Ok(true);
+        };
+        if sample.timestamp > max_time {
+            return Ok(true);
+        }
+        let existing = pending
+            .iter()
+            .find(|s| s.timestamp == sample.timestamp)
+            .cloned()
+            .or_else(|| stored.and_then(|s| s.get(sample.timestamp)));
+        if let Some(existing) = existing {
+            if existing.value.to_bits() == sample.value.to_bits() {
+                return Ok(false);
+            }
+            self.counters.duplicate_samples.fetch_add(1, Ordering::Relaxed);
+            return Err(StorageError::DuplicateSample { timestamp: sample.timestamp });
+        }
+        let window = i64::try_from(out_of_order_window.as_millis()).unwrap_or(i64::MAX);
+        let min_valid = max_time.saturating_sub(window);
+        if sample.timestamp < min_valid {
+            self.counters.out_of_order_samples.fetch_add(1, Ordering::Relaxed);
+            return Err(StorageError::OutOfOrder { timestamp: sample.timestamp, min_valid });
+        }
+        Ok(true)
+    }
+
+    /// Reserve room for a new series under the series limit.
+    fn reserve_series(&self) -> Result<(), StorageError> {
+        let Some(limit) = self.limits.max_series else {
+            self.series_count.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        };
+        self.series_count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                (count < limit).then_some(count + 1)
+            })
+            .map(|_| ())
+            .map_err(|_| StorageError::TooManySeries { limit })
+    }
+
+    /// Set the number of lock shards.
+    ///
+    /// Must be called before any series is added, as existing series are dropped.
+    ///
+    /// # Parameters
+    ///
+    /// - `count` - Number of shards (at least 1)
+    ///
+    /// # Returns
+    ///
+    /// Returns the storage for method chaining.
+    pub fn with_shard_count(mut self, count: usize) -> Self {
+        self.shards = Self::create_shards(count.max(1));
+        self.series_count = AtomicUsize::new(0);
+        self
+    }
+
+    /// Choose how samples are kept in memory.
+    ///
+    /// # Parameters
+    ///
+    /// - `encoding` - Sample encoding for stored series
+    ///
+    /// # Returns
+    ///
+    /// Returns the storage for method chaining.
+    pub fn with_encoding(mut self, encoding: SampleEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Approximate number of heap bytes used by stored samples.
+    ///
+    /// Labels and index structures are not included; this is meant for
+    /// comparing sample encodings.
+    ///
+    /// # Returns
+    ///
+    /// Returns the sample memory usage in bytes.
+    pub fn sample_bytes(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                let shard = shard.data.read().unwrap();
+                shard.series.by_ref.values().map(|s| s.samples.heap_bytes()).sum::<usize>()
+            })
+            .sum()
+    }
 
-impl MemoryStorage {
-    /// Create a new empty in-memory storage.
+    /// Approximate number of heap bytes used by series labels.
+    ///
+    /// Includes the interned strings and the per-series symbol arrays, but
+    /// not the postings lists.
     ///
     /// # Returns
-    /// Returns a new `MemoryStorage` instance with empty series and label index.
-    pub fn new() -> Self {
-        Self {
-            series: RwLock::new(FnvHashMap::default()),
-            label_index: RwLock::new(FnvHashMap::default()),
-        }
+    ///
+    /// Returns the label memory usage in bytes.
+    pub fn label_bytes(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                let shard = shard.data.read().unwrap();
+                let series: usize = shard
+                    .series
+                    .by_ref
+                    .values()
+                    .map(|s| std::mem::size_of_val::<[SymbolLabel]>(&s.labels))
+                    .sum();
+                series + shard.symbols.heap_bytes()
+            })
+            .sum()
     }
 
-    /// Generate fingerprint for a label set
-    fn fingerprint(labels: &[Label]) -> u64 {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
+    /// Use a custom hash function for label sets.
+    ///
+    /// # Parameters
+    ///
+    /// - `hasher` - Label set hasher, e.g. a deliberately colliding one in tests
+    ///
+    /// # Returns
+    ///
+    /// Returns the storage for method chaining.
+    pub fn with_hasher(mut self, hasher: Arc<dyn LabelsHasher>) -> Self {
+        self.hasher = hasher;
+        self
+    }
 
-        let mut hasher = DefaultHasher::new();
-        for label in labels {
-            label.hash(&mut hasher);
+    /// Query series by evaluating every matcher against every stored series.
+    ///
+    /// This bypasses the label index and is mainly useful as a reference to
+    /// verify (and benchmark) the indexed [`Storage::query_series`] path.
+    ///
+    /// # Parameters
+    ///
+    /// - `matchers` - Array of label matchers to filter series
+    ///
+    /// # Returns
+    ///
+    /// Returns a vector of matching time series.
+    pub fn scan_series(&self, matchers: &[Arc<dyn LabelMatcher>]) -> Vec<TimeSeries> {
+        let mut result = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard.data.read().unwrap();
+            result.extend(
+                shard
+                    .series
+                    .by_ref
+                    .values()
+                    .map(|s| s.to_time_series(&shard.symbols))
+                    .filter(|ts| Self::matches_series(&ts.labels, matchers)),
+            );
         }
-        hasher.finish()
+        result
     }
 
-    /// Update label index for new series
-    fn update_label_index(&self, labels: &[Label], fp: u64) {
-        let mut index = self.label_index.write().unwrap();
+    /// Create `count` empty shards with interleaved series references.
+    fn create_shards(count: usize) -> Box<[ShardLock]> {
+        (0..count as u64)
+            .map(|id| ShardLock {
+                writer: Mutex::new(()),
+                data: RwLock::new(Shard {
+                    symbols: SymbolTable::new(),
+                    series: SeriesMap::new(id, count as u64),
+                    index: LabelIndex::new(),
+                }),
+            })
+            .collect()
+    }
 
-        for label in labels {
-            let name_map = index.entry(label.name.clone()).or_default();
-            let series_list = name_map.entry(label.value.clone()).or_default();
-            if !series_list.contains(&fp) {
-                series_list.push(fp);
-            }
-        }
+    /// Get the shard owning a label set hash.
+    #[allow(clippy::cast_possible_truncation)]
+    fn shard_for(&self, hash: u64) -> &ShardLock {
+        &self.shards[(hash % self.shards.len() as u64) as usize]
     }
 
-    /// Check if a time series matches all label matchers
-    fn matches_series(ts: &TimeSeries, matchers: &[Arc<dyn LabelMatcher>]) -> bool {
+    /// Check if a label set matches all label matchers
+    fn matches_series(labels: &[Label], matchers: &[Arc<dyn LabelMatcher>]) -> bool {
         for matcher in matchers {
-            if !matcher.matches(&ts.labels) {
+            if !matcher.matches(labels) {
                 return false;
             }
         }
@@ -74,61 +1207,261 @@ impl MemoryStorage {
 
 impl Storage for MemoryStorage {
     fn add_series(&self, ts: TimeSeries) {
-        let fp = Self::fingerprint(&ts.labels);
-
-        // Update series store
-        {
-            let mut series = self.series.write().unwrap();
-            if let Some(existing) = series.get_mut(&fp) {
-                // Merge samples
-                for sample in ts.samples {
-                    existing.add_sample(sample);
+        match self.try_add_series(ts) {
+            Ok(()) => {}
+            Err(e @ StorageError::Wal(_)) => tracing::error!("dropped samples on ingestion: {}", e),
+            Err(e) => tracing::debug!("dropped samples on ingestion: {}", e),
+        }
+    }
+
+    fn try_add_series(&self, mut ts: TimeSeries) -> Result<(), StorageError> {
+        // Canonical label order makes {a,b} and {b,a} the same series
+        ts.labels.sort();
+        let hash = self.hasher.hash_labels(&ts.labels);
+
+        // Samples older than the retention window are rejected up front
+        let mut result = Ok(());
+        let had_samples = !ts.samples.is_empty();
+        if let Some(min_valid) = self.min_valid_time() {
+            if let Some(sample) = ts.samples.iter().find(|s| s.timestamp < min_valid) {
+                result = Err(StorageError::OutOfBounds { timestamp: sample.timestamp, min_valid });
+                let before = ts.samples.len();
+                ts.samples.retain(|s| s.timestamp >= min_valid);
+                let dropped = (before - ts.samples.len()) as u64;
+                self.counters.out_of_bounds_samples.fetch_add(dropped, Ordering::Relaxed);
+            }
+        }
+
+        let shard_lock = self.shard_for(hash);
+        let _writer = shard_lock.writer.lock().unwrap();
+
+        // Prepare the write under the read lock; holding the writer lock
+        // keeps the shard unchanged until it is applied below
+        let (existing, series_ref, appended) = {
+            let shard = shard_lock.data.read().unwrap();
+            // Labels with unknown strings cannot belong to an existing series
+            let existing =
+                shard.symbols.lookup_labels(&ts.labels).and_then(|l| shard.series.lookup(hash, &l));
+            let series_ref = if let Some(series_ref) = existing {
+                series_ref
+            } else if !had_samples || ts.samples.iter().any(|s| !s.is_stale()) {
+                // New series; staleness markers alone only end series, they never start one
+                if let Err(e) = self.reserve_series() {
+                    self.counters.rejected_series.fetch_add(1, Ordering::Relaxed);
+                    return Err(e);
                 }
+                shard.series.peek_ref()
             } else {
-                // Update label index
-                self.update_label_index(&ts.labels, fp);
-                series.insert(fp, ts);
+                return result;
+            };
+            let stored = existing.map(|r| &shard.series.by_ref[&r].samples);
+
+            // Check samples in request order, so policy checks see earlier ones
+            let mut appended = Vec::with_capacity(ts.samples.len());
+            for sample in ts.samples {
+                match self.check_sample(stored, &appended, &sample) {
+                    Ok(true) => appended.push(sample),
+                    Ok(false) => {}
+                    Err(e) => result = result.and(Err(e)),
+                }
+            }
+            (existing, series_ref, appended)
+        };
+
+        // Log before applying, without blocking readers of the shard
+        if existing.is_none() || !appended.is_empty() {
+            let logged = self.log_wal(|| {
+                let mut records = Vec::with_capacity(2);
+                if existing.is_none() {
+                    records.push(WalRecord::Series { series_ref, labels: ts.labels.clone() });
+                }
+                records.push(WalRecord::Samples { series_ref, samples: appended.clone() });
+                records
+            });
+            if let Err(e) = logged {
+                if existing.is_none() {
+                    self.series_count.fetch_sub(1, Ordering::Relaxed);
+                }
+                return Err(StorageError::Wal(e.to_string()));
             }
         }
+
+        let mut shard = shard_lock.data.write().unwrap();
+        let Shard { symbols, series, index } = &mut *shard;
+        if existing.is_none() {
+            let labels = symbols.intern_labels(&ts.labels);
+            let samples = SampleBuffer::new(self.encoding);
+            let inserted = series.insert(hash, MemSeries { hash, labels, samples });
+            debug_assert_eq!(inserted, series_ref, "logged series reference");
+            index.add(&series.by_ref[&series_ref].labels, series_ref);
+        }
+        let stored = series.by_ref.get_mut(&series_ref).expect("referenced series");
+        for sample in &appended {
+            stored.samples.add(sample.clone());
+        }
+        if let Some(cap) = self.limits.max_samples_per_series {
+            stored.samples.keep_last(cap);
+        }
+        drop(shard);
+
+        if (existing.is_none() || !appended.is_empty()) && self.events.receiver_count() > 0 {
+            // Fails only when the last subscriber went away meanwhile
+            let _ = self.events.send(IngestEvent {
+                labels: ts.labels,
+                samples: appended.len(),
+                created: existing.is_none(),
+            });
+        }
+        if let Some(max) = appended.iter().map(|s| s.timestamp).max() {
+            self.max_time.fetch_max(max, Ordering::Relaxed);
+        }
+        self.counters.samples_appended.fetch_add(appended.len() as u64, Ordering::Relaxed);
+        result
     }
 
-    fn query_series(&self, matchers: &[Arc<dyn LabelMatcher>]) -> Vec<TimeSeries> {
-        let mut results = Vec::new();
+    fn ingest_stats(&self) -> IngestStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        IngestStats {
+            series: self.series_count() as u64,
+            samples_appended: load(&self.counters.samples_appended),
+            out_of_order_samples: load(&self.counters.out_of_order_samples),
+            duplicate_samples: load(&self.counters.duplicate_samples),
+            out_of_bounds_samples: load(&self.counters.out_of_bounds_samples),
+            rejected_series: load(&self.counters.rejected_series),
+        }
+    }
 
-        {
-            let series = self.series.read().unwrap();
+    fn subscribe(&self) -> broadcast::Receiver<IngestEvent> {
+        self.events.subscribe()
+    }
 
-            for ts in series.values() {
-                if Self::matches_series(ts, matchers) {
-                    results.push(ts.clone());
-                }
+    fn delete_series(&self, mint: i64, maxt: i64, matchers: &[Arc<dyn LabelMatcher>]) -> usize {
+        let mut deleted = 0;
+        for shard in self.shards.iter() {
+            let _writer = shard.writer.lock().unwrap();
+            let refs = shard.data.read().unwrap().select_refs(matchers);
+            if refs.is_empty() {
+                continue;
+            }
+            let logged = self.log_wal(|| {
+                refs.iter()
+                    .map(|series_ref| WalRecord::Tombstones { series_ref: *series_ref, mint, maxt })
+                    .collect()
+            });
+            if let Err(e) = logged {
+                tracing::error!("failed to write WAL, series not deleted: {}", e);
+                continue;
             }
+            deleted += self.delete_refs(&mut shard.data.write().unwrap(), &refs, mint, maxt);
         }
+        deleted
+    }
+
+    /// Admin snapshots leave the write-ahead log untouched, so that restarting
+    /// from an older snapshot can still replay everything written since.
+    fn snapshot_to(&self, path: &Path) -> std::io::Result<()> {
+        self.snapshot().write_to(path).map_err(|e| match e {
+            SnapshotError::Io(e) => e,
+            e => std::io::Error::other(e),
+        })
+    }
+
+    fn select<'a>(
+        &'a self,
+        mint: i64,
+        maxt: i64,
+        matchers: &[Arc<dyn LabelMatcher>],
+    ) -> Box<dyn SeriesSet + 'a> {
+        Box::new(MemorySeriesSet {
+            shards: self.shards.iter(),
+            matchers: matchers.to_vec(),
+            current: None,
+            refs: Vec::new().into_iter(),
+            mint,
+            maxt,
+            buf: Vec::new(),
+            labels: Vec::new(),
+        })
+    }
+}
+
+/// Series set over `MemoryStorage`.
+///
+/// Shards are visited one after another, holding only the read lock of the
+/// shard currently being iterated.
+struct MemorySeriesSet<'a> {
+    shards: std::slice::Iter<'a, ShardLock>,
+    matchers: Vec<Arc<dyn LabelMatcher>>,
+    current: Option<RwLockReadGuard<'a, Shard>>,
+    refs: std::vec::IntoIter<u64>,
+    mint: i64,
+    maxt: i64,
+    /// Decode buffer reused across compressed series
+    buf: Vec<Sample>,
+    /// Materialized labels of the current series
+    labels: Vec<Label>,
+}
 
-        results
+impl SeriesSet for MemorySeriesSet<'_> {
+    fn next(&mut self) -> Option<SeriesView<'_>> {
+        let series_ref = loop {
+            if let Some(series_ref) = self.refs.next() {
+                break series_ref;
+            }
+            // Release the previous shard before locking the next one
+            self.current = None;
+            let shard = self.shards.next()?.data.read().unwrap();
+            self.refs = shard.select_refs(&self.matchers).into_iter();
+            self.current = Some(shard);
+        };
+
+        let shard = self.current.as_ref().expect("locked shard");
+        let stored = &shard.series.by_ref[&series_ref];
+        self.labels = shard.symbols.materialize(&stored.labels);
+        let samples = stored.samples.window(self.mint, self.maxt, &mut self.buf);
+        Some(SeriesView { labels: &self.labels, samples })
     }
 }
 
 impl MetadataStorage for MemoryStorage {
     fn label_names(&self) -> Vec<String> {
-        let index = self.label_index.read().unwrap();
-        let mut names: Vec<String> = index.keys().cloned().collect();
-        names.sort();
-        names
+        let mut names = BTreeSet::new();
+        for shard in self.shards.iter() {
+            let shard = shard.data.read().unwrap();
+            names.extend(shard.index.names(&shard.symbols));
+        }
+        names.into_iter().collect()
     }
 
     fn label_values(&self, name: &str) -> Vec<String> {
-        let index = self.label_index.read().unwrap();
-
         // Return all unique label values for the given label name, sorted for determinism.
-        index
-            .get(name)
-            .map(|name_map| {
-                let mut values: Vec<String> = name_map.keys().cloned().collect();
-                values.sort();
-                values
-            })
-            .unwrap_or_default()
+        let mut values = BTreeSet::new();
+        for shard in self.shards.iter() {
+            let shard = shard.data.read().unwrap();
+            values.extend(shard.index.values(&shard.symbols, name));
+        }
+        values.into_iter().collect()
+    }
+
+    fn tsdb_stats(&self, limit: usize) -> TsdbStats {
+        let mut head = HeadStats::default();
+        let mut builder = TsdbStatsBuilder::new();
+        for shard in self.shards.iter() {
+            let shard = shard.data.read().unwrap();
+            for stored in shard.series.by_ref.values() {
+                head.num_series += 1;
+                head.chunk_count += stored.samples.chunk_count() as u64;
+                if let Some((mint, maxt)) = stored.samples.time_range() {
+                    head.min_time = head.min_time.min(mint);
+                    head.max_time = head.max_time.max(maxt);
+                }
+            }
+            for (label, list) in shard.index.postings() {
+                let name = shard.symbols.resolve(label.name);
+                builder.add_postings(name, shard.symbols.resolve(label.value), list.len() as u64);
+            }
+        }
+        builder.build(head, limit)
     }
 }
 
@@ -137,7 +1470,6 @@ impl FullStorage for MemoryStorage {}
 #[cfg(test)]
 mod tests {
     use crate::matchers::{EqualMatcher, NotEqualMatcher};
-    use crate::storage::Sample;
 
     use super::*;
 
@@ -341,4 +1673,849 @@ mod tests {
         let results = storage.query_series(&matchers);
         assert_eq!(results.len(), 0);
     }
+
+    /// Test staleness markers on ingestion: kept for known series, ignored for new ones.
+    #[test]
+    fn test_stale_marker_ingestion() {
+        let storage = MemoryStorage::new();
+        let labels = vec![Label::new("__name__", "up"), Label::new("job", "api")];
+
+        // A marker for an unknown series does not create it
+        let mut ts = TimeSeries::new(labels.clone());
+        ts.add_sample(Sample::stale_marker(1000));
+        storage.add_series(ts);
+        assert!(storage.query_series(&[]).is_empty());
+        assert!(storage.label_names().is_empty());
+
+        // A marker for an existing series is stored with its exact bit pattern
+        let mut ts = TimeSeries::new(labels.clone());
+        ts.add_sample(Sample::new(2000, 1.0));
+        storage.add_series(ts);
+        let mut ts = TimeSeries::new(labels);
+        ts.add_sample(Sample::stale_marker(3000));
+        storage.add_series(ts);
+
+        let series = storage.query_series(&[]);
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].samples.len(), 2);
+        assert!(series[0].samples[1].is_stale());
+    }
+
+    /// Test that indexed queries return exactly what a full scan returns.
+    #[test]
+    fn test_index_matches_scan() {
+        use regex::Regex;
+
+        use crate::matchers::{NotRegexMatcher, RegexMatcher};
+
+        let storage = MemoryStorage::new();
+        for i in 0..200 {
+            let mut labels = vec![
+                Label::new("__name__", if i % 2 == 0 { "http_requests" } else { "cpu_usage" }),
+                Label::new("job", format!("job{}", i % 7)),
+                Label::new("instance", format!("host{i}")),
+            ];
+            if i % 3 == 0 {
+                labels.push(Label::new("env", "prod"));
+            }
+            storage.add_series(TimeSeries::new(labels));
+        }
+
+        let queries: Vec<Vec<Arc<dyn LabelMatcher>>> = vec![
+            vec![],
+            vec![Arc::new(EqualMatcher::new("__name__", "http_requests"))],
+            vec![
+                Arc::new(EqualMatcher::new("__name__", "cpu_usage")),
+                Arc::new(RegexMatcher::new("job", Regex::new("job[1-3]").expect("valid regex"))),
+            ],
+            vec![Arc::new(NotEqualMatcher::new("env", "prod"))],
+            vec![
+                Arc::new(RegexMatcher::new("instance", Regex::new("host1").expect("valid regex"))),
+                Arc::new(NotRegexMatcher::new("job", Regex::new("job[0-2]").expect("valid"))),
+                Arc::new(NotEqualMatcher::new("__name__", "cpu_usage")),
+            ],
+            vec![Arc::new(EqualMatcher::new("env", ""))],
+            vec![Arc::new(EqualMatcher::new("missing", "value"))],
+        ];
+
+        for matchers in queries {
+            let mut indexed: Vec<Vec<Label>> =
+                storage.query_series(&matchers).into_iter().map(|ts| ts.labels).collect();
+            let mut scanned: Vec<Vec<Label>> =
+                storage.scan_series(&matchers).into_iter().map(|ts| ts.labels).collect();
+            indexed.sort();
+            scanned.sort();
+            assert_eq!(indexed, scanned, "matchers: {matchers:?}");
+        }
+    }
+
+    /// Test select restricts samples to the window and borrows them from storage.
+    #[test]
+    fn test_select_window() {
+        let storage = MemoryStorage::new();
+
+        let mut ts = TimeSeries::new(vec![Label::new("__name__", "cpu"), Label::new("job", "api")]);
+        for i in 0..1000 {
+            ts.add_sample(Sample::new(i * 1000, i as f64));
+        }
+        storage.add_series(ts);
+        storage.add_series(TimeSeries::new(vec![Label::new("__name__", "cpu")]));
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> = vec![Arc::new(EqualMatcher::new("job", "api"))];
+        let mut set = storage.select(10_000, 14_000, &matchers);
+        let view = set.next().expect("one series");
+        assert_eq!(view.labels.len(), 2);
+        assert_eq!(view.samples.len(), 5);
+        assert_eq!(view.samples[0], Sample::new(10_000, 10.0));
+        assert_eq!(view.samples[4], Sample::new(14_000, 14.0));
+        assert!(set.next().is_none());
+        drop(set);
+
+        // Series without samples in the window are still yielded
+        let matchers: Vec<Arc<dyn LabelMatcher>> =
+            vec![Arc::new(EqualMatcher::new("__name__", "cpu"))];
+        let mut set = storage.select(2_000_000, 3_000_000, &matchers);
+        let mut count = 0;
+        while let Some(view) = set.next() {
+            assert!(view.samples.is_empty());
+            count += 1;
+        }
+        assert_eq!(count, 2);
+    }
+
+    /// Hasher that maps every label set to the same bucket.
+    struct CollidingHasher;
+
+    impl LabelsHasher for CollidingHasher {
+        fn hash_labels(&self, _labels: &[Label]) -> u64 {
+            42
+        }
+    }
+
+    /// Test that forced hash collisions never merge unrelated series.
+    #[test]
+    fn test_hash_collisions_keep_series_apart() {
+        let storage = MemoryStorage::new().with_hasher(Arc::new(CollidingHasher));
+
+        for job in ["api", "web", "worker"] {
+            let mut ts =
+                TimeSeries::new(vec![Label::new("__name__", "up"), Label::new("job", job)]);
+            ts.add_sample(Sample::new(1000, 1.0));
+            storage.add_series(ts);
+        }
+        // Same labels again merges into the existing series despite the collision
+        let mut ts = TimeSeries::new(vec![Label::new("__name__", "up"), Label::new("job", "web")]);
+        ts.add_sample(Sample::new(2000, 2.0));
+        storage.add_series(ts);
+
+        assert_eq!(storage.query_series(&[]).len(), 3);
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> = vec![Arc::new(EqualMatcher::new("job", "web"))];
+        let series = storage.query_series(&matchers);
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].samples, vec![Sample::new(1000, 1.0), Sample::new(2000, 2.0)]);
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> =
+            vec![Arc::new(EqualMatcher::new("job", "worker"))];
+        let series = storage.query_series(&matchers);
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].samples, vec![Sample::new(1000, 1.0)]);
+    }
+
+    /// Test that label order does not affect series identity.
+    #[test]
+    fn test_label_order_is_canonical() {
+        let storage = MemoryStorage::new();
+
+        let mut ts = TimeSeries::new(vec![Label::new("b", "2"), Label::new("a", "1")]);
+        ts.add_sample(Sample::new(1000, 1.0));
+        storage.add_series(ts);
+
+        let mut ts = TimeSeries::new(vec![Label::new("a", "1"), Label::new("b", "2")]);
+        ts.add_sample(Sample::new(2000, 2.0));
+        storage.add_series(ts);
+
+        let series = storage.query_series(&[]);
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].labels, vec![Label::new("a", "1"), Label::new("b", "2")]);
+        assert_eq!(series[0].samples.len(), 2);
+    }
+
+    /// Test the default hasher separates label boundaries.
+    #[test]
+    fn test_default_hasher_separators() {
+        let hasher = FnvLabelsHasher;
+        let a = hasher.hash_labels(&[Label::new("a", "bc")]);
+        let b = hasher.hash_labels(&[Label::new("ab", "c")]);
+        assert_ne!(a, b);
+        assert_eq!(a, hasher.hash_labels(&[Label::new("a", "bc")]));
+    }
+
+    /// Build the same data set in a storage with the given encoding.
+    fn create_encoded_storage(encoding: SampleEncoding) -> MemoryStorage {
+        let storage = MemoryStorage::new().with_encoding(encoding);
+        for i in 0..20 {
+            let mut ts = TimeSeries::new(vec![
+                Label::new("__name__", "node_cpu_seconds_total"),
+                Label::new("cpu", i.to_string()),
+            ]);
+            for j in 0..1000 {
+                ts.add_sample(Sample::new(1_700_000_000_000 + j * 15_000, (j / 10) as f64));
+            }
+            storage.add_series(ts);
+        }
+        storage
+    }
+
+    /// Test the XOR encoding returns the same data as raw samples, including merges.
+    #[test]
+    fn test_xor_encoding_matches_raw() {
+        let raw = create_encoded_storage(SampleEncoding::Raw);
+        let xor = create_encoded_storage(SampleEncoding::Xor);
+
+        // Out-of-order and duplicate merges go through the rebuild path
+        for storage in [&raw, &xor] {
+            let mut ts = TimeSeries::new(vec![
+                Label::new("__name__", "node_cpu_seconds_total"),
+                Label::new("cpu", "3"),
+            ]);
+            ts.add_sample(Sample::new(1_700_000_000_001, -1.0));
+            ts.add_sample(Sample::new(1_700_000_015_000, 99.0));
+            storage.add_series(ts);
+        }
+
+        let collect = |storage: &MemoryStorage| {
+            let mut series: Vec<(Vec<Label>, Vec<Sample>)> =
+                storage.query_series(&[]).into_iter().map(|ts| (ts.labels, ts.samples)).collect();
+            series.sort_by(|a, b| a.0.cmp(&b.0));
+            series
+        };
+        assert_eq!(collect(&xor), collect(&raw));
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> = vec![Arc::new(EqualMatcher::new("cpu", "7"))];
+        let mut set = xor.select(1_700_000_150_000, 1_700_000_180_000, &matchers);
+        let view = set.next().expect("one series");
+        assert_eq!(view.samples.len(), 3);
+        assert_eq!(view.samples[0].timestamp, 1_700_000_150_000);
+        assert!(set.next().is_none());
+    }
+
+    /// Test the XOR encoding uses much less sample memory than raw vectors.
+    #[test]
+    fn test_xor_encoding_memory_usage() {
+        let raw = create_encoded_storage(SampleEncoding::Raw).sample_bytes();
+        let xor = create_encoded_storage(SampleEncoding::Xor).sample_bytes();
+
+        assert!(raw >= 20 * 1000 * std::mem::size_of::<Sample>());
+        assert!(xor * 5 < raw, "xor {xor} bytes vs raw {raw} bytes");
+    }
+
+    /// Test parsing sample encodings from strings.
+    #[test]
+    fn test_sample_encoding_from_str() {
+        assert_eq!("raw".parse::<SampleEncoding>(), Ok(SampleEncoding::Raw));
+        assert_eq!("xor".parse::<SampleEncoding>(), Ok(SampleEncoding::Xor));
+        assert!("gorilla".parse::<SampleEncoding>().is_err());
+    }
+
+    /// Stress test: concurrent writers and readers see a consistent storage.
+    #[test]
+    fn test_concurrent_writers_and_readers() {
+        const WRITERS: usize = 8;
+        const SERIES_PER_WRITER: usize = 100;
+        const ROUNDS: i64 = 20;
+
+        let storage = Arc::new(MemoryStorage::new());
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|w| {
+                let storage = Arc::clone(&storage);
+                std::thread::spawn(move || {
+                    for round in 0..ROUNDS {
+                        for s in 0..SERIES_PER_WRITER {
+                            let mut ts = TimeSeries::new(vec![
+                                Label::new("__name__", "stress"),
+                                Label::new("writer", w.to_string()),
+                                Label::new("series", s.to_string()),
+                            ]);
+                            ts.add_sample(Sample::new(round * 1000, round as f64));
+                            storage.add_series(ts);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let readers: Vec<_> = (0..4)
+            .map(|r| {
+                let storage = Arc::clone(&storage);
+                let done = Arc::clone(&done);
+                std::thread::spawn(move || {
+                    let matchers: Vec<Arc<dyn LabelMatcher>> =
+                        vec![Arc::new(EqualMatcher::new("writer", r.to_string()))];
+                    let mut last_count = 0;
+                    while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                        let mut set = storage.select(i64::MIN, i64::MAX, &matchers);
+                        let mut count = 0;
+                        while let Some(view) = set.next() {
+                            assert!(view.labels.contains(&Label::new("writer", r.to_string())));
+                            assert!(view
+                                .samples
+                                .windows(2)
+                                .all(|w| w[0].timestamp < w[1].timestamp));
+                            count += 1;
+                        }
+                        drop(set);
+                        // Series are never lost once visible
+                        assert!(count >= last_count);
+                        last_count = count;
+                        assert!(storage.label_values("writer").len() <= WRITERS);
+                    }
+                })
+            })
+            .collect();
+
+        for writer in writers {
+            writer.join().expect("writer thread");
+        }
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+        for reader in readers {
+            reader.join().expect("reader thread");
+        }
+
+        let series = storage.query_series(&[]);
+        assert_eq!(series.len(), WRITERS * SERIES_PER_WRITER);
+        assert!(series.iter().all(|ts| ts.samples.len() == ROUNDS as usize));
+        assert_eq!(storage.label_values("writer").len(), WRITERS);
+    }
+
+    /// Test results do not depend on the number of shards.
+    #[test]
+    fn test_shard_count_is_transparent() {
+        let single = MemoryStorage::new().with_shard_count(1);
+        let sharded = MemoryStorage::new().with_shard_count(7);
+        for storage in [&single, &sharded] {
+            for i in 0..50 {
+                let mut ts = TimeSeries::new(vec![
+                    Label::new("__name__", "up"),
+                    Label::new("instance", format!("host{i}")),
+                ]);
+                ts.add_sample(Sample::new(1000, f64::from(i)));
+                storage.add_series(ts);
+            }
+        }
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> =
+            vec![Arc::new(NotEqualMatcher::new("instance", "host3"))];
+        let labels = |storage: &MemoryStorage| {
+            let mut labels: Vec<Vec<Label>> =
+                storage.query_series(&matchers).into_iter().map(|ts| ts.labels).collect();
+            labels.sort();
+            labels
+        };
+        assert_eq!(labels(&single).len(), 49);
+        assert_eq!(labels(&single), labels(&sharded));
+        assert_eq!(single.label_values("instance"), sharded.label_values("instance"));
+        assert_eq!(single.label_names(), sharded.label_names());
+    }
+
+    /// Test interned labels use a fraction of the memory of owned strings.
+    #[test]
+    fn test_label_interning_memory_usage() {
+        let storage = MemoryStorage::new();
+        let mut owned_bytes = 0;
+        for i in 0..5000 {
+            let labels = vec![
+                Label::new("__name__", "http_requests_total"),
+                Label::new("job", format!("job-{}", i % 10)),
+                Label::new("instance", format!("host-{:04}.example.org:9100", i % 500)),
+                Label::new("method", if i % 2 == 0 { "GET" } else { "POST" }),
+                Label::new("series", i.to_string()),
+            ];
+            owned_bytes += labels
+                .iter()
+                .map(|l| l.name.len() + l.value.len() + 2 * std::mem::size_of::<String>())
+                .sum::<usize>();
+            storage.add_series(TimeSeries::new(labels));
+        }
+
+        let interned_bytes = storage.label_bytes();
+        assert!(interned_bytes * 2 < owned_bytes, "{interned_bytes} vs {owned_bytes}");
+
+        // Labels are materialized unchanged at the API boundary
+        let matchers: Vec<Arc<dyn LabelMatcher>> =
+            vec![Arc::new(EqualMatcher::new("series", "1234"))];
+        let series = storage.query_series(&matchers);
+        assert_eq!(series.len(), 1);
+        assert_eq!(
+            series[0].labels,
+            vec![
+                Label::new("__name__", "http_requests_total"),
+                Label::new("instance", "host-0234.example.org:9100"),
+                Label::new("job", "job-4"),
+                Label::new("method", "GET"),
+                Label::new("series", "1234"),
+            ]
+        );
+    }
+
+    /// Test label strings of removed series are released, so churn does not grow memory.
+    #[test]
+    fn test_label_churn_releases_symbols() {
+        let limits =
+            StorageLimits { retention: Some(Duration::from_secs(60)), ..Default::default() };
+        let storage = MemoryStorage::new().with_limits(limits);
+        let pod = |i: usize, t: i64| {
+            let mut ts = TimeSeries::new(vec![
+                Label::new("__name__", "up"),
+                Label::new("pod", format!("api-{i:08}")),
+            ]);
+            ts.add_sample(Sample::new(t, 1.0));
+            ts
+        };
+
+        // Each generation of pods ages out of retention at the next compaction
+        let symbols = || -> usize {
+            storage.shards.iter().map(|s| s.data.read().unwrap().symbols.len()).sum()
+        };
+        for generation in 0..20 {
+            let t = generation * 120_000;
+            for i in 0..100 {
+                storage.add_series(pod(generation as usize * 100 + i, t));
+            }
+            storage.compact();
+            assert_eq!(storage.series_count(), 100);
+            // 100 pod names plus `__name__`, `up` and `pod` in each shard
+            assert!(symbols() <= 100 + 3 * DEFAULT_SHARD_COUNT, "{} symbols", symbols());
+        }
+
+        // Deleting the last generation leaves no strings behind
+        let matchers: Vec<Arc<dyn LabelMatcher>> =
+            vec![Arc::new(EqualMatcher::new("__name__", "up"))];
+        storage.delete_series(i64::MIN, i64::MAX, &matchers);
+        assert_eq!(storage.series_count(), 0);
+        assert!(storage.shards.iter().all(|s| s.data.read().unwrap().symbols.is_empty()));
+        assert!(storage.label_names().is_empty());
+    }
+
+    fn create_limited_series(
+        storage: &MemoryStorage,
+        name: &str,
+        timestamps: &[i64],
+    ) -> Result<(), StorageError> {
+        let mut ts = TimeSeries::new(vec![Label::new("__name__", name)]);
+        for &timestamp in timestamps {
+            ts.add_sample(Sample::new(timestamp, 1.0));
+        }
+        storage.try_add_series(ts)
+    }
+
+    /// Test the active series limit rejects new series but not existing ones.
+    #[test]
+    fn test_max_series_limit() {
+        let limits = StorageLimits { max_series: Some(2), ..Default::default() };
+        let storage = MemoryStorage::new().with_limits(limits);
+
+        assert!(create_limited_series(&storage, "a", &[1000]).is_ok());
+        assert!(create_limited_series(&storage, "b", &[1000]).is_ok());
+        assert_eq!(
+            create_limited_series(&storage, "c", &[1000]),
+            Err(StorageError::TooManySeries { limit: 2 })
+        );
+        assert!(create_limited_series(&storage, "a", &[2000]).is_ok());
+        assert_eq!(storage.series_count(), 2);
+        assert_eq!(storage.label_values("__name__"), vec!["a", "b"]);
+    }
+
+    /// Test retention rejects old samples on ingestion and drops them on compaction.
+    #[test]
+    fn test_retention() {
+        for encoding in [SampleEncoding::Raw, SampleEncoding::Xor] {
+            let limits =
+                StorageLimits { retention: Some(Duration::from_secs(10)), ..Default::default() };
+            let storage = MemoryStorage::new().with_limits(limits).with_encoding(encoding);
+            assert_eq!(storage.min_valid_time(), None);
+
+            assert!(create_limited_series(&storage, "old", &[1000, 2000]).is_ok());
+            assert!(create_limited_series(&storage, "new", &[5000, 15_000]).is_ok());
+            assert_eq!(storage.min_valid_time(), Some(5000));
+
+            // Partially out of bounds: valid samples are kept
+            assert_eq!(
+                create_limited_series(&storage, "new", &[4000, 16_000]),
+                Err(StorageError::OutOfBounds { timestamp: 4000, min_valid: 5000 })
+            );
+            // Entirely out of bounds samples never create a series
+            assert!(create_limited_series(&storage, "ancient", &[10]).is_err());
+
+            let stats = storage.compact();
+            // The newest sample moved the window to [6000, 16000]
+            assert_eq!(stats, CompactionStats { removed_samples: 3, removed_series: 1 });
+            assert_eq!(storage.series_count(), 1);
+            assert_eq!(storage.label_values("__name__"), vec!["new"]);
+
+            let series = storage.query_series(&[]);
+            let timestamps: Vec<i64> = series[0].samples.iter().map(|s| s.timestamp).collect();
+            assert_eq!(timestamps, vec![15_000, 16_000]);
+        }
+    }
+
+    /// Test the per-series sample cap keeps the newest samples.
+    #[test]
+    fn test_max_samples_per_series() {
+        let limits = StorageLimits { max_samples_per_series: Some(3), ..Default::default() };
+        let storage = MemoryStorage::new().with_limits(limits);
+
+        assert!(create_limited_series(&storage, "a", &[1000, 2000, 3000, 4000, 5000]).is_ok());
+        let series = storage.query_series(&[]);
+        let timestamps: Vec<i64> = series[0].samples.iter().map(|s| s.timestamp).collect();
+        assert_eq!(timestamps, vec![3000, 4000, 5000]);
+        assert_eq!(storage.compact(), CompactionStats::default());
+    }
+
+    /// Test the background compaction task applies retention.
+    #[tokio::test]
+    async fn test_spawn_compaction() {
+        let limits = StorageLimits {
+            retention: Some(Duration::from_secs(1)),
+            max_samples_per_series: Some(1),
+            ..Default::default()
+        };
+        let storage = Arc::new(MemoryStorage::new().with_limits(limits));
+        assert!(create_limited_series(&storage, "a", &[1000]).is_ok());
+        assert!(create_limited_series(&storage, "b", &[5000]).is_ok());
+
+        // The first tick fires immediately
+        let task = storage.spawn_compaction(Duration::from_secs(30));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(storage.series_count(), 1);
+        task.abort();
+    }
+
+    /// Test saving and restoring a snapshot reproduces series, index and metadata.
+    #[test]
+    fn test_snapshot_roundtrip() {
+        use regex::Regex;
+
+        use crate::matchers::RegexMatcher;
+
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("storage.snap");
+
+        let source = create_encoded_storage(SampleEncoding::Xor);
+        let mut ts = TimeSeries::new(vec![Label::new("__name__", "up"), Label::new("job", "api")]);
+        ts.add_sample(Sample::new(1000, 1.0));
+        ts.add_sample(Sample::stale_marker(2000));
+        source.add_series(ts);
+
+        let meta = source.save_snapshot(&path).expect("save snapshot");
+        assert_eq!(meta.series, 21);
+        assert_eq!(meta.samples, 20 * 1000 + 2);
+
+        // Restore into a differently configured storage
+        let restored = MemoryStorage::new().with_shard_count(3);
+        assert_eq!(restored.restore_snapshot(&path).expect("restore snapshot"), meta);
+        assert_eq!(restored.series_count(), 21);
+        assert_eq!(restored.min_valid_time(), None);
+        assert_eq!(restored.label_names(), source.label_names());
+        assert_eq!(restored.label_values("cpu"), source.label_values("cpu"));
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> = vec![
+            Arc::new(RegexMatcher::new("cpu", Regex::new("1.*").expect("valid regex"))),
+            Arc::new(NotEqualMatcher::new("cpu", "10")),
+        ];
+        let collect = |storage: &MemoryStorage, matchers: &[Arc<dyn LabelMatcher>]| {
+            let mut series: Vec<(Vec<Label>, Vec<Sample>)> = storage
+                .query_series(matchers)
+                .into_iter()
+                .map(|ts| (ts.labels, ts.samples))
+                .collect();
+            series.sort_by(|a, b| a.0.cmp(&b.0));
+            series
+        };
+        assert_eq!(collect(&restored, &matchers).len(), 10);
+        assert_eq!(collect(&restored, &matchers), collect(&source, &matchers));
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> = vec![Arc::new(EqualMatcher::new("job", "api"))];
+        let series = restored.query_series(&matchers);
+        assert!(series[0].samples[1].is_stale());
+
+        // Restoring twice is rejected instead of duplicating the index
+        assert!(matches!(restored.restore_snapshot(&path), Err(SnapshotError::StorageNotEmpty)));
+    }
+
+    /// Test series with swapped label values survive a snapshot, and bad indexes are rejected.
+    #[test]
+    fn test_snapshot_swapped_label_values() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("storage.snap");
+
+        let source = MemoryStorage::new();
+        for (src, dst) in [("x", "y"), ("y", "x")] {
+            let mut ts = TimeSeries::new(vec![Label::new("src", src), Label::new("dst", dst)]);
+            ts.add_sample(Sample::new(1000, 1.0));
+            source.add_series(ts);
+        }
+        source.save_snapshot(&path).expect("save snapshot");
+        let restored = MemoryStorage::new();
+        assert_eq!(restored.restore_snapshot(&path).expect("restore snapshot").series, 2);
+        assert_eq!(restored.label_values("src"), vec!["x", "y"]);
+
+        let data = SnapshotData::read_from(&path).expect("read snapshot");
+        let mut bad_symbol = data.clone();
+        bad_symbol.series[0].labels[0].1 = u32::MAX;
+        let mut bad_position = data.clone();
+        bad_position.postings[0].series.push(u64::MAX);
+        for data in [bad_symbol, bad_position] {
+            let err = MemoryStorage::new().restore(&data).expect_err("out of range");
+            assert!(matches!(err, SnapshotError::Corrupt(_)), "{err}");
+        }
+    }
+
+    /// Test a corrupt snapshot file yields a clear error and leaves storage empty.
+    #[test]
+    fn test_snapshot_corrupt_file() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("storage.snap");
+        create_encoded_storage(SampleEncoding::Raw).save_snapshot(&path).expect("save snapshot");
+
+        let mut bytes = std::fs::read(&path).expect("read snapshot");
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0xff;
+        std::fs::write(&path, bytes).expect("write corrupt snapshot");
+
+        let storage = MemoryStorage::new();
+        let err = storage.restore_snapshot(&path).expect_err("corrupt snapshot");
+        assert!(matches!(err, SnapshotError::ChecksumMismatch { .. }), "{err}");
+        assert_eq!(storage.series_count(), 0);
+    }
+
+    /// Test a storage killed mid-write recovers every complete write from its WAL.
+    #[test]
+    fn test_wal_recovery() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let wal_dir = dir.path().join("wal");
+
+        let storage = MemoryStorage::new().with_encoding(SampleEncoding::Xor);
+        assert_eq!(storage.open_wal(&wal_dir).expect("open wal"), 0);
+        for i in 0..5 {
+            let mut ts = TimeSeries::new(vec![
+                Label::new("__name__", "up"),
+                Label::new("instance", format!("host-{}", i % 2)),
+            ]);
+            ts.add_sample(Sample::new(i * 1000, i as f64));
+            storage.add_series(ts);
+        }
+        // Killed without a snapshot, in the middle of appending the last write
+        drop(storage);
+        let segment = wal_dir.join("00000001");
+        let len = std::fs::metadata(&segment).expect("segment").len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&segment)
+            .expect("open segment")
+            .set_len(len - 3)
+            .expect("truncate segment");
+
+        let recovered = MemoryStorage::new();
+        assert_eq!(recovered.open_wal(&wal_dir).expect("replay wal"), 4);
+        assert_eq!(recovered.series_count(), 2);
+        let matchers: Vec<Arc<dyn LabelMatcher>> =
+            vec![Arc::new(EqualMatcher::new("instance", "host-0"))];
+        let series = recovered.query_series(&matchers);
+        let timestamps: Vec<i64> = series[0].samples.iter().map(|s| s.timestamp).collect();
+        assert_eq!(timestamps, vec![0, 2000]);
+
+        // Writes after recovery land in the log too and survive another restart
+        let mut ts = TimeSeries::new(vec![Label::new("__name__", "up")]);
+        ts.add_sample(Sample::new(9000, 1.0));
+        recovered.add_series(ts);
+        drop(recovered);
+
+        let restarted = MemoryStorage::new();
+        assert_eq!(restarted.open_wal(&wal_dir).expect("replay wal"), 5);
+        assert_eq!(restarted.series_count(), 3);
+    }
+
+    /// Test writes that cannot be logged fail and are not applied.
+    #[test]
+    fn test_wal_write_failure() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let wal_dir = dir.path().join("wal");
+        let storage = MemoryStorage::new();
+        // One-byte segments rotate on every append after the first
+        *storage.wal.lock().unwrap() = Some(Wal::open(&wal_dir, 1).expect("open wal"));
+        let up = |instance: &str| {
+            let mut ts = TimeSeries::new(vec![
+                Label::new("__name__", "up"),
+                Label::new("instance", instance),
+            ]);
+            ts.add_sample(Sample::new(1000, 1.0));
+            ts
+        };
+        assert!(storage.try_add_series(up("a")).is_ok());
+
+        std::fs::remove_dir_all(&wal_dir).expect("remove wal dir");
+        assert!(matches!(storage.try_add_series(up("b")), Err(StorageError::Wal(_))));
+        assert_eq!(storage.series_count(), 1);
+        assert_eq!(storage.label_values("instance"), vec!["a"]);
+        assert_eq!(storage.ingest_stats().samples_appended, 1);
+    }
+
+    /// Test snapshots truncate the WAL and restore plus replay loses nothing.
+    #[test]
+    fn test_wal_truncated_by_snapshot() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let wal_dir = dir.path().join("wal");
+        let snapshot = dir.path().join("storage.snap");
+        let add = |storage: &MemoryStorage, name: &str, timestamp: i64| {
+            let mut ts = TimeSeries::new(vec![Label::new("__name__", name)]);
+            ts.add_sample(Sample::new(timestamp, 1.0));
+            storage.add_series(ts);
+        };
+
+        let storage = MemoryStorage::new();
+        storage.open_wal(&wal_dir).expect("open wal");
+        add(&storage, "before", 1000);
+        storage.save_snapshot(&snapshot).expect("save snapshot");
+        let segments = std::fs::read_dir(&wal_dir).expect("wal dir").count();
+        assert_eq!(segments, 1);
+        add(&storage, "before", 2000);
+        add(&storage, "after", 3000);
+        drop(storage);
+
+        let recovered = MemoryStorage::new();
+        recovered.restore_snapshot(&snapshot).expect("restore snapshot");
+        assert_eq!(recovered.open_wal(&wal_dir).expect("replay wal"), 2);
+        assert_eq!(recovered.series_count(), 2);
+        let matchers: Vec<Arc<dyn LabelMatcher>> =
+            vec![Arc::new(EqualMatcher::new("__name__", "before"))];
+        assert_eq!(recovered.query_series(&matchers)[0].samples.len(), 2);
+    }
+
+    /// Test deletes remove samples in range and drop emptied series from the index.
+    #[test]
+    fn test_delete_series() {
+        for encoding in [SampleEncoding::Raw, SampleEncoding::Xor] {
+            let storage = create_encoded_storage(encoding);
+            let cpu = |value: &str| -> Vec<Arc<dyn LabelMatcher>> {
+                vec![Arc::new(EqualMatcher::new("cpu", value))]
+            };
+
+            // 1000 samples per series, 15s apart
+            let (mint, maxt) = (1_700_000_000_000 + 100 * 15_000, 1_700_000_000_000 + 199 * 15_000);
+            assert_eq!(storage.delete_series(mint, maxt, &cpu("3")), 100);
+            assert_eq!(storage.query_series(&cpu("3"))[0].samples.len(), 900);
+            assert_eq!(storage.series_count(), 20);
+
+            assert_eq!(storage.delete_series(i64::MIN, i64::MAX, &cpu("3")), 900);
+            assert!(storage.query_series(&cpu("3")).is_empty());
+            assert_eq!(storage.series_count(), 19);
+            assert!(!storage.label_values("cpu").contains(&"3".to_string()));
+            assert_eq!(storage.delete_series(i64::MIN, i64::MAX, &cpu("3")), 0);
+
+            // The index still selects the remaining series correctly
+            let matchers: Vec<Arc<dyn LabelMatcher>> =
+                vec![Arc::new(NotEqualMatcher::new("cpu", "4"))];
+            let mut indexed = storage.query_series(&matchers);
+            let mut scanned = storage.scan_series(&matchers);
+            indexed.sort_by(|a, b| a.labels.cmp(&b.labels));
+            scanned.sort_by(|a, b| a.labels.cmp(&b.labels));
+            assert_eq!(indexed.len(), 18);
+            assert!(indexed.iter().zip(&scanned).all(|(a, b)| a.labels == b.labels));
+        }
+    }
+
+    /// Test deletes are recorded in the WAL and not undone by replay.
+    #[test]
+    fn test_wal_replays_deletes() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let storage = MemoryStorage::new();
+        storage.open_wal(dir.path()).expect("open wal");
+        for name in ["kept", "deleted"] {
+            let mut ts = TimeSeries::new(vec![Label::new("__name__", name)]);
+            ts.add_sample(Sample::new(1000, 1.0));
+            ts.add_sample(Sample::new(2000, 1.0));
+            storage.add_series(ts);
+        }
+        let name = |value: &str| -> Vec<Arc<dyn LabelMatcher>> {
+            vec![Arc::new(EqualMatcher::new("__name__", value))]
+        };
+        storage.delete_series(i64::MIN, i64::MAX, &name("deleted"));
+        storage.delete_series(1500, 2500, &name("kept"));
+        drop(storage);
+
+        let recovered = MemoryStorage::new();
+        recovered.open_wal(dir.path()).expect("replay wal");
+        assert_eq!(recovered.label_values("__name__"), vec!["kept".to_string()]);
+        assert_eq!(recovered.query_series(&name("kept"))[0].samples.len(), 1);
+    }
+
+    /// Test the reject policy refuses duplicates and samples outside the out-of-order window.
+    #[test]
+    fn test_ingest_policy_reject() {
+        for encoding in [SampleEncoding::Raw, SampleEncoding::Xor] {
+            let policy = IngestPolicy::Reject { out_of_order_window: Duration::from_secs(10) };
+            let storage = MemoryStorage::new().with_encoding(encoding).with_ingest_policy(policy);
+            let add = |samples: &[(i64, f64)]| {
+                storage.try_add_series(TimeSeries {
+                    labels: vec![Label::new("__name__", "up")],
+                    samples: samples.iter().map(|(t, v)| Sample::new(*t, *v)).collect(),
+                })
+            };
+
+            assert_eq!(add(&[(20_000, 1.0), (30_000, 1.0)]), Ok(()));
+            // Exact repeats are accepted as no-ops, like Prometheus
+            assert_eq!(add(&[(30_000, 1.0)]), Ok(()));
+            assert_eq!(
+                add(&[(30_000, 2.0)]),
+                Err(StorageError::DuplicateSample { timestamp: 30_000 })
+            );
+            // Within the window out-of-order samples are inserted
+            assert_eq!(add(&[(25_000, 1.0)]), Ok(()));
+            assert_eq!(
+                add(&[(19_000, 1.0), (40_000, 1.0)]),
+                Err(StorageError::OutOfOrder { timestamp: 19_000, min_valid: 20_000 })
+            );
+            // Duplicates within one request are caught as well
+            assert_eq!(
+                add(&[(50_000, 1.0), (50_000, 3.0)]),
+                Err(StorageError::DuplicateSample { timestamp: 50_000 })
+            );
+
+            let series = storage.query_series(&[]);
+            let timestamps: Vec<i64> = series[0].samples.iter().map(|s| s.timestamp).collect();
+            assert_eq!(timestamps, vec![20_000, 25_000, 30_000, 40_000, 50_000]);
+            assert_eq!(series[0].samples[2].value, 1.0);
+
+            let stats = storage.ingest_stats();
+            assert_eq!(stats.samples_appended, 5);
+            assert_eq!(stats.duplicate_samples, 2);
+            assert_eq!(stats.out_of_order_samples, 1);
+        }
+    }
+
+    /// Test the default policy keeps overwriting and accepting old samples.
+    #[test]
+    fn test_ingest_policy_overwrite() {
+        let storage = MemoryStorage::new();
+        let add = |timestamp: i64, value: f64| {
+            storage.try_add_series(TimeSeries {
+                labels: vec![Label::new("__name__", "up")],
+                samples: vec![Sample::new(timestamp, value)],
+            })
+        };
+        assert_eq!(add(2000, 1.0), Ok(()));
+        assert_eq!(add(2000, 2.0), Ok(()));
+        assert_eq!(add(1000, 1.0), Ok(()));
+
+        let series = storage.query_series(&[]);
+        assert_eq!(series[0].samples, vec![Sample::new(1000, 1.0), Sample::new(2000, 2.0)]);
+        assert_eq!(
+            "reject".parse(),
+            Ok(IngestPolicy::Reject { out_of_order_window: Duration::ZERO })
+        );
+        assert!("drop".parse::<IngestPolicy>().is_err());
+    }
 }
@@ -4,13 +4,32 @@
 //! It includes traits for different storage capabilities and specific implementations
 //! like in-memory storage.
 
+pub mod chunk;
+pub mod index;
 pub mod memory;
+pub mod notify;
+pub mod snapshot;
+pub mod stats;
+pub mod symbols;
+pub mod wal;
 
 // Re-export main implementations
-pub use memory::MemoryStorage;
-
+pub use memory::{
+    CompactionStats, IngestPolicy, MemoryStorage, RetentionAnchor, SampleEncoding, StorageLimits,
+    DEFAULT_SHARD_COUNT,
+};
+pub use notify::{IngestEvent, WaitError};
+pub use snapshot::{SnapshotError, SnapshotMeta};
+pub use stats::{HeadStats, IngestStats, Stat, TsdbStats};
+pub use wal::WalError;
+
+use std::io;
+use std::path::Path;
 use std::sync::Arc;
 
+use thiserror::Error;
+use tokio::sync::broadcast;
+
 use crate::matchers::LabelMatcher;
 
 /// Storage abstraction for querying and storing time series data.
@@ -25,8 +44,53 @@ pub trait Storage: Send + Sync {
     /// - `ts` - Time series to store, samples will be merged if series already exists
     fn add_series(&self, ts: TimeSeries);
 
+    /// Add or update a time series, reporting data rejected by storage limits.
+    ///
+    /// Samples that pass the limits are stored even if others are rejected,
+    /// so an error means a partial write. The default implementation has no
+    /// limits and delegates to [`Storage::add_series`].
+    ///
+    /// # Parameters
+    ///
+    /// - `ts` - Time series to store, samples will be merged if series already exists
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageError` describing the first limit that was hit.
+    fn try_add_series(&self, ts: TimeSeries) -> Result<(), StorageError> {
+        self.add_series(ts);
+        Ok(())
+    }
+
+    /// Select series by label matchers, restricted to a time window.
+    ///
+    /// Implementations should avoid copying samples: the returned set lends
+    /// out views that borrow from storage (or from a buffer owned by the set).
+    /// The set may hold read locks while alive, so it should be dropped before
+    /// writing to the same storage.
+    ///
+    /// # Parameters
+    ///
+    /// - `mint` - Start of the window in milliseconds (inclusive)
+    /// - `maxt` - End of the window in milliseconds (inclusive)
+    /// - `matchers` - Array of label matchers to filter series
+    ///
+    /// # Returns
+    ///
+    /// Returns a `SeriesSet` over matching series. Series without samples in
+    /// the window are still yielded, with an empty sample slice.
+    fn select<'a>(
+        &'a self,
+        mint: i64,
+        maxt: i64,
+        matchers: &[Arc<dyn LabelMatcher>],
+    ) -> Box<dyn SeriesSet + 'a>;
+
     /// Query series by label matchers.
     ///
+    /// This clones the complete history of every matching series; prefer
+    /// [`Storage::select`] when only a time window is needed.
+    ///
     /// # Parameters
     ///
     /// - `matchers` - Array of label matchers to filter series
@@ -34,7 +98,137 @@ pub trait Storage: Send + Sync {
     /// # Returns
     ///
     /// Returns a vector of matching time series.
-    fn query_series(&self, matchers: &[Arc<dyn LabelMatcher>]) -> Vec<TimeSeries>;
+    fn query_series(&self, matchers: &[Arc<dyn LabelMatcher>]) -> Vec<TimeSeries> {
+        let mut set = self.select(i64::MIN, i64::MAX, matchers);
+        let mut result = Vec::new();
+        while let Some(view) = set.next() {
+            result.push(view.to_time_series());
+        }
+        result
+    }
+
+    /// Delete samples of matching series within a time window.
+    ///
+    /// Series left without samples are removed entirely, including their
+    /// label index entries, so they no longer show up in metadata queries.
+    ///
+    /// # Parameters
+    ///
+    /// - `mint` - Start of the window in milliseconds (inclusive)
+    /// - `maxt` - End of the window in milliseconds (inclusive)
+    /// - `matchers` - Array of label matchers to filter series
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of deleted samples.
+    fn delete_series(&self, mint: i64, maxt: i64, matchers: &[Arc<dyn LabelMatcher>]) -> usize;
+
+    /// Write a point-in-time copy of all stored data to a file.
+    ///
+    /// The default implementation does not support snapshots.
+    ///
+    /// # Parameters
+    ///
+    /// - `path` - Destination file, replaced if it exists
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the snapshot cannot be written or the
+    /// storage does not support snapshots.
+    fn snapshot_to(&self, path: &Path) -> io::Result<()> {
+        let _ = path;
+        Err(io::Error::new(io::ErrorKind::Unsupported, "storage does not support snapshots"))
+    }
+
+    /// Counters of ingested and rejected data, exposed as self-metrics.
+    ///
+    /// The default implementation reports no data.
+    ///
+    /// # Returns
+    ///
+    /// Returns the ingestion counters.
+    fn ingest_stats(&self) -> IngestStats {
+        IngestStats::default()
+    }
+
+    /// Subscribe to ingestion events.
+    ///
+    /// An event is published for every write that creates a series or
+    /// appends samples to it. See [`notify`] for helpers awaiting data.
+    ///
+    /// # Returns
+    ///
+    /// Returns a receiver of the events written after this call.
+    fn subscribe(&self) -> broadcast::Receiver<IngestEvent>;
+}
+
+/// Errors returned when data is rejected by storage limits or cannot be persisted.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum StorageError {
+    /// Creating the series would exceed the active series limit.
+    #[error("too many series: limit of {limit} active series reached")]
+    TooManySeries {
+        /// Configured maximum number of series
+        limit: usize,
+    },
+    /// Sample is older than the retention window.
+    #[error("out of bounds: sample timestamp {timestamp} is older than the minimum valid time {min_valid}")]
+    OutOfBounds {
+        /// Timestamp of the rejected sample
+        timestamp: i64,
+        /// Oldest timestamp currently accepted
+        min_valid: i64,
+    },
+    /// Sample is older than the series' newest sample by more than the out-of-order window.
+    #[error("out of order sample: timestamp {timestamp} is older than the minimum accepted time {min_valid} of the series")]
+    OutOfOrder {
+        /// Timestamp of the rejected sample
+        timestamp: i64,
+        /// Oldest timestamp currently accepted for the series
+        min_valid: i64,
+    },
+    /// Sample repeats an existing timestamp with a different value.
+    #[error("duplicate sample for timestamp {timestamp}")]
+    DuplicateSample {
+        /// Timestamp of the rejected sample
+        timestamp: i64,
+    },
+    /// The write could not be recorded in the write-ahead log and was not applied.
+    #[error("failed to write WAL: {0}")]
+    Wal(String),
+}
+
+/// A set of series produced by [`Storage::select`].
+///
+/// This is a lending iterator: each view borrows from the set and must be
+/// released before the next call.
+pub trait SeriesSet {
+    /// Advance to the next series.
+    ///
+    /// # Returns
+    ///
+    /// Returns a view of the next series, or `None` when the set is exhausted.
+    fn next(&mut self) -> Option<SeriesView<'_>>;
+}
+
+/// Borrowed view of a series restricted to the selected time window.
+#[derive(Debug, Clone, Copy)]
+pub struct SeriesView<'a> {
+    /// Labels of the series.
+    pub labels: &'a [Label],
+    /// Samples of the series within the window, sorted by timestamp.
+    pub samples: &'a [Sample],
+}
+
+impl SeriesView<'_> {
+    /// Copy this view into an owned time series.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `TimeSeries` holding the view's labels and windowed samples.
+    pub fn to_time_series(&self) -> TimeSeries {
+        TimeSeries { labels: self.labels.to_vec(), samples: self.samples.to_vec() }
+    }
 }
 
 /// Metadata operations for storage introspection.
@@ -59,11 +253,28 @@ pub trait MetadataStorage: Send + Sync {
     ///
     /// Returns a vector of all values for the label, or empty vector if label not found.
     fn label_values(&self, name: &str) -> Vec<String>;
+
+    /// Compute cardinality statistics of the stored series.
+    ///
+    /// # Parameters
+    ///
+    /// - `limit` - Maximum number of entries in each statistics list
+    ///
+    /// # Returns
+    ///
+    /// Returns the statistics served by `/api/v1/status/tsdb`.
+    fn tsdb_stats(&self, limit: usize) -> TsdbStats;
 }
 
 /// Combined storage trait providing both data and metadata operations.
 pub trait FullStorage: Storage + MetadataStorage {}
 
+/// Bit pattern Prometheus uses to mark a series as stale.
+///
+/// This is a specific NaN value, distinct from NaNs produced by arithmetic,
+/// so it must be compared by bits rather than by value.
+pub const STALE_NAN_BITS: u64 = 0x7ff0_0000_0000_0002;
+
 /// A metric label representing a name=value pair.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Label {
@@ -108,6 +319,28 @@ impl Sample {
     pub const fn new(timestamp: i64, value: f64) -> Self {
         Self { timestamp, value }
     }
+
+    /// Create a staleness marker sample at the given timestamp.
+    ///
+    /// # Parameters
+    ///
+    /// - `timestamp` - Timestamp in milliseconds since Unix epoch
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Sample` carrying the Prometheus stale NaN value.
+    pub fn stale_marker(timestamp: i64) -> Self {
+        Self { timestamp, value: f64::from_bits(STALE_NAN_BITS) }
+    }
+
+    /// Check whether this sample is a Prometheus staleness marker.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` only for the stale NaN bit pattern, not for ordinary NaNs.
+    pub fn is_stale(&self) -> bool {
+        self.value.to_bits() == STALE_NAN_BITS
+    }
 }
 
 /// A time series containing labels and samples for a metric.
@@ -137,17 +370,7 @@ impl TimeSeries {
     ///
     /// - `sample` - Sample to add, will replace existing sample at same timestamp
     pub fn add_sample(&mut self, sample: Sample) {
-        // Keep samples sorted by timestamp
-        match self.samples.binary_search_by_key(&sample.timestamp, |s| s.timestamp) {
-            Ok(pos) => {
-                // Replace existing sample at same timestamp
-                self.samples[pos] = sample;
-            }
-            Err(pos) => {
-                // Insert at correct position
-                self.samples.insert(pos, sample);
-            }
-        }
+        insert_sample(&mut self.samples, sample);
     }
 
     /// Get samples in time range [start, end] (inclusive)
@@ -161,7 +384,55 @@ impl TimeSeries {
     ///
     /// Returns a vector of samples in the specified time range.
     pub fn samples_in_range(&self, start: i64, end: i64) -> Vec<&Sample> {
-        self.samples.iter().filter(|s| s.timestamp >= start && s.timestamp <= end).collect()
+        self.window(start, end).iter().collect()
+    }
+
+    /// Borrow the samples in time range [start, end] (inclusive) without copying.
+    ///
+    /// # Parameters
+    ///
+    /// - `start` - Start timestamp (inclusive)
+    /// - `end` - End timestamp (inclusive)
+    ///
+    /// # Returns
+    ///
+    /// Returns a slice of the sorted samples in the specified time range.
+    pub fn window(&self, start: i64, end: i64) -> &[Sample] {
+        let from = self.samples.partition_point(|s| s.timestamp < start);
+        let to = self.samples.partition_point(|s| s.timestamp <= end);
+        &self.samples[from..to.max(from)]
+    }
+
+    /// Get the latest sample at or before `time` within the lookback window.
+    ///
+    /// # Parameters
+    ///
+    /// - `time` - Evaluation timestamp (inclusive)
+    /// - `lookback` - Maximum age of the sample in milliseconds (exclusive)
+    ///
+    /// # Returns
+    ///
+    /// Returns the latest sample in `(time - lookback, time]`, which may be a stale marker.
+    pub fn latest_at(&self, time: i64, lookback: i64) -> Option<&Sample> {
+        let end = self.samples.partition_point(|s| s.timestamp <= time);
+        self.samples[..end].last().filter(|s| s.timestamp > time - lookback)
+    }
+}
+
+/// Insert a sample into a timestamp-sorted vector, replacing any sample at
+/// the same timestamp.
+pub(crate) fn insert_sample(samples: &mut Vec<Sample>, sample: Sample) {
+    // Fast path: in-order appends are the common case
+    if samples.last().map_or(true, |last| last.timestamp < sample.timestamp) {
+        samples.push(sample);
+        return;
+    }
+
+    match samples.binary_search_by_key(&sample.timestamp, |s| s.timestamp) {
+        // Replace existing sample at same timestamp
+        Ok(pos) => samples[pos] = sample,
+        // Insert at correct position
+        Err(pos) => samples.insert(pos, sample),
     }
 }
 
@@ -206,6 +477,35 @@ mod tests {
         assert_eq!(sample3.value, 100.0);
     }
 
+    /// Test staleness marker detection by bit pattern.
+    #[test]
+    fn test_stale_marker() {
+        let marker = Sample::stale_marker(1000);
+        assert!(marker.is_stale());
+        assert!(marker.value.is_nan());
+        assert_eq!(marker.timestamp, 1000);
+
+        // Ordinary NaN values are not staleness markers
+        assert!(!Sample::new(1000, f64::NAN).is_stale());
+        assert!(!Sample::new(1000, 0.0).is_stale());
+    }
+
+    /// Test latest_at lookback selection.
+    #[test]
+    fn test_latest_at() {
+        let mut ts = TimeSeries::new(vec![Label::new("test", "latest")]);
+        ts.add_sample(Sample::new(1000, 10.0));
+        ts.add_sample(Sample::new(2000, 20.0));
+        ts.add_sample(Sample::stale_marker(3000));
+
+        assert_eq!(ts.latest_at(2500, 1000).map(|s| s.value), Some(20.0));
+        assert_eq!(ts.latest_at(2000, 1000).map(|s| s.value), Some(20.0));
+        assert!(ts.latest_at(3500, 1000).is_some_and(Sample::is_stale));
+        assert!(ts.latest_at(500, 1000).is_none());
+        // Lookback start is exclusive
+        assert!(ts.latest_at(4000, 1000).is_none());
+    }
+
     /// Test TimeSeries creation and sample management.
     #[test]
     fn test_time_series_operations() {
@@ -267,6 +567,36 @@ mod tests {
         assert_eq!(single_point[0].timestamp, 3000);
     }
 
+    /// Test zero-copy window selection.
+    #[test]
+    fn test_window() {
+        let mut ts = TimeSeries::new(vec![Label::new("test", "window")]);
+        for i in 1..=5 {
+            ts.add_sample(Sample::new(i * 1000, i as f64));
+        }
+
+        let window = ts.window(1500, 3500);
+        assert_eq!(window, &[Sample::new(2000, 2.0), Sample::new(3000, 3.0)]);
+        assert!(std::ptr::eq(&window[0], &ts.samples[1]));
+
+        assert_eq!(ts.window(i64::MIN, i64::MAX).len(), 5);
+        assert!(ts.window(6000, 7000).is_empty());
+        // Inverted range is empty rather than panicking
+        assert!(ts.window(4000, 2000).is_empty());
+    }
+
+    /// Test converting a view into an owned series.
+    #[test]
+    fn test_series_view_to_time_series() {
+        let labels = vec![Label::new("__name__", "up")];
+        let samples = vec![Sample::new(1000, 1.0)];
+        let view = SeriesView { labels: &labels, samples: &samples };
+
+        let ts = view.to_time_series();
+        assert_eq!(ts.labels, labels);
+        assert_eq!(ts.samples, samples);
+    }
+
     /// Test edge cases for TimeSeries operations.
     #[test]
     fn test_time_series_edge_cases() {
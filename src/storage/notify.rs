@@ -0,0 +1,209 @@
+//! Ingestion notifications for awaiting remote-written data in tests.
+//!
+//! Storage publishes an [`IngestEvent`] for every write through
+//! [`Storage::subscribe`]. The wait helpers build on it to let tests await
+//! data deterministically instead of polling in a sleep loop:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! use prom_mock_rs::storage::notify::wait_for_series;
+//! use prom_mock_rs::{EqualMatcher, LabelMatcher, MemoryStorage};
+//!
+//! # async fn example(storage: Arc<MemoryStorage>) {
+//! let matchers: Vec<Arc<dyn LabelMatcher>> = vec![Arc::new(EqualMatcher::new("job", "exporter"))];
+//! // ... trigger the exporter ...
+//! let series = wait_for_series(storage.as_ref(), &matchers, Duration::from_secs(5)).await.unwrap();
+//! # }
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+
+use crate::matchers::LabelMatcher;
+use crate::storage::{Label, Storage, TimeSeries};
+
+/// Capacity of the ingest event channel; slower subscribers skip events.
+pub const INGEST_EVENT_CAPACITY: usize = 1024;
+
+/// A write that added samples to (or created) a series.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IngestEvent {
+    /// Labels of the series, sorted by name
+    pub labels: Vec<Label>,
+    /// Number of samples written
+    pub samples: usize,
+    /// Whether the write created the series
+    pub created: bool,
+}
+
+/// Errors returned by the wait helpers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum WaitError {
+    /// The condition was not met in time.
+    #[error("timed out after {0:?}")]
+    Timeout(Duration),
+    /// The storage stopped publishing events.
+    #[error("storage closed the ingest event channel")]
+    Closed,
+}
+
+/// Wait until at least one series matches the given matchers.
+///
+/// # Parameters
+///
+/// - `storage` - Storage receiving the writes
+/// - `matchers` - Array of label matchers to filter series
+/// - `timeout` - Maximum time to wait
+///
+/// # Returns
+///
+/// Returns the matching series as soon as there are any.
+///
+/// # Errors
+///
+/// Returns `WaitError::Timeout` if no series matched in time.
+pub async fn wait_for_series(
+    storage: &dyn Storage,
+    matchers: &[Arc<dyn LabelMatcher>],
+    timeout: Duration,
+) -> Result<Vec<TimeSeries>, WaitError> {
+    wait_until(storage, timeout, || {
+        let series = storage.query_series(matchers);
+        (!series.is_empty()).then_some(series)
+    })
+    .await
+}
+
+/// Wait until storage has appended at least `count` samples since startup.
+///
+/// Counts samples as reported by [`Storage::ingest_stats`], so samples that
+/// were rejected by limits or ingest policies are not included.
+///
+/// # Parameters
+///
+/// - `storage` - Storage receiving the writes
+/// - `count` - Number of appended samples to wait for
+/// - `timeout` - Maximum time to wait
+///
+/// # Returns
+///
+/// Returns the number of appended samples, at least `count`.
+///
+/// # Errors
+///
+/// Returns `WaitError::Timeout` if fewer samples were appended in time.
+pub async fn wait_for_samples(
+    storage: &dyn Storage,
+    count: u64,
+    timeout: Duration,
+) -> Result<u64, WaitError> {
+    wait_until(storage, timeout, || {
+        let appended = storage.ingest_stats().samples_appended;
+        (appended >= count).then_some(appended)
+    })
+    .await
+}
+
+/// Re-check `condition` after every ingest event until it yields a value.
+async fn wait_until<T>(
+    storage: &dyn Storage,
+    timeout: Duration,
+    mut condition: impl FnMut() -> Option<T>,
+) -> Result<T, WaitError> {
+    // Subscribe before the first check so no write can slip in between
+    let mut events: Receiver<IngestEvent> = storage.subscribe();
+    let wait = async {
+        loop {
+            if let Some(value) = condition() {
+                return Ok(value);
+            }
+            match events.recv().await {
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Err(WaitError::Closed),
+            }
+        }
+    };
+    tokio::time::timeout(timeout, wait).await.unwrap_or(Err(WaitError::Timeout(timeout)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::matchers::EqualMatcher;
+    use crate::storage::{MemoryStorage, Sample};
+
+    use super::*;
+
+    fn add_sample(storage: &MemoryStorage, job: &str, timestamp: i64) {
+        let mut ts = TimeSeries::new(vec![Label::new("__name__", "up"), Label::new("job", job)]);
+        ts.add_sample(Sample::new(timestamp, 1.0));
+        storage.add_series(ts);
+    }
+
+    /// Test subscribers receive an event per write.
+    #[tokio::test]
+    async fn test_subscribe() {
+        let storage = MemoryStorage::new();
+        let mut events = storage.subscribe();
+        add_sample(&storage, "api", 1000);
+        add_sample(&storage, "api", 2000);
+
+        let event = events.recv().await.expect("first event");
+        assert_eq!(
+            event,
+            IngestEvent {
+                labels: vec![Label::new("__name__", "up"), Label::new("job", "api")],
+                samples: 1,
+                created: true,
+            }
+        );
+        assert!(!events.recv().await.expect("second event").created);
+    }
+
+    /// Test waiting for series written concurrently and for sample counts.
+    #[tokio::test]
+    async fn test_wait_for_series_and_samples() {
+        let storage = Arc::new(MemoryStorage::new());
+        let writer = Arc::clone(&storage);
+        tokio::spawn(async move {
+            for i in 0..3 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                add_sample(&writer, "exporter", i * 1000);
+            }
+        });
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> =
+            vec![Arc::new(EqualMatcher::new("job", "exporter"))];
+        let series = wait_for_series(storage.as_ref(), &matchers, Duration::from_secs(5))
+            .await
+            .expect("series appears");
+        assert_eq!(series.len(), 1);
+
+        let appended = wait_for_samples(storage.as_ref(), 3, Duration::from_secs(5))
+            .await
+            .expect("samples arrive");
+        assert_eq!(appended, 3);
+    }
+
+    /// Test waits give up after the timeout.
+    #[tokio::test]
+    async fn test_wait_timeout() {
+        let storage = MemoryStorage::new();
+        add_sample(&storage, "api", 1000);
+
+        let matchers: Vec<Arc<dyn LabelMatcher>> =
+            vec![Arc::new(EqualMatcher::new("job", "missing"))];
+        let timeout = Duration::from_millis(20);
+        assert_eq!(
+            wait_for_series(&storage, &matchers, timeout).await.map(|s| s.len()),
+            Err(WaitError::Timeout(timeout))
+        );
+        assert_eq!(wait_for_samples(&storage, 1, timeout).await, Ok(1));
+        assert_eq!(wait_for_samples(&storage, 2, timeout).await, Err(WaitError::Timeout(timeout)));
+    }
+}
@@ -0,0 +1,449 @@
+//! Versioned binary snapshot format for stored series.
+//!
+//! A snapshot file is laid out as (all integers little-endian):
+//!
+//! ```text
+//! magic    8 bytes  "PROMSNAP"
+//! version  u32
+//! length   u64      length of the body in bytes
+//! body     ...      metadata, symbols, series and postings
+//! crc32    u32      CRC-32 (IEEE) of the body
+//! ```
+//!
+//! The body holds a symbol table of all label strings, every series with
+//! its labels (as symbol ids) and samples, and the label index postings
+//! referring to series by their position in the file. Files are written to
+//! a temporary sibling and renamed into place, so readers never observe a
+//! partially written snapshot.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::storage::wal::WalError;
+use crate::storage::Sample;
+
+/// Magic bytes at the start of every snapshot file.
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"PROMSNAP";
+
+/// Current snapshot format version.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Errors that can occur when writing or reading snapshots.
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    /// I/O error while reading or writing the snapshot file.
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+    /// The file does not start with the snapshot magic bytes.
+    #[error("not a snapshot file (bad magic bytes)")]
+    BadMagic,
+    /// The snapshot was written by an unsupported format version.
+    #[error("unsupported snapshot version {0} (supported: {SNAPSHOT_VERSION})")]
+    UnsupportedVersion(u32),
+    /// The body checksum does not match, the file is corrupt.
+    #[error("snapshot checksum mismatch: expected {expected:#010x}, got {actual:#010x}")]
+    ChecksumMismatch {
+        /// Checksum stored in the file
+        expected: u32,
+        /// Checksum computed over the body
+        actual: u32,
+    },
+    /// The snapshot is structurally invalid (truncated or inconsistent).
+    #[error("corrupt snapshot: {0}")]
+    Corrupt(String),
+    /// Snapshots can only be restored into empty storage.
+    #[error("storage is not empty")]
+    StorageNotEmpty,
+    /// The write-ahead log could not be moved past the snapshot.
+    #[error("wal: {0}")]
+    Wal(#[from] WalError),
+}
+
+/// Snapshot metadata stored alongside the data.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotMeta {
+    /// Wall clock time the snapshot was taken, in milliseconds
+    pub created_at: i64,
+    /// Newest sample timestamp in the storage, `i64::MIN` if none
+    pub max_time: i64,
+    /// Number of series in the snapshot
+    pub series: usize,
+    /// Total number of samples in the snapshot
+    pub samples: usize,
+}
+
+/// A series in snapshot form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotSeries {
+    /// Labels as (name, value) indexes into [`SnapshotData::symbols`]
+    pub labels: Vec<(u32, u32)>,
+    /// Samples sorted by timestamp
+    pub samples: Vec<Sample>,
+}
+
+/// A postings list in snapshot form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotPostings {
+    /// Label name symbol
+    pub name: u32,
+    /// Label value symbol
+    pub value: u32,
+    /// Sorted positions of the series in [`SnapshotData::series`]
+    pub series: Vec<u64>,
+}
+
+/// Complete in-memory representation of a snapshot file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SnapshotData {
+    /// Wall clock time the snapshot was taken, in milliseconds
+    pub created_at: i64,
+    /// Newest sample timestamp in the storage, `i64::MIN` if none
+    pub max_time: i64,
+    /// Label strings referenced by series and postings
+    pub symbols: Vec<String>,
+    /// Stored series
+    pub series: Vec<SnapshotSeries>,
+    /// Label index postings
+    pub postings: Vec<SnapshotPostings>,
+}
+
+impl SnapshotData {
+    /// Summarize the snapshot.
+    ///
+    /// # Returns
+    ///
+    /// Returns the snapshot metadata.
+    pub fn meta(&self) -> SnapshotMeta {
+        SnapshotMeta {
+            created_at: self.created_at,
+            max_time: self.max_time,
+            series: self.series.len(),
+            samples: self.series.iter().map(|s| s.samples.len()).sum(),
+        }
+    }
+
+    /// Encode the snapshot into its file representation.
+    ///
+    /// # Returns
+    ///
+    /// Returns the complete file contents, including header and checksum.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&self.created_at.to_le_bytes());
+        body.extend_from_slice(&self.max_time.to_le_bytes());
+
+        put_len(&mut body, self.symbols.len());
+        for symbol in &self.symbols {
+            put_len(&mut body, symbol.len());
+            body.extend_from_slice(symbol.as_bytes());
+        }
+
+        put_len(&mut body, self.series.len());
+        for series in &self.series {
+            put_len(&mut body, series.labels.len());
+            for (name, value) in &series.labels {
+                body.extend_from_slice(&name.to_le_bytes());
+                body.extend_from_slice(&value.to_le_bytes());
+            }
+            put_len(&mut body, series.samples.len());
+            for sample in &series.samples {
+                body.extend_from_slice(&sample.timestamp.to_le_bytes());
+                body.extend_from_slice(&sample.value.to_bits().to_le_bytes());
+            }
+        }
+
+        put_len(&mut body, self.postings.len());
+        for postings in &self.postings {
+            body.extend_from_slice(&postings.name.to_le_bytes());
+            body.extend_from_slice(&postings.value.to_le_bytes());
+            put_len(&mut body, postings.series.len());
+            for position in &postings.series {
+                body.extend_from_slice(&position.to_le_bytes());
+            }
+        }
+
+        let mut out = Vec::with_capacity(body.len() + 24);
+        out.extend_from_slice(&SNAPSHOT_MAGIC);
+        out.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        out.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        out.extend_from_slice(&body);
+        out.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+        out
+    }
+
+    /// Decode and validate a snapshot file.
+    ///
+    /// # Parameters
+    ///
+    /// - `bytes` - Complete file contents
+    ///
+    /// # Returns
+    ///
+    /// Returns the decoded snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SnapshotError` if the header, checksum or structure is invalid.
+    pub fn decode(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut header = Reader { bytes, pos: 0 };
+        if header.take(SNAPSHOT_MAGIC.len()).ok() != Some(&SNAPSHOT_MAGIC[..]) {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = header.u32()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let body_len = header.len()?;
+        let body = header.take(body_len)?;
+        let expected = header.u32()?;
+        if header.pos != bytes.len() {
+            return Err(SnapshotError::Corrupt("trailing data after checksum".to_string()));
+        }
+        let actual = crc32fast::hash(body);
+        if expected != actual {
+            return Err(SnapshotError::ChecksumMismatch { expected, actual });
+        }
+
+        let mut r = Reader { bytes: body, pos: 0 };
+        let created_at = r.i64()?;
+        let max_time = r.i64()?;
+
+        let symbol_count = r.len()?;
+        let mut symbols = Vec::with_capacity(symbol_count.min(body.len()));
+        for _ in 0..symbol_count {
+            let len = r.len()?;
+            let raw = r.take(len)?;
+            let symbol = std::str::from_utf8(raw).map_err(|_| {
+                SnapshotError::Corrupt("label string is not valid UTF-8".to_string())
+            })?;
+            symbols.push(symbol.to_string());
+        }
+        let check_symbol = |symbol: u32| {
+            if (symbol as usize) < symbols.len() {
+                Ok(symbol)
+            } else {
+                Err(SnapshotError::Corrupt(format!("unknown symbol {symbol}")))
+            }
+        };
+
+        let series_count = r.len()?;
+        let mut series = Vec::with_capacity(series_count.min(body.len()));
+        for _ in 0..series_count {
+            let label_count = r.len()?;
+            let mut labels = Vec::with_capacity(label_count.min(body.len()));
+            for _ in 0..label_count {
+                labels.push((check_symbol(r.u32()?)?, check_symbol(r.u32()?)?));
+            }
+            let sample_count = r.len()?;
+            let mut samples = Vec::with_capacity(sample_count.min(body.len()));
+            for _ in 0..sample_count {
+                let timestamp = r.i64()?;
+                samples.push(Sample::new(timestamp, f64::from_bits(r.u64()?)));
+            }
+            series.push(SnapshotSeries { labels, samples });
+        }
+
+        let postings_count = r.len()?;
+        let mut postings = Vec::with_capacity(postings_count.min(body.len()));
+        for _ in 0..postings_count {
+            let name = check_symbol(r.u32()?)?;
+            let value = check_symbol(r.u32()?)?;
+            let len = r.len()?;
+            let mut list = Vec::with_capacity(len.min(body.len()));
+            for _ in 0..len {
+                let position = r.u64()?;
+                if position >= series.len() as u64 {
+                    return Err(SnapshotError::Corrupt(format!("unknown series {position}")));
+                }
+                list.push(position);
+            }
+            postings.push(SnapshotPostings { name, value, series: list });
+        }
+
+        if r.pos != body.len() {
+            return Err(SnapshotError::Corrupt("unexpected data after postings".to_string()));
+        }
+        Ok(Self { created_at, max_time, symbols, series, postings })
+    }
+
+    /// Atomically write the snapshot to a file.
+    ///
+    /// The data is written to `<path>.tmp`, synced, and renamed over `path`.
+    ///
+    /// # Parameters
+    ///
+    /// - `path` - Destination file
+    ///
+    /// # Errors
+    ///
+    /// Returns `SnapshotError::Io` if writing or renaming fails.
+    pub fn write_to(&self, path: &Path) -> Result<(), SnapshotError> {
+        let tmp = temp_path(path);
+        let result = (|| {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(&self.encode())?;
+            file.sync_all()?;
+            fs::rename(&tmp, path)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        Ok(result?)
+    }
+
+    /// Read and validate a snapshot file.
+    ///
+    /// # Parameters
+    ///
+    /// - `path` - Snapshot file
+    ///
+    /// # Returns
+    ///
+    /// Returns the decoded snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SnapshotError` if the file cannot be read or is invalid.
+    pub fn read_from(path: &Path) -> Result<Self, SnapshotError> {
+        Self::decode(&fs::read(path)?)
+    }
+}
+
+/// Temporary file used while writing `path`.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Append a length or count as u64.
+fn put_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u64).to_le_bytes());
+}
+
+/// Bounds-checked little-endian reader.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.bytes.len());
+        let end =
+            end.ok_or_else(|| SnapshotError::Corrupt("unexpected end of file".to_string()))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        Ok(self.take(N)?.try_into().expect("slice of requested length"))
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, SnapshotError> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    fn len(&mut self) -> Result<usize, SnapshotError> {
+        usize::try_from(self.u64()?)
+            .map_err(|_| SnapshotError::Corrupt("length out of range".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_snapshot() -> SnapshotData {
+        SnapshotData {
+            created_at: 1_700_000_000_000,
+            max_time: 2000,
+            symbols: vec!["__name__".to_string(), "up".to_string()],
+            series: vec![SnapshotSeries {
+                labels: vec![(0, 1)],
+                samples: vec![Sample::new(1000, 1.0), Sample::stale_marker(2000)],
+            }],
+            postings: vec![SnapshotPostings { name: 0, value: 1, series: vec![0] }],
+        }
+    }
+
+    /// Test encoding and decoding round trips, preserving NaN bit patterns.
+    #[test]
+    fn test_roundtrip() {
+        let snapshot = create_test_snapshot();
+        let decoded = SnapshotData::decode(&snapshot.encode()).expect("valid snapshot");
+        assert_eq!(decoded.symbols, snapshot.symbols);
+        assert_eq!(decoded.postings, snapshot.postings);
+        assert!(decoded.series[0].samples[1].is_stale());
+        assert_eq!(
+            decoded.meta(),
+            SnapshotMeta { created_at: 1_700_000_000_000, max_time: 2000, series: 1, samples: 2 }
+        );
+    }
+
+    /// Test header validation.
+    #[test]
+    fn test_invalid_header() {
+        let mut bytes = create_test_snapshot().encode();
+        assert!(matches!(SnapshotData::decode(b"hello"), Err(SnapshotError::BadMagic)));
+
+        bytes[8] = 99;
+        assert!(matches!(SnapshotData::decode(&bytes), Err(SnapshotError::UnsupportedVersion(99))));
+    }
+
+    /// Test corrupt and truncated files are rejected.
+    #[test]
+    fn test_corruption_detected() {
+        let bytes = create_test_snapshot().encode();
+
+        let mut flipped = bytes.clone();
+        flipped[30] ^= 0x01;
+        assert!(matches!(
+            SnapshotData::decode(&flipped),
+            Err(SnapshotError::ChecksumMismatch { .. })
+        ));
+
+        let truncated = &bytes[..bytes.len() - 10];
+        assert!(matches!(SnapshotData::decode(truncated), Err(SnapshotError::Corrupt(_))));
+    }
+
+    /// Test structurally invalid bodies with a valid checksum are rejected.
+    #[test]
+    fn test_inconsistent_body() {
+        let mut snapshot = create_test_snapshot();
+        snapshot.postings[0].series = vec![5];
+        assert!(matches!(
+            SnapshotData::decode(&snapshot.encode()),
+            Err(SnapshotError::Corrupt(msg)) if msg == "unknown series 5"
+        ));
+    }
+
+    /// Test atomic writes leave no temporary file behind.
+    #[test]
+    fn test_write_and_read_file() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("storage.snap");
+
+        let snapshot = create_test_snapshot();
+        snapshot.write_to(&path).expect("write snapshot");
+        assert!(!temp_path(&path).exists());
+
+        let read = SnapshotData::read_from(&path).expect("read snapshot");
+        assert_eq!(read.meta(), snapshot.meta());
+
+        let missing = SnapshotData::read_from(&dir.path().join("missing.snap"));
+        assert!(matches!(missing, Err(SnapshotError::Io(_))));
+    }
+}
@@ -0,0 +1,178 @@
+//! Storage statistics: cardinality as reported by Prometheus'
+//! `/api/v1/status/tsdb`, and ingestion counters for self-metrics.
+
+use fnv::FnvHashMap;
+
+/// Counters of ingested and rejected data since startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestStats {
+    /// Number of currently stored series
+    pub series: u64,
+    /// Samples written to storage, including overwrites
+    pub samples_appended: u64,
+    /// Samples rejected for being older than the out-of-order window
+    pub out_of_order_samples: u64,
+    /// Samples rejected for repeating a timestamp with a different value
+    pub duplicate_samples: u64,
+    /// Samples rejected for being older than the retention window
+    pub out_of_bounds_samples: u64,
+    /// New series rejected by the series limit
+    pub rejected_series: u64,
+}
+
+/// A named count in a cardinality statistics list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stat {
+    /// Metric name, label name or `name=value` pair
+    pub name: String,
+    /// Counted value
+    pub value: u64,
+}
+
+/// Summary of the stored (head) data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadStats {
+    /// Number of stored series
+    pub num_series: u64,
+    /// Number of sample chunks
+    pub chunk_count: u64,
+    /// Oldest sample timestamp in milliseconds, `i64::MAX` if there are none
+    pub min_time: i64,
+    /// Newest sample timestamp in milliseconds, `i64::MIN` if there are none
+    pub max_time: i64,
+}
+
+impl Default for HeadStats {
+    fn default() -> Self {
+        Self { num_series: 0, chunk_count: 0, min_time: i64::MAX, max_time: i64::MIN }
+    }
+}
+
+/// Cardinality statistics, each list sorted by descending value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TsdbStats {
+    /// Summary of the stored data
+    pub head: HeadStats,
+    /// Series per metric name
+    pub series_count_by_metric_name: Vec<Stat>,
+    /// Distinct values per label name
+    pub label_value_count_by_label_name: Vec<Stat>,
+    /// Total length of the distinct values per label name
+    pub memory_in_bytes_by_label_name: Vec<Stat>,
+    /// Series per label pair
+    pub series_count_by_label_value_pair: Vec<Stat>,
+}
+
+/// Accumulates postings list sizes into [`TsdbStats`].
+///
+/// The same label pair may be added several times (e.g. once per storage
+/// shard); its series counts are summed.
+#[derive(Debug, Default)]
+pub struct TsdbStatsBuilder {
+    /// Series count per label name and value
+    postings: FnvHashMap<String, FnvHashMap<String, u64>>,
+}
+
+impl TsdbStatsBuilder {
+    /// Create an empty builder.
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `TsdbStatsBuilder` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the series having a label pair.
+    ///
+    /// # Parameters
+    ///
+    /// - `name` - Label name
+    /// - `value` - Label value
+    /// - `series` - Number of series with this label pair
+    pub fn add_postings(&mut self, name: &str, value: &str, series: u64) {
+        let values = self.postings.entry(name.to_string()).or_default();
+        *values.entry(value.to_string()).or_default() += series;
+    }
+
+    /// Build the statistics, keeping the top `limit` entries of every list.
+    ///
+    /// # Parameters
+    ///
+    /// - `head` - Summary of the stored data
+    /// - `limit` - Maximum number of entries per list
+    ///
+    /// # Returns
+    ///
+    /// Returns the cardinality statistics.
+    pub fn build(self, head: HeadStats, limit: usize) -> TsdbStats {
+        let mut stats = TsdbStats { head, ..TsdbStats::default() };
+        for (name, values) in self.postings {
+            if name == "__name__" {
+                stats.series_count_by_metric_name.extend(
+                    values
+                        .iter()
+                        .map(|(value, series)| Stat { name: value.clone(), value: *series }),
+                );
+            }
+            let bytes: usize = values.keys().map(String::len).sum();
+            stats
+                .memory_in_bytes_by_label_name
+                .push(Stat { name: name.clone(), value: bytes as u64 });
+            stats
+                .label_value_count_by_label_name
+                .push(Stat { name: name.clone(), value: values.len() as u64 });
+            stats.series_count_by_label_value_pair.extend(
+                values
+                    .into_iter()
+                    .map(|(value, series)| Stat { name: format!("{name}={value}"), value: series }),
+            );
+        }
+
+        for list in [
+            &mut stats.series_count_by_metric_name,
+            &mut stats.label_value_count_by_label_name,
+            &mut stats.memory_in_bytes_by_label_name,
+            &mut stats.series_count_by_label_value_pair,
+        ] {
+            list.sort_by(|a, b| b.value.cmp(&a.value).then_with(|| a.name.cmp(&b.name)));
+            list.truncate(limit);
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(name: &str, value: u64) -> Stat {
+        Stat { name: name.to_string(), value }
+    }
+
+    /// Test postings are summed across additions and lists are ranked and limited.
+    #[test]
+    fn test_build_stats() {
+        let mut builder = TsdbStatsBuilder::new();
+        builder.add_postings("__name__", "up", 2);
+        builder.add_postings("__name__", "up", 1);
+        builder.add_postings("__name__", "http_requests_total", 4);
+        builder.add_postings("job", "api", 5);
+        builder.add_postings("job", "node", 2);
+
+        let stats = builder.build(HeadStats::default(), 2);
+        assert_eq!(
+            stats.series_count_by_metric_name,
+            vec![stat("http_requests_total", 4), stat("up", 3)]
+        );
+        assert_eq!(
+            stats.label_value_count_by_label_name,
+            vec![stat("__name__", 2), stat("job", 2)]
+        );
+        assert_eq!(stats.memory_in_bytes_by_label_name, vec![stat("__name__", 21), stat("job", 7)]);
+        assert_eq!(
+            stats.series_count_by_label_value_pair,
+            vec![stat("job=api", 5), stat("__name__=http_requests_total", 4)]
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! String interning for label names and values.
+//!
+//! Series and the label index refer to label strings through small
+//! [`Symbol`] ids, so repetitive labels such as `job` or `instance` are
+//! stored once per symbol table instead of once per series and posting.
+//! Symbols are reference counted per series, so strings of removed series
+//! are dropped and churning label values do not grow the table forever.
+
+use std::sync::Arc;
+
+use fnv::FnvHashMap;
+
+use crate::storage::Label;
+
+/// Interned string id, only meaningful for the table that created it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+/// A label pair in interned form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolLabel {
+    /// Interned label name
+    pub name: Symbol,
+    /// Interned label value
+    pub value: Symbol,
+}
+
+/// Reference-counted table mapping strings to symbols and back.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    /// Lookup from string to symbol
+    ids: FnvHashMap<Arc<str>, Symbol>,
+    /// Strings indexed by symbol id, sharing allocations with `ids`
+    strings: Vec<Arc<str>>,
+    /// Number of references held on each symbol
+    refs: Vec<u32>,
+    /// Released symbol ids, reused before the table grows
+    free: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    /// Create a new empty symbol table.
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `SymbolTable` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of interned strings.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Whether no strings are interned.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Intern a string, reusing the existing symbol if it is already known.
+    ///
+    /// Every call takes a reference on the symbol, to be given back with
+    /// [`SymbolTable::release`].
+    ///
+    /// # Parameters
+    ///
+    /// - `s` - String to intern
+    ///
+    /// # Returns
+    ///
+    /// Returns the symbol for the string.
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        if let Some(symbol) = self.ids.get(s) {
+            self.refs[symbol.0 as usize] += 1;
+            return *symbol;
+        }
+        let string: Arc<str> = Arc::from(s);
+        let symbol = if let Some(symbol) = self.free.pop() {
+            self.strings[symbol.0 as usize] = Arc::clone(&string);
+            self.refs[symbol.0 as usize] = 1;
+            symbol
+        } else {
+            let symbol = Symbol(u32::try_from(self.strings.len()).expect("symbol table overflow"));
+            self.strings.push(Arc::clone(&string));
+            self.refs.push(1);
+            symbol
+        };
+        self.ids.insert(string, symbol);
+        symbol
+    }
+
+    /// Give back a reference taken by [`SymbolTable::intern`].
+    ///
+    /// The string is dropped with its last reference, and its symbol may
+    /// then be reused for another string.
+    ///
+    /// # Parameters
+    ///
+    /// - `symbol` - Symbol created by this table
+    pub fn release(&mut self, symbol: Symbol) {
+        let refs = &mut self.refs[symbol.0 as usize];
+        *refs -= 1;
+        if *refs == 0 {
+            let string = std::mem::replace(&mut self.strings[symbol.0 as usize], Arc::from(""));
+            self.ids.remove(&string);
+            self.free.push(symbol);
+        }
+    }
+
+    /// Look up the symbol of a string without interning it.
+    ///
+    /// # Parameters
+    ///
+    /// - `s` - String to look up
+    ///
+    /// # Returns
+    ///
+    /// Returns the symbol, or `None` if the string was never interned.
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        self.ids.get(s).copied()
+    }
+
+    /// Resolve a symbol to its string.
+    ///
+    /// # Parameters
+    ///
+    /// - `symbol` - Symbol created by this table
+    ///
+    /// # Returns
+    ///
+    /// Returns the interned string.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+
+    /// Intern every name and value of a label set.
+    ///
+    /// The label set holds one reference per string until it is passed to
+    /// [`SymbolTable::release_labels`].
+    ///
+    /// # Parameters
+    ///
+    /// - `labels` - Labels to intern, order is preserved
+    ///
+    /// # Returns
+    ///
+    /// Returns the interned label set.
+    pub fn intern_labels(&mut self, labels: &[Label]) -> Box<[SymbolLabel]> {
+        labels
+            .iter()
+            .map(|l| SymbolLabel { name: self.intern(&l.name), value: self.intern(&l.value) })
+            .collect()
+    }
+
+    /// Release the references held by an interned label set.
+    ///
+    /// # Parameters
+    ///
+    /// - `labels` - Labels returned by [`SymbolTable::intern_labels`]
+    pub fn release_labels(&mut self, labels: &[SymbolLabel]) {
+        for label in labels {
+            self.release(label.name);
+            self.release(label.value);
+        }
+    }
+
+    /// Convert a label set to interned form without interning new strings.
+    ///
+    /// # Parameters
+    ///
+    /// - `labels` - Labels to look up
+    ///
+    /// # Returns
+    ///
+    /// Returns the interned label set, or `None` if any string is unknown
+    /// (in which case no series with these labels can exist).
+    pub fn lookup_labels(&self, labels: &[Label]) -> Option<Vec<SymbolLabel>> {
+        labels
+            .iter()
+            .map(|l| Some(SymbolLabel { name: self.get(&l.name)?, value: self.get(&l.value)? }))
+            .collect()
+    }
+
+    /// Materialize an interned label set into owned labels.
+    ///
+    /// # Parameters
+    ///
+    /// - `labels` - Interned labels created by this table
+    ///
+    /// # Returns
+    ///
+    /// Returns the labels with resolved strings.
+    pub fn materialize(&self, labels: &[SymbolLabel]) -> Vec<Label> {
+        labels.iter().map(|l| Label::new(self.resolve(l.name), self.resolve(l.value))).collect()
+    }
+
+    /// Approximate heap bytes used by the interned strings and lookup table.
+    pub fn heap_bytes(&self) -> usize {
+        let strings: usize = self.ids.keys().map(|s| s.len()).sum();
+        let entry = std::mem::size_of::<Arc<str>>() * 2
+            + std::mem::size_of::<Symbol>()
+            + std::mem::size_of::<u32>();
+        strings + self.strings.len() * entry + self.free.len() * std::mem::size_of::<Symbol>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test interning returns stable symbols that resolve back to the string.
+    #[test]
+    fn test_intern_and_resolve() {
+        let mut table = SymbolTable::new();
+        let job = table.intern("job");
+        let api = table.intern("api");
+        assert_ne!(job, api);
+        assert_eq!(table.intern("job"), job);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.resolve(api), "api");
+        assert_eq!(table.get("api"), Some(api));
+        assert_eq!(table.get("web"), None);
+    }
+
+    /// Test interning, looking up and materializing label sets.
+    #[test]
+    fn test_label_sets() {
+        let mut table = SymbolTable::new();
+        let labels = vec![Label::new("job", "api"), Label::new("instance", "api")];
+
+        assert!(table.lookup_labels(&labels).is_none());
+        let interned = table.intern_labels(&labels);
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.lookup_labels(&labels).as_deref(), Some(&interned[..]));
+        assert_eq!(table.materialize(&interned), labels);
+    }
+
+    /// Test released strings are dropped with their last reference and their ids reused.
+    #[test]
+    fn test_release() {
+        let mut table = SymbolTable::new();
+        let first = table.intern_labels(&[Label::new("pod", "a")]);
+        let second = table.intern_labels(&[Label::new("pod", "b")]);
+        assert_eq!(table.len(), 3);
+
+        table.release_labels(&first);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get("a"), None);
+        assert_eq!(table.materialize(&second), vec![Label::new("pod", "b")]);
+
+        let bytes = table.heap_bytes();
+        let third = table.intern_labels(&[Label::new("pod", "c")]);
+        assert_eq!(third[0].value, first[0].value);
+        assert_eq!(table.resolve(third[0].value), "c");
+        assert_eq!(table.heap_bytes(), bytes + 1 - std::mem::size_of::<Symbol>());
+
+        table.release_labels(&second);
+        table.release_labels(&third);
+        assert!(table.is_empty());
+    }
+}
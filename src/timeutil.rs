@@ -3,6 +3,8 @@
 //! This module provides utilities for parsing and resolving relative time
 //! expressions like "now-15m" into absolute timestamps.
 
+use std::str::FromStr;
+
 use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
 
 /// Result of resolving a time/interval parameter.
@@ -15,6 +17,51 @@ pub enum ResolvedParam {
     Relative(String),
 }
 
+/// Reference time "now" is taken from when answering requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockAnchor {
+    /// The live clock
+    Now,
+    /// The clock frozen when the server starts
+    Startup,
+    /// A fixed point in time
+    At(OffsetDateTime),
+}
+
+impl ClockAnchor {
+    /// Fixed "now" this anchor stands for.
+    ///
+    /// # Parameters
+    ///
+    /// - `startup` - Time the server started
+    ///
+    /// # Returns
+    ///
+    /// Returns the fixed time, or `None` for the live clock.
+    pub fn fixed_now(self, startup: OffsetDateTime) -> Option<OffsetDateTime> {
+        match self {
+            Self::Now => None,
+            Self::Startup => Some(startup),
+            Self::At(time) => Some(time),
+        }
+    }
+}
+
+impl FromStr for ClockAnchor {
+    type Err = String;
+
+    /// Parse `now`, `startup` or an RFC3339 time.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "now" => Ok(Self::Now),
+            "startup" => Ok(Self::Startup),
+            other => OffsetDateTime::parse(other, &Rfc3339).map(Self::At).map_err(|_| {
+                format!("unknown clock anchor: {other} (expected now, startup or an RFC3339 time)")
+            }),
+        }
+    }
+}
+
 /// Resolve relative time expressions to absolute timestamps.
 ///
 /// Supports:
@@ -68,6 +115,50 @@ pub fn resolve_relative(input: &str, now: Option<OffsetDateTime>) -> ResolvedPar
     ResolvedParam::Raw(s.to_string())
 }
 
+/// Convert a time parameter to UNIX seconds.
+///
+/// Accepts everything [`resolve_relative`] resolves, plus fractional UNIX
+/// seconds as sent by Prometheus clients.
+///
+/// # Parameters
+///
+/// - `input` - Time expression string to convert
+/// - `now` - Time that relative expressions are resolved against
+///
+/// # Returns
+///
+/// Returns the time in UNIX seconds, or `None` if the format is not recognized.
+pub fn unix_seconds(input: &str, now: OffsetDateTime) -> Option<f64> {
+    let s = input.trim();
+    if let Ok(seconds) = s.parse::<f64>() {
+        return seconds.is_finite().then_some(seconds);
+    }
+    if let Ok(t) = OffsetDateTime::parse(s, &Rfc3339) {
+        return Some(t.unix_timestamp_nanos() as f64 / 1e9);
+    }
+    match resolve_relative(s, Some(now)) {
+        ResolvedParam::Relative(ts) => ts.parse().ok(),
+        ResolvedParam::Absolute(_) | ResolvedParam::Raw(_) => None,
+    }
+}
+
+/// Convert a duration parameter like `30s`, `1m30s` or `15` to seconds.
+///
+/// # Parameters
+///
+/// - `input` - Duration string, either float seconds or with units
+///
+/// # Returns
+///
+/// Returns the duration in seconds, or `None` if the format is not recognized.
+pub fn duration_seconds(input: &str) -> Option<f64> {
+    let s = input.trim();
+    if let Ok(seconds) = s.parse::<f64>() {
+        return seconds.is_finite().then_some(seconds);
+    }
+    humantime::parse_duration(s).ok().map(|d| d.as_secs_f64())
+}
+
 fn split_num_unit(s: &str) -> Option<(&str, &str)> {
     let i = s.find(|c: char| !c.is_ascii_digit())?;
     Some((&s[..i], &s[i..]))
@@ -168,6 +259,33 @@ mod tests {
         }
     }
 
+    /// Test converting time and duration parameters to seconds.
+    #[test]
+    fn test_unix_and_duration_seconds() {
+        let now = datetime!(2022-01-01 12:00:00 UTC);
+        assert_eq!(unix_seconds("1641038400.5", now), Some(1_641_038_400.5));
+        assert_eq!(unix_seconds("2022-01-01T00:00:00Z", now), Some(1_640_995_200.0));
+        assert_eq!(unix_seconds("now-1h", now), Some(1_641_034_800.0));
+        assert_eq!(unix_seconds("yesterday", now), None);
+
+        assert_eq!(duration_seconds("15"), Some(15.0));
+        assert_eq!(duration_seconds("1m30s"), Some(90.0));
+        assert_eq!(duration_seconds("250ms"), Some(0.25));
+        assert_eq!(duration_seconds("soon"), None);
+    }
+
+    /// Test parsing clock anchors and the fixed time they stand for.
+    #[test]
+    fn test_clock_anchor() {
+        let startup = datetime!(2022-01-01 12:00:00 UTC);
+        let anchor = |s: &str| s.parse::<ClockAnchor>().map(|a| a.fixed_now(startup));
+
+        assert_eq!(anchor("now"), Ok(None));
+        assert_eq!(anchor("startup"), Ok(Some(startup)));
+        assert_eq!(anchor("2025-08-03T00:00:00Z"), Ok(Some(datetime!(2025-08-03 00:00:00 UTC))));
+        assert!(anchor("yesterday").is_err());
+    }
+
     /// Test edge cases and boundary conditions.
     #[test]
     fn test_edge_cases() {